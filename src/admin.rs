@@ -0,0 +1,37 @@
+//! Restricts routes to administrators.
+
+use rocket::{
+    http::Status,
+    request::{self, FromRequest, Request},
+    Outcome,
+};
+use subtle::ConstantTimeEq;
+
+use crate::configuration::ADMIN_TOKEN;
+
+/// The header that contains the administrator token.
+const ADMIN_TOKEN_HEADER: &str = "X-Admin-Token";
+
+/// A request guard that only succeeds for requests of administrators.
+///
+/// A request is made by an administrator if it contains the configured token in the `X-Admin-Token` header.
+/// If no token is configured, all administrator routes are disabled. The token is compared in constant time so that
+/// the response time does not reveal how much of a guessed token is correct.
+pub struct Admin;
+
+impl<'a, 'r> FromRequest<'a, 'r> for Admin {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Admin, ()> {
+        if ADMIN_TOKEN.is_empty() {
+            return Outcome::Failure((Status::Forbidden, ()));
+        }
+
+        match request.headers().get_one(ADMIN_TOKEN_HEADER) {
+            Some(token) if bool::from(token.as_bytes().ct_eq(ADMIN_TOKEN.as_bytes())) => {
+                Outcome::Success(Admin)
+            }
+            _ => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
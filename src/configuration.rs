@@ -12,14 +12,115 @@ mod macros;
 
 use lazy_static::lazy_static;
 
+use crate::{database::StorageBackend, images::ImageStorageBackend};
+
 configuration_variable! {
     /// The maximum size of an image upload.
     pub static ref IMAGE_UPLOAD_SIZE_LIMIT: u64 = 10 * 1024 * 1024;
 
+    /// The maximum number of pixels of an uploaded image.
+    ///
+    /// Images are decoded completely, so this limits the memory needed for an upload, which can be far larger
+    /// than the file itself.
+    pub static ref IMAGE_MAX_PIXELS: u64 = 50_000_000;
+
+    /// The maximum width and height of the thumbnail of an uploaded image in pixels.
+    pub static ref IMAGE_THUMB_SIZE: u32 = 256;
+
+    /// The maximum width and height of the medium size of an uploaded image in pixels.
+    pub static ref IMAGE_MEDIUM_SIZE: u32 = 1280;
+
+    /// The maximum number of facilities returned on a single page of a list query.
+    ///
+    /// If this is `0`, the number of facilities is unlimited unless the client asks for a limit.
+    pub static ref MAX_PAGE_SIZE: u64 = 0;
+
+    /// The maximum number of facilities a client may ask for in a nearest search.
+    pub static ref MAX_NEAREST_COUNT: u64 = 100;
+
+    /// The maximum number of facilities a client may ask for in a text search.
+    pub static ref MAX_SEARCH_RESULTS: u64 = 100;
+
+    /// The distance in meters at which the rank of a text search result is halved, if the search is biased by a location.
+    pub static ref SEARCH_DISTANCE_SCALE: f64 = 5000.0;
+
+    /// The highest zoom level at which map tiles may be answered with clusters of facilities.
+    pub static ref CLUSTER_MAX_ZOOM: u8 = 8;
+
+    /// The minimum number of facilities in a map tile for it to be answered with clusters.
+    pub static ref CLUSTER_MIN_FEATURES: u64 = 500;
+
+    /// The number of zoom levels between a clustered map tile and the tiles of its clustering grid.
+    ///
+    /// For example a depth of `3` divides a tile into a grid of 8 by 8 cells.
+    pub static ref CLUSTER_GRID_DEPTH: u8 = 3;
+
+    /// The comma-separated names of the attributes whose values are counted for every cluster.
+    pub static ref CLUSTER_BREAKDOWN_ATTRIBUTES := "wheelchairAccess";
+
+    /// The number of days after which a vote about the value of an attribute only counts half as much.
+    pub static ref VERIFICATION_HALF_LIFE_DAYS: f64 = 365.0;
+
+    /// The maximum number of votes that are kept for every attribute of a facility.
+    ///
+    /// Only the most recent votes are kept, so that popular facilities don't grow without bound.
+    pub static ref MAX_VOTES_PER_ATTRIBUTE: u64 = 100;
+
+    /// The confidence in the value of an attribute below which users are asked to verify it again.
+    ///
+    /// Confidences range from `0` to `1`. A single recent vote results in a confidence of about `0.5`.
+    pub static ref VERIFICATION_CONFIDENCE_THRESHOLD: f64 = 0.6;
+
+    /// How important it is to ask users for images, compared to the `questionPriority` of the attributes.
+    pub static ref ADD_IMAGE_QUESTION_PRIORITY: i32 = 50;
+
+    /// How important it is to ask users to label images, compared to the `questionPriority` of the attributes.
+    pub static ref LABEL_IMAGE_QUESTION_PRIORITY: i32 = 30;
+
+    /// The number of disputes after which the value of an attribute or the label of an image is corrected.
+    pub static ref DISPUTE_THRESHOLD: u64 = 3;
+
     /// Whether to initialize the database.
     pub static ref INITIALIZE_DB: u64 = 0;
 
-    /// The path where all the uploaded images are stored.
+    /// Whether to convert `lastUpdated` timestamps that are stored as strings into dates at startup.
+    ///
+    /// This only needs to be done once for databases that were created before timestamps were stored as dates.
+    pub static ref MIGRATE_TIMESTAMPS: u64 = 0;
+
+    /// Whether to move `fee` values from the misspelled path `properties.accesibility.fee` at startup.
+    ///
+    /// This only needs to be done once for databases that were created before the path was corrected.
+    pub static ref MIGRATE_FEE_PATH: u64 = 0;
+
+    /// Whether to convert the attributes listed in `properties.verifiedAttributes` into votes at startup.
+    ///
+    /// This only needs to be done once for databases that were created before attributes were verified by votes.
+    pub static ref MIGRATE_VERIFIED_ATTRIBUTES: u64 = 0;
+
+    /// Where to store the facilities.
+    ///
+    /// Either `mongodb` for the configured MongoDB database or `memory` for a store that only lives
+    /// as long as the server runs.
+    pub static ref STORAGE_BACKEND: StorageBackend = StorageBackend::MongoDB;
+
+    /// The path to a JSON file with the catalogue of attributes the server manages.
+    ///
+    /// If this is empty, the built-in catalogue is used.
+    pub static ref ATTRIBUTE_CATALOGUE := "";
+
+    /// The token administrators need to send in the `X-Admin-Token` header to use administrator routes.
+    ///
+    /// If this is empty, administrator routes are disabled.
+    pub static ref ADMIN_TOKEN := "";
+
+    /// Where to store the uploaded images.
+    ///
+    /// Either `filesystem` for the directory `IMAGE_PATH` or `s3` for the bucket `S3_BUCKET` of an S3-compatible
+    /// object storage.
+    pub static ref IMAGE_STORAGE_BACKEND: ImageStorageBackend = ImageStorageBackend::Filesystem;
+
+    /// The path where all the uploaded images are stored, if they are stored in the filesystem.
     pub static ref IMAGE_PATH := {
         if cfg!(feature = "testpages") {
             "/tmp/backend_images"
@@ -28,12 +129,44 @@ configuration_variable! {
         }
     };
 
+    /// The bucket where all the uploaded images are stored, if they are stored in an S3-compatible object storage.
+    pub static ref S3_BUCKET := {
+        if cfg!(feature = "testpages") {
+            "backend-images"
+        } else {
+            panic!("You need to set the {} environment variable to the bucket for the images before you can run the backend.", env_var_name!(S3_BUCKET))
+        }
+    };
+
+    /// The URL of the S3-compatible object storage, such as `http://localhost:9000` for a local MinIO server.
+    ///
+    /// If this is empty, Amazon S3 is used.
+    pub static ref S3_ENDPOINT := "";
+
+    /// The region of the S3 bucket.
+    pub static ref S3_REGION := "us-east-1";
+
+    /// The access key for the S3-compatible object storage.
+    ///
+    /// If this is empty, the credentials are read from the `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`
+    /// environment variables.
+    pub static ref S3_ACCESS_KEY := "";
+
+    /// The secret key for the S3-compatible object storage.
+    pub static ref S3_SECRET_KEY := "";
+
     /// The name of the MongoDB database to use.
     pub static ref DATABASE_NAME := "sanitary_facilities";
 
     /// The name of the database collection for sanitary facilities.
     pub static ref FACILITIES_COLLECTION_NAME := "facilities";
 
+    /// The name of the database collection for the history of changes to the facilities.
+    pub static ref HISTORY_COLLECTION_NAME := "history";
+
+    /// The name of the database collection for the visits users announced with will-visit requests.
+    pub static ref VISITS_COLLECTION_NAME := "visits";
+
     /// The source ID of our data in the accessibility cloud.
     pub static ref SOURCE_ID := {
         if cfg!(feature = "testpages") {
@@ -62,5 +195,14 @@ configuration_variable! {
 
 /// Check that all required configuration variables are set.
 pub fn check_required_configuration() {
-    let _ = (&*SOURCE_ID, &*IMAGE_URL_PREFIX, &*IMAGE_PATH);
+    let _ = (&*SOURCE_ID, &*IMAGE_URL_PREFIX);
+
+    match *IMAGE_STORAGE_BACKEND {
+        ImageStorageBackend::Filesystem => {
+            let _ = &*IMAGE_PATH;
+        }
+        ImageStorageBackend::S3 => {
+            let _ = &*S3_BUCKET;
+        }
+    }
 }
@@ -1,6 +1,12 @@
 //! Handles interfacing with the database.
 
-use chrono::Utc;
+mod history;
+mod memory;
+mod migrations;
+mod mongo;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{error, info, warn};
 use rocket::{
     request::{self, FromRequest, Request},
     Outcome, Rocket,
@@ -9,27 +15,125 @@ use rocket_contrib::{
     database,
     databases::mongodb::{
         self, bson,
-        coll::{
-            options::{FindOneAndUpdateOptions, FindOptions},
-            results::InsertOneResult,
-            Collection,
-        },
+        coll::options::{FindOptions, IndexOptions},
         db::ThreadedDatabase,
         doc,
         oid::ObjectId,
         to_bson, Bson, Client, Document, ThreadedClient,
     },
 };
+use std::{fmt, str::FromStr};
 
+pub use self::history::{
+    overwritten_path, paths_overlap, removed_required_path, values_before, ChangeOperation,
+};
+use self::{memory::MemoryFacilityStore, mongo::MongoFacilityStore};
 use crate::{
-    configuration::INITIALIZE_DB,
-    facilities::{IDPair, MinimalFacilityData},
+    configuration::{
+        DATABASE_NAME, FACILITIES_COLLECTION_NAME, HISTORY_COLLECTION_NAME, INITIALIZE_DB,
+        MIGRATE_FEE_PATH, MIGRATE_TIMESTAMPS, MIGRATE_VERIFIED_ATTRIBUTES, STORAGE_BACKEND,
+        VISITS_COLLECTION_NAME,
+    },
+    facilities::{add_confidence_scores, IDPair, MinimalFacilityData},
 };
 
+/// The number of times an update is attempted if the facility is modified concurrently.
+const MAX_UPDATE_ATTEMPTS: usize = 5;
+
+/// The fields of a facility that are searched by text searches along with their weights.
+///
+/// Matches in fields with a higher weight contribute more to the relevance of a facility.
+pub const TEXT_INDEX_WEIGHTS: [(&str, i32); 4] = [
+    ("properties.name", 10),
+    ("properties.address.street", 2),
+    ("properties.address.city", 2),
+    ("properties.address.zip", 1),
+];
+
+/// The storage backends that facilities can be stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageBackend {
+    /// Stores facilities in the configured MongoDB database.
+    MongoDB,
+    /// Stores facilities in memory.
+    ///
+    /// All data is lost when the server stops, so this is only meant for development and testing.
+    Memory,
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<StorageBackend, String> {
+        match s {
+            "mongodb" => Ok(StorageBackend::MongoDB),
+            "memory" => Ok(StorageBackend::Memory),
+            _ => Err(format!(
+                "unknown storage backend `{}` (expected `mongodb` or `memory`)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for StorageBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageBackend::MongoDB => write!(f, "mongodb"),
+            StorageBackend::Memory => write!(f, "memory"),
+        }
+    }
+}
+
+/// Describes the operations needed to store and retrieve facilities.
+///
+/// Filters and updates are expressed in the MongoDB query language, so that every implementation
+/// behaves like the MongoDB implementation, including the semantics of upserts.
+pub trait FacilityStore {
+    /// Returns all documents that match the filter.
+    ///
+    /// Of the options only `sort`, `skip` and `limit` need to be supported, as well as projecting and sorting
+    /// by `{ "$meta": "textScore" }` for `$text` queries.
+    fn find(
+        &self,
+        filter: Option<Document>,
+        options: Option<FindOptions>,
+    ) -> mongodb::Result<Box<dyn Iterator<Item = Document>>>;
+
+    /// Returns the number of documents that match the filter.
+    fn count(&self, filter: Document) -> mongodb::Result<u64>;
+
+    /// Runs the aggregation pipeline on all documents and returns its results.
+    ///
+    /// Only the stages `$match`, `$group`, `$sort` and `$limit` need to be supported. Groups are only accumulated
+    /// with `$sum` and `$avg`.
+    fn aggregate(&self, pipeline: Vec<Document>) -> mongodb::Result<Vec<Document>>;
+
+    /// Finds the first document that matches and updates it according to the update document.
+    ///
+    /// If `upsert` is set and no document matches, a new document is created from the equality
+    /// conditions of the filter and the update, including the fields in `$setOnInsert`.
+    ///
+    /// Returns the matched or inserted document as it is after the update.
+    fn find_one_and_update(
+        &self,
+        filter: Document,
+        update: Document,
+        upsert: bool,
+    ) -> mongodb::Result<Option<Document>>;
+
+    /// Inserts a new document.
+    fn insert_one(&self, doc: Document) -> mongodb::Result<()>;
+}
+
 /// Initializes the database.
 ///
 /// This sets up needed invariants in the database, such as indices.
 pub fn init(rocket: &mut Rocket) {
+    if *STORAGE_BACKEND != StorageBackend::MongoDB {
+        return;
+    }
+
     let connection_info = rocket
         .config()
         .get_table("databases")
@@ -45,14 +149,83 @@ pub fn init(rocket: &mut Rocket) {
         Client::with_uri(connection_info).expect("Database connection could not be established.");
 
     let facilities_collection = client
-        .db(&*crate::configuration::DATABASE_NAME)
-        .collection(&*crate::configuration::FACILITIES_COLLECTION_NAME);
+        .db(&DATABASE_NAME)
+        .collection(&FACILITIES_COLLECTION_NAME);
 
     if *INITIALIZE_DB > 0 {
         // Set up an index for the locations in the `facilities` collection.
         facilities_collection
             .create_index(doc! { "geometry": "2dsphere" }, None)
             .expect("Could not create a required index in the database.");
+
+        // Set up a text index for searching facilities by name and address.
+        let mut keys = Document::new();
+        let mut weights = Document::new();
+        for &(field, weight) in TEXT_INDEX_WEIGHTS.iter() {
+            keys.insert(field, "text");
+            weights.insert(field, weight);
+        }
+
+        let mut options = IndexOptions::new();
+        options.weights = Some(weights);
+
+        facilities_collection
+            .create_index(keys, Some(options))
+            .expect("Could not create a required index in the database.");
+
+        // Set up an index for reading the history of a facility.
+        client
+            .db(&DATABASE_NAME)
+            .collection(&HISTORY_COLLECTION_NAME)
+            .create_index(doc! { "id.sourceId": 1, "id.originalId": 1 }, None)
+            .expect("Could not create a required index in the database.");
+
+        // Set up indices for the visit statistics by facility and by area.
+        let visits_collection = client
+            .db(&DATABASE_NAME)
+            .collection(&VISITS_COLLECTION_NAME);
+
+        visits_collection
+            .create_index(doc! { "id.sourceId": 1, "id.originalId": 1 }, None)
+            .expect("Could not create a required index in the database.");
+
+        visits_collection
+            .create_index(doc! { "geometry": "2dsphere" }, None)
+            .expect("Could not create a required index in the database.");
+    }
+
+    if *MIGRATE_TIMESTAMPS > 0 {
+        let (converted, skipped) = migrations::convert_string_timestamps(&facilities_collection)
+            .expect("Could not convert the timestamps in the database.");
+
+        info!(
+            "Converted the `lastUpdated` timestamps of {} facilities.",
+            converted
+        );
+
+        if skipped > 0 {
+            warn!(
+                "Could not convert the `lastUpdated` timestamps of {} facilities.",
+                skipped
+            );
+        }
+    }
+
+    if *MIGRATE_FEE_PATH > 0 {
+        let moved = migrations::move_misspelled_fee(&facilities_collection)
+            .expect("Could not move the fee values in the database.");
+
+        info!("Moved the `fee` values of {} facilities.", moved);
+    }
+
+    if *MIGRATE_VERIFIED_ATTRIBUTES > 0 {
+        let converted = migrations::convert_verified_attributes(&facilities_collection)
+            .expect("Could not convert the verified attributes in the database.");
+
+        info!(
+            "Converted the verified attributes of {} facilities into votes.",
+            converted
+        );
     }
 }
 
@@ -65,22 +238,44 @@ pub struct DatabaseConnection(mongodb::db::Database);
 
 /// A request guard for the facilities collection.
 ///
-/// This is a short hand for retrieving a database connection and then
-/// getting the collection from that.
-pub struct FacilityCollection(mongodb::coll::Collection);
+/// This is a short hand for retrieving the configured facility store along with the history of the facilities
+/// and the visits of users.
+pub struct FacilityCollection {
+    /// The store of the facilities themselves.
+    facilities: Box<dyn FacilityStore>,
+    /// The store of the change records.
+    history: Box<dyn FacilityStore>,
+    /// The store of the visits users announced.
+    visits: Box<dyn FacilityStore>,
+}
 
 impl<'a, 'r> FromRequest<'a, 'r> for FacilityCollection {
     type Error = ();
 
     fn from_request(request: &'a Request<'r>) -> request::Outcome<FacilityCollection, ()> {
-        let database_connection = DatabaseConnection::from_request(request)?;
-
-        Outcome::Success(FacilityCollection(
-            database_connection
-                .client
-                .db(&crate::configuration::DATABASE_NAME)
-                .collection(&crate::configuration::FACILITIES_COLLECTION_NAME),
-        ))
+        match *STORAGE_BACKEND {
+            StorageBackend::MongoDB => {
+                let database_connection = DatabaseConnection::from_request(request)?;
+                let database = database_connection.client.db(&DATABASE_NAME);
+
+                Outcome::Success(FacilityCollection {
+                    facilities: Box::new(MongoFacilityStore::new(
+                        database.collection(&FACILITIES_COLLECTION_NAME),
+                    )),
+                    history: Box::new(MongoFacilityStore::new(
+                        database.collection(&HISTORY_COLLECTION_NAME),
+                    )),
+                    visits: Box::new(MongoFacilityStore::new(
+                        database.collection(&VISITS_COLLECTION_NAME),
+                    )),
+                })
+            }
+            StorageBackend::Memory => Outcome::Success(FacilityCollection {
+                facilities: Box::new(MemoryFacilityStore::new(&FACILITIES_COLLECTION_NAME)),
+                history: Box::new(MemoryFacilityStore::new(&HISTORY_COLLECTION_NAME)),
+                visits: Box::new(MemoryFacilityStore::new(&VISITS_COLLECTION_NAME)),
+            }),
+        }
     }
 }
 
@@ -89,45 +284,26 @@ impl FacilityCollection {
     pub fn perform_json_query(
         &self,
         filter: Option<Document>,
+        options: Option<FindOptions>,
     ) -> mongodb::Result<impl Iterator<Item = serde_json::Value>> {
-        perform_json_query(&self.0, filter, None).map(|values| {
-            values.map(|mut val| {
-                if let Some(obj) = val.as_object_mut() {
-                    // return `{ "properties": { "_id": <id> } }` instead of `{ "_id": { "$oid": <id> } }`, just like in the accessibility cloud API
-                    let id = obj
-                        .remove("_id")
-                        .expect("MongoDB document doesn't contain `_id`.");
-
-                    obj.entry("properties")
-                        .or_insert_with(|| serde_json::json!({}))["_id"] = id["$oid"].clone();
-
-                    let mut filter_flagged_content = |content: &str| {
-                        if let Some(nested_property) = obj
-                            .get_mut("properties")
-                            .and_then(|props| props.as_object_mut())
-                            .and_then(|props_obj| props_obj.get_mut(content))
-                            .and_then(|images| images.as_array_mut())
-                        {
-                            *nested_property = nested_property
-                                .drain(..)
-                                .filter(|prop| {
-                                    prop.as_object()
-                                        .and_then(|prop_obj| prop_obj.get("flagged"))
-                                        .and_then(|flagged| flagged.as_bool())
-                                        != Some(true)
-                                })
-                                .collect();
-                        }
-                    };
-
-                    filter_flagged_content("images");
-
-                    filter_flagged_content("comments");
-                }
+        Ok(self
+            .facilities
+            .find(filter, options)?
+            .filter_map(facility_to_json))
+    }
 
-                val
-            })
-        })
+    /// Performs the given aggregation on the facility collection returning all results in json.
+    ///
+    /// The pipeline must keep the facilities as they are, but may add fields to them.
+    pub fn perform_json_aggregation(
+        &self,
+        pipeline: Vec<Document>,
+    ) -> mongodb::Result<impl Iterator<Item = serde_json::Value>> {
+        Ok(self
+            .facilities
+            .aggregate(pipeline)?
+            .into_iter()
+            .filter_map(facility_to_json))
     }
 
     /// Performs the given query and returns the data without modifications.
@@ -136,27 +312,61 @@ impl FacilityCollection {
     pub fn find_raw(
         &self,
         filter: Option<Document>,
+        options: Option<FindOptions>,
     ) -> mongodb::Result<impl Iterator<Item = serde_json::Value>> {
-        perform_json_query(&self.0, filter, None)
+        Ok(self
+            .facilities
+            .find(filter, options)?
+            .filter_map(|val| to_bson(&val).ok())
+            .map(|val| val.into()))
+    }
+
+    /// Returns the number of facilities that match the filter.
+    pub fn count(&self, filter: Document) -> mongodb::Result<u64> {
+        self.facilities.count(filter)
+    }
+
+    /// Returns a facility by ID as it is stored in the database.
+    pub fn document_by_id(&self, id: IDPair) -> mongodb::Result<Option<Document>> {
+        Ok(self
+            .facilities
+            .find(
+                Some(doc! {
+                    "properties.sourceId": id.sourceId,
+                    "properties.originalId": id.originalId,
+                }),
+                None,
+            )?
+            .next())
     }
 
     /// Returns a facility by ID.
     pub fn by_id(&self, id: IDPair) -> mongodb::Result<Option<serde_json::Value>> {
-        self.find_raw(Some(doc! {
-            "properties.sourceId": id.sourceId,
-            "properties.originalId": id.originalId,
-        }))
+        self.find_raw(
+            Some(doc! {
+                "properties.sourceId": id.sourceId,
+                "properties.originalId": id.originalId,
+            }),
+            None,
+        )
         .map(|mut iter| iter.next())
     }
 
     /// Finds the first facility that matches and updates it according to the update document.
+    ///
+    /// The change is recorded in the history of the facility as the given operation, or as `createFacility` if the
+    /// update inserted the facility. If the history cannot be written, the failure is logged, but the update is kept.
+    ///
+    /// Returns the matched document as it was before the update.
     pub fn find_one_and_update(
         &self,
         filter: Document,
         mut update: Document,
         upsert_data: Option<MinimalFacilityData>,
+        operation: ChangeOperation,
     ) -> mongodb::Result<Option<Document>> {
         let upsert = upsert_data.is_some();
+        let now = Utc::now();
 
         if let Some(facility_info) = upsert_data {
             let old_document = update.insert(
@@ -176,22 +386,91 @@ impl FacilityCollection {
             Bson::Document(set_doc) => Some(set_doc),
             _ => None,
         }) {
-            set_doc.insert("lastUpdated", Utc::now().to_string());
+            set_doc.insert("lastUpdated", Bson::UtcDatetime(now));
         } else {
-            update.insert("$set", doc! { "lastUpdated": Utc::now().to_string() });
+            update.insert("$set", doc! { "lastUpdated": Bson::UtcDatetime(now) });
+        }
+
+        let paths = history::touched_paths(&update);
+
+        for _ in 0..MAX_UPDATE_ATTEMPTS {
+            let before = self.facilities.find(Some(filter.clone()), None)?.next();
+
+            // Update the facility only if it was not modified since it was read, so that the change record
+            // describes exactly this update. Every recorded update sets `lastUpdated`.
+            let update_filter = match &before {
+                Some(before) => {
+                    let mut update_filter = filter.clone();
+                    update_filter.insert("_id", before.get("_id").cloned().unwrap_or(Bson::Null));
+                    update_filter.insert(
+                        "lastUpdated",
+                        before.get("lastUpdated").cloned().unwrap_or(Bson::Null),
+                    );
+                    update_filter
+                }
+                None if upsert => filter.clone(),
+                // Nothing matches, so there is nothing to update or record.
+                None => return Ok(None),
+            };
+
+            let after = self.facilities.find_one_and_update(
+                update_filter,
+                update.clone(),
+                upsert && before.is_none(),
+            )?;
+
+            if after.is_none() && before.is_some() {
+                // The facility was modified concurrently, so read it again.
+                continue;
+            }
+
+            // An upsert that inserted the facility created it, whatever else the update did.
+            let operation = match before {
+                Some(_) => operation,
+                None => ChangeOperation::createFacility,
+            };
+            self.record_change(operation, before.as_ref(), after.as_ref(), &paths, now);
+
+            return Ok(before);
         }
 
-        find_one_and_update(&self.0, filter, update, upsert)
+        Err(mongodb::Error::OperationError(String::from(
+            "The facility was modified concurrently too often.",
+        )))
+    }
+
+    /// Increments the number of times users were asked the questions with the given keys about the facility.
+    ///
+    /// Counting questions is bookkeeping rather than a change of the facility, so it neither updates `lastUpdated`
+    /// nor is it recorded in the history.
+    pub fn count_questions(&self, id: IDPair, keys: &[String]) -> mongodb::Result<()> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let mut increments = Document::new();
+        for key in keys {
+            increments.insert(format!("properties.questionsAsked.{}", key), 1);
+        }
+
+        self.facilities.find_one_and_update(
+            doc! { "properties.sourceId": id.sourceId, "properties.originalId": id.originalId },
+            doc! { "$inc": increments },
+            false,
+        )?;
+
+        Ok(())
     }
 
     /// Inserts a new facility into the collection.
-    pub fn insert(&self, mut doc: Document) -> mongodb::Result<InsertOneResult> {
+    pub fn insert(&self, mut doc: Document) -> mongodb::Result<()> {
         let id = ObjectId::new()?;
         let id_str = id.to_string();
+        let now = Utc::now();
 
         doc.insert("_id", id);
         doc.insert("type", "Feature");
-        doc.insert("lastUpdated", Utc::now().to_string());
+        doc.insert("lastUpdated", Bson::UtcDatetime(now));
 
         if doc.get_document("properties").is_err() {
             doc.insert("properties", doc! {});
@@ -205,32 +484,186 @@ impl FacilityCollection {
         props.insert("originalId", id_str);
         props.insert("category", "toilets");
 
-        self.0.insert_one(doc, None)
+        self.facilities.insert_one(doc.clone())?;
+
+        self.record_change(
+            ChangeOperation::createFacility,
+            None,
+            Some(&doc),
+            &[String::from("geometry"), String::from("properties")],
+            now,
+        );
+
+        Ok(())
     }
-}
 
-/// Performs the given query on the given collection returning all results in json.
-fn perform_json_query(
-    collection: &Collection,
-    filter: Option<Document>,
-    options: Option<FindOptions>,
-) -> mongodb::Result<impl Iterator<Item = serde_json::Value>> {
-    Ok(collection
-        .find(filter, options)?
-        .filter_map(|val| val.ok())
-        .filter_map(|val| to_bson(&val).ok())
-        .map(|val| val.into()))
+    /// Appends the change to the history, if anything changed.
+    ///
+    /// The change has already been made at this point, so a failure to record it is logged instead of returned.
+    fn record_change(
+        &self,
+        operation: ChangeOperation,
+        before: Option<&Document>,
+        after: Option<&Document>,
+        paths: &[String],
+        timestamp: DateTime<Utc>,
+    ) {
+        if let Some(record) = history::change_record(operation, before, after, paths, timestamp) {
+            if let Err(error) = self.history.insert_one(record) {
+                error!("The change of a facility could not be recorded: {}", error);
+            }
+        }
+    }
+
+    /// Returns the change record with the given ID without modifications.
+    pub fn find_change(&self, change_id: ObjectId) -> mongodb::Result<Option<Document>> {
+        Ok(self
+            .history
+            .find(Some(doc! { "_id": change_id }), None)?
+            .next())
+    }
+
+    /// Returns the change records of the facility made after the timestamp in the order they were made.
+    pub fn changes_since(
+        &self,
+        id: IDPair,
+        timestamp: DateTime<Utc>,
+    ) -> mongodb::Result<Vec<Document>> {
+        let mut options = FindOptions::new();
+        options.sort = Some(doc! { "timestamp": 1, "_id": 1 });
+
+        Ok(self
+            .history
+            .find(
+                Some(doc! {
+                    "id.sourceId": id.sourceId,
+                    "id.originalId": id.originalId,
+                    "timestamp": { "$gt": Bson::UtcDatetime(timestamp) }
+                }),
+                Some(options),
+            )?
+            .collect())
+    }
+
+    /// Sets the paths of the facility to the given values, removing the paths without a value.
+    ///
+    /// This is recorded in the history like any other change.
+    /// Returns `Ok(None)` if the facility does not exist.
+    pub fn restore(
+        &self,
+        id: IDPair,
+        values: Vec<(String, Option<Bson>)>,
+        operation: ChangeOperation,
+    ) -> mongodb::Result<Option<Document>> {
+        self.find_one_and_update(
+            doc! { "properties.sourceId": id.sourceId, "properties.originalId": id.originalId },
+            history::restore_update(values),
+            None,
+            operation,
+        )
+    }
+
+    /// Returns all changes of the facility with the given ID in the order they were made.
+    pub fn history(&self, id: IDPair) -> mongodb::Result<impl Iterator<Item = serde_json::Value>> {
+        let mut options = FindOptions::new();
+        options.sort = Some(doc! { "timestamp": 1, "_id": 1 });
+
+        Ok(self
+            .history
+            .find(
+                Some(doc! { "id.sourceId": id.sourceId, "id.originalId": id.originalId }),
+                Some(options),
+            )?
+            .map(|mut record| {
+                // return the ID and the timestamp as strings, just like for facilities
+                if let Ok(id) = record.get_object_id("_id").map(ObjectId::to_hex) {
+                    record.insert("_id", id);
+                }
+
+                if let Ok(timestamp) = record
+                    .get_utc_datetime("timestamp")
+                    .map(|date| date.to_rfc3339_opts(SecondsFormat::Millis, true))
+                {
+                    record.insert("timestamp", timestamp);
+                }
+
+                Bson::Document(record).into()
+            }))
+    }
+
+    /// Records a visit that a user announced.
+    pub fn record_visit(&self, visit: Document) -> mongodb::Result<()> {
+        self.visits.insert_one(visit)
+    }
+
+    /// Returns the number of recorded visits that match the filter.
+    pub fn count_visits(&self, filter: Document) -> mongodb::Result<u64> {
+        self.visits.count(filter)
+    }
+
+    /// Runs the aggregation pipeline on the recorded visits.
+    pub fn aggregate_visits(&self, pipeline: Vec<Document>) -> mongodb::Result<Vec<Document>> {
+        self.visits.aggregate(pipeline)
+    }
 }
 
-/// Finds the first element that matches and updates it according to the update document.
-fn find_one_and_update(
-    collection: &Collection,
-    filter: Document,
-    update: Document,
-    upsert: bool,
-) -> mongodb::Result<Option<Document>> {
-    let mut options = FindOneAndUpdateOptions::new();
-    options.upsert = Some(upsert);
-
-    collection.find_one_and_update(filter, update, Some(options))
+/// Converts a facility from the database into the json returned by the API.
+fn facility_to_json(mut doc: Document) -> Option<serde_json::Value> {
+    // return the time of the last update as an RFC 3339 string instead of `{ "$date": { "$numberLong": <ms> } }`
+    if let Ok(last_updated) = doc
+        .get_utc_datetime("lastUpdated")
+        .map(|date| date.to_rfc3339_opts(SecondsFormat::Millis, true))
+    {
+        doc.insert("lastUpdated", last_updated);
+    }
+
+    let mut val = serde_json::Value::from(to_bson(&doc).ok()?);
+
+    if let Some(obj) = val.as_object_mut() {
+        // return `{ "properties": { "_id": <id> } }` instead of `{ "_id": { "$oid": <id> } }`, just like in the accessibility cloud API
+        let id = obj
+            .remove("_id")
+            .expect("MongoDB document doesn't contain `_id`.");
+
+        obj.entry("properties")
+            .or_insert_with(|| serde_json::json!({}))["_id"] = id["$oid"].clone();
+
+        let mut filter_flagged_content = |content: &str| {
+            if let Some(nested_property) = obj
+                .get_mut("properties")
+                .and_then(|props| props.as_object_mut())
+                .and_then(|props_obj| props_obj.get_mut(content))
+                .and_then(|images| images.as_array_mut())
+            {
+                *nested_property = nested_property
+                    .drain(..)
+                    .filter(|prop| {
+                        prop.as_object()
+                            .and_then(|prop_obj| prop_obj.get("flagged"))
+                            .and_then(|flagged| flagged.as_bool())
+                            != Some(true)
+                    })
+                    .collect();
+            }
+        };
+
+        filter_flagged_content("images");
+
+        filter_flagged_content("comments");
+
+        // The hashes of images are only used to recognize uploads of the same file.
+        if let Some(images) = obj
+            .get_mut("properties")
+            .and_then(|props| props.get_mut("images"))
+            .and_then(|images| images.as_array_mut())
+        {
+            for image in images.iter_mut().filter_map(|image| image.as_object_mut()) {
+                image.remove("sha256");
+            }
+        }
+    }
+
+    add_confidence_scores(&mut val);
+
+    Some(val)
 }
@@ -0,0 +1,231 @@
+//! Records the changes made to facilities.
+//!
+//! Every mutation of a facility appends an immutable record to the history collection. A record contains the values
+//! of all paths the mutation touched, both before and after the mutation, so that it can be undone later.
+
+use chrono::{DateTime, Utc};
+use rocket_contrib::databases::mongodb::{bson, doc, to_bson, Bson, Document};
+use serde::Serialize;
+
+/// The operations that change facilities.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[allow(non_camel_case_types)]
+pub enum ChangeOperation {
+    /// A new facility was created.
+    createFacility,
+    /// The name, address or accessibility of a facility was set.
+    setFacility,
+    /// A comment was added.
+    addComment,
+    /// A comment was flagged as inappropriate.
+    flagComment,
+    /// Attributes were verified.
+    verifyAttributes,
+    /// The value of an attribute was disputed.
+    disputeAttribute,
+    /// An image was uploaded.
+    addImage,
+    /// An image was labeled.
+    setImageLabel,
+    /// The label of an image was verified.
+    verifyImageLabel,
+    /// The label of an image was disputed.
+    disputeImageLabel,
+    /// An image was flagged as inappropriate.
+    flagImage,
+    /// A previous change was reverted.
+    revertChange,
+    /// The facility was restored to an earlier state.
+    restoreFacility,
+}
+
+/// The update operators whose fields are recorded.
+const UPDATE_OPERATORS: [&str; 6] = [
+    "$set",
+    "$setOnInsert",
+    "$unset",
+    "$inc",
+    "$push",
+    "$addToSet",
+];
+
+/// The paths that are maintained by the database layer and therefore not recorded.
+const UNRECORDED_PATHS: [&str; 1] = ["lastUpdated"];
+
+/// The paths that every facility has and that therefore must not be removed by undoing changes.
+const REQUIRED_PATHS: [&str; 2] = ["type", "geometry"];
+
+/// Returns the paths touched by the update document.
+///
+/// Paths with a positional operator such as `properties.images.$.label` are recorded as the whole array,
+/// because the position of the element is only known to the database.
+pub fn touched_paths(update: &Document) -> Vec<String> {
+    let mut paths: Vec<String> = Vec::new();
+
+    for operator in UPDATE_OPERATORS.iter() {
+        if let Ok(fields) = update.get_document(operator) {
+            for field in fields.keys() {
+                let path = match field.find(".$") {
+                    Some(index) => &field[..index],
+                    None => field.as_str(),
+                };
+
+                if !UNRECORDED_PATHS.contains(&path)
+                    && !paths.iter().any(|existing| existing == path)
+                {
+                    paths.push(String::from(path));
+                }
+            }
+        }
+    }
+
+    paths
+}
+
+/// Determines whether two database paths are equal or one of them lies within the other.
+pub fn paths_overlap(a: &str, b: &str) -> bool {
+    let within = |path: &str, parent: &str| {
+        path.starts_with(parent) && path[parent.len()..].starts_with('.')
+    };
+
+    a == b || within(a, b) || within(b, a)
+}
+
+/// Returns the value at the dotted path within the document.
+///
+/// Numeric path components index into arrays.
+pub fn value_at<'a>(doc: &'a Document, path: &str) -> Option<&'a Bson> {
+    let mut components = path.split('.');
+    let mut value = doc.get(components.next()?)?;
+
+    for component in components {
+        value = match value {
+            Bson::Document(doc) => doc.get(component)?,
+            Bson::Array(elements) => elements.get(component.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+
+    Some(value)
+}
+
+/// Creates the update document that sets the paths to the given values.
+///
+/// Paths without a value are removed.
+pub fn restore_update(values: Vec<(String, Option<Bson>)>) -> Document {
+    let mut set_doc = Document::new();
+    let mut unset_doc = Document::new();
+
+    for (path, value) in values {
+        match value {
+            Some(value) => set_doc.insert(path, value),
+            None => unset_doc.insert(path, ""),
+        };
+    }
+
+    let mut update = Document::new();
+    if !set_doc.is_empty() {
+        update.insert("$set", set_doc);
+    }
+    if !unset_doc.is_empty() {
+        update.insert("$unset", unset_doc);
+    }
+
+    update
+}
+
+/// Returns the paths changed by a change record along with their values before the change.
+pub fn values_before(record: &Document) -> Vec<(String, Option<Bson>)> {
+    record
+        .get_array("changes")
+        .map(|changes| {
+            changes
+                .iter()
+                .filter_map(|change| match change {
+                    Bson::Document(change) => Some((
+                        String::from(change.get_str("path").ok()?),
+                        change.get("before").cloned(),
+                    )),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns a path that every facility has and that setting the paths to the given values would remove.
+pub fn removed_required_path(values: &[(String, Option<Bson>)]) -> Option<&'static str> {
+    REQUIRED_PATHS.iter().cloned().find(|required| {
+        values
+            .iter()
+            .any(|(path, value)| value.is_none() && paths_overlap(path, required))
+    })
+}
+
+/// Returns a path that the change record changed and that one of the later change records touched again.
+///
+/// Undoing the change of such a path would also undo the later changes. This includes arrays, since changes to
+/// single elements of an array are recorded as a change of the whole array.
+pub fn overwritten_path(record: &Document, later_records: &[Document]) -> Option<String> {
+    values_before(record)
+        .into_iter()
+        .map(|(path, _)| path)
+        .find(|changed_path| {
+            later_records.iter().any(|later_record| {
+                values_before(later_record)
+                    .iter()
+                    .any(|(path, _)| paths_overlap(path, changed_path))
+            })
+        })
+}
+
+/// Creates the change record for a mutation of a facility.
+///
+/// Paths whose values did not change are left out. Returns `None` if nothing changed at all.
+/// A missing `before` or `after` value means that the path did not exist before or after the mutation.
+pub fn change_record(
+    operation: ChangeOperation,
+    before: Option<&Document>,
+    after: Option<&Document>,
+    paths: &[String],
+    timestamp: DateTime<Utc>,
+) -> Option<Document> {
+    let facility = after.or(before)?;
+    let properties = facility.get_document("properties").ok()?;
+
+    let changes: Vec<Bson> = paths
+        .iter()
+        .filter_map(|path| {
+            let old_value = before.and_then(|doc| value_at(doc, path));
+            let new_value = after.and_then(|doc| value_at(doc, path));
+
+            if old_value == new_value {
+                return None;
+            }
+
+            let mut change = doc! { "path": path.clone() };
+            if let Some(old_value) = old_value {
+                change.insert("before", old_value.clone());
+            }
+            if let Some(new_value) = new_value {
+                change.insert("after", new_value.clone());
+            }
+
+            Some(Bson::Document(change))
+        })
+        .collect();
+
+    if changes.is_empty() {
+        return None;
+    }
+
+    Some(doc! {
+        "id": {
+            "sourceId": properties.get_str("sourceId").ok()?,
+            "originalId": properties.get_str("originalId").ok()?
+        },
+        "operation": to_bson(&operation).ok()?,
+        "changes": changes,
+        "timestamp": Bson::UtcDatetime(timestamp)
+    })
+}
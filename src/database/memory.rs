@@ -0,0 +1,960 @@
+//! Implements a facility store that keeps all data in memory.
+//!
+//! This store understands the subset of the MongoDB query and update language that the backend uses,
+//! so that the whole API can be run without a MongoDB server. All data is lost when the server stops.
+
+use geoutils::Location;
+use lazy_static::lazy_static;
+use rocket_contrib::databases::mongodb::{
+    self, coll::options::FindOptions, oid::ObjectId, Bson, Document,
+};
+use std::{cmp::Ordering, collections::HashMap, sync::RwLock};
+
+use super::{FacilityStore, TEXT_INDEX_WEIGHTS};
+
+lazy_static! {
+    /// The documents of all in-memory collections by collection name.
+    static ref COLLECTIONS: RwLock<HashMap<String, Vec<Document>>> = RwLock::new(HashMap::new());
+}
+
+/// A facility store that keeps all facilities in memory.
+pub struct MemoryFacilityStore {
+    /// The name of the collection within the in-memory storage.
+    collection: String,
+}
+
+impl MemoryFacilityStore {
+    /// Creates a store for the in-memory collection with the given name.
+    pub fn new(collection: &str) -> MemoryFacilityStore {
+        MemoryFacilityStore {
+            collection: String::from(collection),
+        }
+    }
+}
+
+impl FacilityStore for MemoryFacilityStore {
+    fn count(&self, filter: Document) -> mongodb::Result<u64> {
+        let collections = COLLECTIONS
+            .read()
+            .map_err(|_| mongodb::Error::PoisonLockError)?;
+
+        let mut count = 0;
+        for doc in collections.get(&self.collection).into_iter().flatten() {
+            if matches(doc, &filter)? {
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    fn aggregate(&self, pipeline: Vec<Document>) -> mongodb::Result<Vec<Document>> {
+        let collections = COLLECTIONS
+            .read()
+            .map_err(|_| mongodb::Error::PoisonLockError)?;
+
+        let mut results: Vec<Document> = collections
+            .get(&self.collection)
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+
+        for stage in &pipeline {
+            results = match stage.iter().next() {
+                Some((name, Bson::Document(argument))) if name == "$match" => {
+                    let mut matching = Vec::new();
+                    for doc in results {
+                        if matches(&doc, argument)? {
+                            matching.push(doc);
+                        }
+                    }
+                    matching
+                }
+                Some((name, Bson::Document(argument))) if name == "$geoNear" => {
+                    geo_near(results, argument)?
+                }
+                Some((name, Bson::Document(argument))) if name == "$group" => {
+                    group(&results, argument)?
+                }
+                Some((name, Bson::Document(argument))) if name == "$sort" => {
+                    results.sort_by(|a, b| compare_by_sort(a, b, argument));
+                    results
+                }
+                Some((name, argument)) if name == "$limit" => {
+                    let limit =
+                        number_of(argument).ok_or_else(|| unsupported("non-numeric limits"))?;
+                    results.truncate(limit as usize);
+                    results
+                }
+                _ => return Err(unsupported("this aggregation stage")),
+            };
+        }
+
+        Ok(results)
+    }
+
+    fn find(
+        &self,
+        filter: Option<Document>,
+        options: Option<FindOptions>,
+    ) -> mongodb::Result<Box<dyn Iterator<Item = Document>>> {
+        let filter = filter.unwrap_or_else(Document::new);
+        let options = options.unwrap_or_else(FindOptions::new);
+        let collections = COLLECTIONS
+            .read()
+            .map_err(|_| mongodb::Error::PoisonLockError)?;
+
+        let mut results = Vec::new();
+        for doc in collections.get(&self.collection).into_iter().flatten() {
+            if matches(doc, &filter)? {
+                results.push(doc.clone());
+            }
+        }
+
+        // Add the relevance of the documents if it is requested as `{ <field>: { "$meta": "textScore" } }`.
+        if let (Some(projection), Some(search)) = (&options.projection, filter.get("$text")) {
+            for (field, value) in projection.iter() {
+                if is_text_score(value) {
+                    for doc in results.iter_mut() {
+                        let score = text_score(doc, search)?;
+                        doc.insert(field.clone(), score);
+                    }
+                }
+            }
+        }
+
+        if let Some(sort) = &options.sort {
+            results.sort_by(|a, b| compare_by_sort(a, b, sort));
+        } else if let Some((path, origin)) = near_sphere_origin(&filter) {
+            // Without an explicit sort order `$nearSphere` queries return their results sorted by distance.
+            let distance = |doc: &Document| {
+                lookup(doc, &path)
+                    .into_iter()
+                    .filter_map(point_of)
+                    .map(|point| origin.haversine_distance_to(&point))
+                    .fold(std::f64::INFINITY, f64::min)
+            };
+
+            results.sort_by(|a, b| {
+                distance(a)
+                    .partial_cmp(&distance(b))
+                    .unwrap_or(Ordering::Equal)
+            });
+        }
+
+        let skip = options.skip.filter(|&skip| skip > 0).unwrap_or(0) as usize;
+        let limit = options
+            .limit
+            .filter(|&limit| limit > 0)
+            .map_or(std::usize::MAX, |limit| limit as usize);
+
+        Ok(Box::new(results.into_iter().skip(skip).take(limit)))
+    }
+
+    fn find_one_and_update(
+        &self,
+        filter: Document,
+        update: Document,
+        upsert: bool,
+    ) -> mongodb::Result<Option<Document>> {
+        let mut collections = COLLECTIONS
+            .write()
+            .map_err(|_| mongodb::Error::PoisonLockError)?;
+        let documents = collections
+            .entry(self.collection.clone())
+            .or_insert_with(Vec::new);
+
+        for doc in documents.iter_mut() {
+            if matches(doc, &filter)? {
+                // Updates are atomic in MongoDB, so a failing update must not leave the document half updated.
+                let mut updated = doc.clone();
+                apply_update(&mut updated, &filter, &update, false)?;
+                *doc = updated.clone();
+
+                return Ok(Some(updated));
+            }
+        }
+
+        if upsert {
+            let mut doc = Document::new();
+            doc.insert("_id", ObjectId::new()?);
+
+            for (key, value) in filter.iter() {
+                if !key.starts_with('$') && !is_operator_document(value) {
+                    *entry_at_path(&mut doc, key)? = value.clone();
+                }
+            }
+
+            apply_update(&mut doc, &filter, &update, true)?;
+            documents.push(doc.clone());
+
+            return Ok(Some(doc));
+        }
+
+        Ok(None)
+    }
+
+    fn insert_one(&self, mut doc: Document) -> mongodb::Result<()> {
+        if !doc.contains_key("_id") {
+            doc.insert("_id", ObjectId::new()?);
+        }
+
+        COLLECTIONS
+            .write()
+            .map_err(|_| mongodb::Error::PoisonLockError)?
+            .entry(self.collection.clone())
+            .or_insert_with(Vec::new)
+            .push(doc);
+
+        Ok(())
+    }
+}
+
+/// Creates the error for a query or update that the in-memory store does not understand.
+fn unsupported(what: &str) -> mongodb::Error {
+    mongodb::Error::OperationError(format!("The in-memory store does not support {}.", what))
+}
+
+/// Determines whether the value is a document of query operators such as `{ "$gte": 1 }`.
+fn is_operator_document(value: &Bson) -> bool {
+    match value {
+        Bson::Document(doc) => doc.keys().next().map_or(false, |key| key.starts_with('$')),
+        _ => false,
+    }
+}
+
+/// Returns all values found at the dotted path within the document.
+///
+/// Arrays on the way are traversed the way MongoDB does it, so `properties.images.url`
+/// returns the URLs of all images and the elements of an array are returned alongside the array itself.
+pub(super) fn lookup<'a>(doc: &'a Document, path: &str) -> Vec<&'a Bson> {
+    let components: Vec<&str> = path.split('.').collect();
+    let mut values = Vec::new();
+
+    if let Some(value) = doc.get(components[0]) {
+        collect_values(value, &components[1..], &mut values);
+    }
+
+    values
+}
+
+/// Collects all values at the remaining path components below the given value.
+fn collect_values<'a>(value: &'a Bson, path: &[&str], values: &mut Vec<&'a Bson>) {
+    match path.split_first() {
+        None => {
+            values.push(value);
+
+            if let Bson::Array(elements) = value {
+                values.extend(elements.iter());
+            }
+        }
+        Some((component, rest)) => match value {
+            Bson::Document(doc) => {
+                if let Some(value) = doc.get(component) {
+                    collect_values(value, rest, values);
+                }
+            }
+            Bson::Array(elements) => {
+                if let Ok(index) = component.parse::<usize>() {
+                    if let Some(value) = elements.get(index) {
+                        collect_values(value, rest, values);
+                    }
+                } else {
+                    for element in elements {
+                        if let Bson::Document(_) = element {
+                            collect_values(element, path, values);
+                        }
+                    }
+                }
+            }
+            _ => (),
+        },
+    }
+}
+
+/// Determines whether the document matches the filter.
+pub(super) fn matches(doc: &Document, filter: &Document) -> mongodb::Result<bool> {
+    for (key, condition) in filter.iter() {
+        let matched = match key.as_str() {
+            "$and" | "$or" | "$nor" => {
+                let mut results = Vec::new();
+                for sub_filter in condition_list(condition)? {
+                    match sub_filter {
+                        Bson::Document(sub_filter) => results.push(matches(doc, sub_filter)?),
+                        _ => return Err(unsupported("non-document logical operands")),
+                    }
+                }
+
+                match key.as_str() {
+                    "$and" => results.iter().all(|&matched| matched),
+                    "$or" => results.iter().any(|&matched| matched),
+                    _ => !results.iter().any(|&matched| matched),
+                }
+            }
+            "$text" => text_score(doc, condition)? > 0.0,
+            key if key.starts_with('$') => return Err(unsupported(key)),
+            key => matches_condition(&lookup(doc, key), condition)?,
+        };
+
+        if !matched {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Splits a text into lowercase words.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Calculates the relevance of the document for a `$text` query.
+///
+/// Every occurrence of a search term in one of the text fields adds the weight of the field, which is a simpler
+/// measure than the one MongoDB uses, but ranks documents similarly.
+fn text_score(doc: &Document, search: &Bson) -> mongodb::Result<f64> {
+    let terms: Vec<String> = match search
+        .as_document()
+        .and_then(|search| search.get_str("$search").ok())
+    {
+        Some(search) => words(search).collect(),
+        None => return Err(unsupported("`$text` queries without `$search`")),
+    };
+
+    let mut score = 0.0;
+    for &(field, weight) in TEXT_INDEX_WEIGHTS.iter() {
+        for value in lookup(doc, field) {
+            if let Bson::String(text) = value {
+                let occurrences = words(text).filter(|word| terms.contains(word)).count();
+                score += f64::from(weight) * occurrences as f64;
+            }
+        }
+    }
+
+    Ok(score)
+}
+
+/// Determines whether the value is `{ "$meta": "textScore" }`.
+fn is_text_score(value: &Bson) -> bool {
+    value
+        .as_document()
+        .and_then(|value| value.get_str("$meta").ok())
+        == Some("textScore")
+}
+
+/// Returns the operands of an operator that expects an array.
+fn condition_list(condition: &Bson) -> mongodb::Result<&Vec<Bson>> {
+    match condition {
+        Bson::Array(list) => Ok(list),
+        _ => Err(unsupported("non-array operands")),
+    }
+}
+
+/// Determines whether the values found at a path satisfy the condition.
+fn matches_condition(values: &[&Bson], condition: &Bson) -> mongodb::Result<bool> {
+    match condition {
+        Bson::Document(operators) if is_operator_document(condition) => {
+            for (operator, argument) in operators.iter() {
+                if !matches_operator(values, operator, argument, operators)? {
+                    return Ok(false);
+                }
+            }
+
+            Ok(true)
+        }
+        Bson::Null => Ok(values.is_empty() || values.iter().any(|value| equals(value, condition))),
+        _ => Ok(values.iter().any(|value| equals(value, condition))),
+    }
+}
+
+/// Determines whether the values satisfy a single query operator.
+fn matches_operator(
+    values: &[&Bson],
+    operator: &str,
+    argument: &Bson,
+    operators: &Document,
+) -> mongodb::Result<bool> {
+    let any_compares = |accepted: &[Ordering]| {
+        values.iter().any(|value| {
+            compare(value, argument).map_or(false, |ordering| accepted.contains(&ordering))
+        })
+    };
+
+    Ok(match operator {
+        "$eq" => values.iter().any(|value| equals(value, argument)),
+        // Like equality with `null`, `{ "$ne": null }` also excludes documents without the field.
+        "$ne" => !matches_condition(values, argument)?,
+        "$gt" => any_compares(&[Ordering::Greater]),
+        "$gte" => any_compares(&[Ordering::Greater, Ordering::Equal]),
+        "$lt" => any_compares(&[Ordering::Less]),
+        "$lte" => any_compares(&[Ordering::Less, Ordering::Equal]),
+        "$in" => condition_list(argument)?
+            .iter()
+            .any(|candidate| matches_condition(values, candidate).unwrap_or(false)),
+        "$nin" => !condition_list(argument)?
+            .iter()
+            .any(|candidate| matches_condition(values, candidate).unwrap_or(false)),
+        "$exists" => argument.as_bool().unwrap_or(true) != values.is_empty(),
+        "$not" => !matches_condition(values, argument)?,
+        "$elemMatch" => match argument {
+            Bson::Document(sub_filter) => {
+                let mut matched = false;
+                for value in values {
+                    if let Bson::Document(element) = value {
+                        matched = matched || matches(element, sub_filter)?;
+                    }
+                }
+                matched
+            }
+            _ => return Err(unsupported("non-document `$elemMatch` operands")),
+        },
+        "$geoWithin" => {
+            let argument = argument
+                .as_document()
+                .ok_or_else(|| unsupported("non-document `$geoWithin` operands"))?;
+
+            if let Ok(geometry) = argument.get_document("$geometry") {
+                let polygons = polygons_of(geometry).ok_or_else(|| {
+                    unsupported("`$geoWithin` geometries other than `Polygon` and `MultiPolygon`")
+                })?;
+
+                values
+                    .iter()
+                    .filter_map(|value| point_of(value))
+                    .any(|point| polygons.iter().any(|rings| in_polygon(&point, rings)))
+            } else {
+                let (bottom_left, top_right) =
+                    match argument.get_array("$box").ok().and_then(|corners| {
+                        Some((point_of(corners.get(0)?)?, point_of(corners.get(1)?)?))
+                    }) {
+                        Some(corners) => corners,
+                        None => {
+                            return Err(unsupported(
+                                "`$geoWithin` queries other than `$box` and `$geometry`",
+                            ))
+                        }
+                    };
+
+                values
+                    .iter()
+                    .filter_map(|value| point_of(value))
+                    .any(|point| {
+                        point.latitude() >= bottom_left.latitude()
+                            && point.latitude() <= top_right.latitude()
+                            && point.longitude() >= bottom_left.longitude()
+                            && point.longitude() <= top_right.longitude()
+                    })
+            }
+        }
+        "$nearSphere" => {
+            let (origin, min_distance, max_distance) = near_sphere_arguments(argument)
+                .ok_or_else(|| unsupported("`$nearSphere` queries without `$geometry`"))?;
+
+            values
+                .iter()
+                .filter_map(|value| point_of(value))
+                .map(|point| origin.haversine_distance_to(&point))
+                .any(|distance| distance >= min_distance && distance <= max_distance)
+        }
+        // `$minDistance` and `$maxDistance` are handled as part of `$nearSphere`.
+        "$minDistance" | "$maxDistance" if operators.contains_key("$nearSphere") => true,
+        operator => return Err(unsupported(operator)),
+    })
+}
+
+/// Returns the search origin and the minimum and maximum distance of a `$nearSphere` argument.
+fn near_sphere_arguments(argument: &Bson) -> Option<(Location, f64, f64)> {
+    let argument = argument.as_document()?;
+    let origin = point_of(argument.get("$geometry")?)?;
+    let min_distance = argument
+        .get("$minDistance")
+        .and_then(number_of)
+        .unwrap_or(0.0);
+    let max_distance = argument
+        .get("$maxDistance")
+        .and_then(number_of)
+        .unwrap_or(std::f64::INFINITY);
+
+    Some((origin, min_distance, max_distance))
+}
+
+/// Finds a `$nearSphere` condition in the filter and returns its path and origin.
+fn near_sphere_origin(filter: &Document) -> Option<(String, Location)> {
+    filter.iter().find_map(|(key, condition)| {
+        let origin = near_sphere_arguments(condition.as_document()?.get("$nearSphere")?)?.0;
+
+        Some((key.clone(), origin))
+    })
+}
+
+/// Implements the `$geoNear` aggregation stage, which must be the first stage of a pipeline.
+///
+/// Returns the documents that match the query within the distance bounds, sorted by their distance to the origin.
+fn geo_near(documents: Vec<Document>, argument: &Document) -> mongodb::Result<Vec<Document>> {
+    let origin = argument
+        .get("near")
+        .and_then(point_of)
+        .ok_or_else(|| unsupported("`$geoNear` without a point"))?;
+    let key = argument.get_str("key").unwrap_or("geometry");
+    let distance_field = argument
+        .get_str("distanceField")
+        .map_err(|_| unsupported("`$geoNear` without `distanceField`"))?;
+    let query = argument
+        .get_document("query")
+        .map(Document::clone)
+        .unwrap_or_else(|_| Document::new());
+    let min_distance = argument
+        .get("minDistance")
+        .and_then(number_of)
+        .unwrap_or(0.0);
+    let max_distance = argument
+        .get("maxDistance")
+        .and_then(number_of)
+        .unwrap_or(std::f64::INFINITY);
+
+    let mut results = Vec::new();
+    for mut doc in documents {
+        let distance = lookup(&doc, key)
+            .into_iter()
+            .filter_map(point_of)
+            .map(|point| origin.haversine_distance_to(&point))
+            .fold(std::f64::INFINITY, f64::min);
+
+        if distance >= min_distance && distance <= max_distance && matches(&doc, &query)? {
+            *entry_at_path(&mut doc, distance_field)? = Bson::FloatingPoint(distance);
+            results.push((distance, doc));
+        }
+    }
+
+    results.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+    Ok(results.into_iter().map(|(_, doc)| doc).collect())
+}
+
+/// Returns the rings of all polygons in a GeoJSON `Polygon` or `MultiPolygon`.
+fn polygons_of(geometry: &Document) -> Option<Vec<Vec<Vec<Location>>>> {
+    let rings_of = |rings: &Bson| -> Option<Vec<Vec<Location>>> {
+        match rings {
+            Bson::Array(rings) => rings
+                .iter()
+                .map(|ring| match ring {
+                    Bson::Array(ring) => ring.iter().map(point_of).collect(),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        }
+    };
+    let coordinates = geometry.get("coordinates")?;
+
+    match geometry.get_str("type").ok()? {
+        "Polygon" => Some(vec![rings_of(coordinates)?]),
+        "MultiPolygon" => match coordinates {
+            Bson::Array(polygons) => polygons.iter().map(rings_of).collect(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Determines whether the point lies within the polygon, but not within one of its holes.
+///
+/// Unlike MongoDB this treats the edges as straight lines on a flat map, which is close enough for small areas.
+fn in_polygon(point: &Location, rings: &[Vec<Location>]) -> bool {
+    let in_ring = |ring: &Vec<Location>| {
+        let (x, y) = (point.longitude(), point.latitude());
+        let mut inside = false;
+
+        for (a, b) in ring.iter().zip(ring.iter().skip(1)) {
+            let (ax, ay, bx, by) = (a.longitude(), a.latitude(), b.longitude(), b.latitude());
+
+            if (ay > y) != (by > y) && x < (bx - ax) * (y - ay) / (by - ay) + ax {
+                inside = !inside;
+            }
+        }
+
+        inside
+    };
+
+    match rings.split_first() {
+        Some((outer, holes)) => in_ring(outer) && !holes.iter().any(in_ring),
+        None => false,
+    }
+}
+
+/// Converts a GeoJSON point or a legacy coordinate pair into a location.
+fn point_of(value: &Bson) -> Option<Location> {
+    let coordinates = match value {
+        Bson::Document(doc) => doc.get_array("coordinates").ok()?,
+        Bson::Array(coordinates) => coordinates,
+        _ => return None,
+    };
+
+    let lon = number_of(coordinates.get(0)?)?;
+    let lat = number_of(coordinates.get(1)?)?;
+
+    Some(Location::new(lat, lon))
+}
+
+/// Returns the value as a floating point number, if it is a number.
+pub(super) fn number_of(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::FloatingPoint(number) => Some(*number),
+        Bson::I32(number) => Some(f64::from(*number)),
+        Bson::I64(number) => Some(*number as f64),
+        _ => None,
+    }
+}
+
+/// Compares two values the way MongoDB does for values of the same type.
+///
+/// Returns `None` for values that can't be compared.
+pub(super) fn compare(a: &Bson, b: &Bson) -> Option<Ordering> {
+    match (a, b) {
+        (Bson::String(a), Bson::String(b)) => Some(a.cmp(b)),
+        (Bson::Boolean(a), Bson::Boolean(b)) => Some(a.cmp(b)),
+        (Bson::UtcDatetime(a), Bson::UtcDatetime(b)) => Some(a.cmp(b)),
+        (Bson::ObjectId(a), Bson::ObjectId(b)) => Some(a.bytes().cmp(&b.bytes())),
+        (Bson::Null, Bson::Null) => Some(Ordering::Equal),
+        (a, b) => number_of(a)?.partial_cmp(&number_of(b)?),
+    }
+}
+
+/// Evaluates an aggregation expression for the document.
+///
+/// Strings starting with `$` refer to the value at that path. All other values are constants.
+fn evaluate(doc: &Document, expression: &Bson) -> Bson {
+    match expression {
+        Bson::String(path) if path.starts_with('$') => lookup(doc, &path[1..])
+            .into_iter()
+            .next()
+            .cloned()
+            .unwrap_or(Bson::Null),
+        Bson::Document(fields) => Bson::Document(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), evaluate(doc, value)))
+                .collect(),
+        ),
+        _ => expression.clone(),
+    }
+}
+
+/// Groups the documents like the `$group` stage of an aggregation pipeline.
+///
+/// The groups keep the order in which their first document appeared.
+fn group(documents: &[Document], specification: &Document) -> mongodb::Result<Vec<Document>> {
+    let key_expression = specification.get("_id").unwrap_or(&Bson::Null);
+
+    let mut groups: Vec<(Bson, Vec<&Document>)> = Vec::new();
+    for doc in documents {
+        let key = evaluate(doc, key_expression);
+
+        match groups.iter_mut().find(|(existing, _)| *existing == key) {
+            Some((_, members)) => members.push(doc),
+            None => groups.push((key, vec![doc])),
+        }
+    }
+
+    let mut results = Vec::new();
+    for (key, members) in groups {
+        let mut result = Document::new();
+        result.insert("_id", key);
+
+        for (field, accumulator) in specification.iter().filter(|(field, _)| *field != "_id") {
+            let (operator, expression) = match accumulator {
+                Bson::Document(accumulator) if accumulator.len() == 1 => {
+                    accumulator.iter().next().unwrap()
+                }
+                _ => return Err(unsupported("accumulators other than `$sum` and `$avg`")),
+            };
+
+            // Like MongoDB, both accumulators ignore values that are not numbers.
+            let values: Vec<Bson> = members
+                .iter()
+                .map(|doc| evaluate(doc, expression))
+                .filter(|value| number_of(value).is_some())
+                .collect();
+            let sum: f64 = values.iter().filter_map(number_of).sum();
+
+            let value = match operator.as_str() {
+                "$sum"
+                    if values
+                        .iter()
+                        .all(|value| !matches!(value, Bson::FloatingPoint(_))) =>
+                {
+                    Bson::I64(sum as i64)
+                }
+                "$sum" => Bson::FloatingPoint(sum),
+                "$avg" if values.is_empty() => Bson::Null,
+                "$avg" => Bson::FloatingPoint(sum / values.len() as f64),
+                _ => return Err(unsupported("accumulators other than `$sum` and `$avg`")),
+            };
+
+            result.insert(field.clone(), value);
+        }
+
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+/// Compares two documents according to a MongoDB sort specification such as `{ "_id": 1 }`.
+///
+/// Documents without a value for a sort key are ordered first, just like in MongoDB.
+fn compare_by_sort(a: &Document, b: &Document, sort: &Document) -> Ordering {
+    for (key, direction) in sort.iter() {
+        let ordering = match (lookup(a, key).first(), lookup(b, key).first()) {
+            (Some(a), Some(b)) => compare(a, b).unwrap_or(Ordering::Equal),
+            (Some(_), None) => Ordering::Greater,
+            (None, Some(_)) => Ordering::Less,
+            (None, None) => Ordering::Equal,
+        };
+
+        // Sorting by the relevance always puts the most relevant documents first.
+        let descending = is_text_score(direction)
+            || number_of(direction).map_or(false, |direction| direction < 0.0);
+
+        let ordering = if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    Ordering::Equal
+}
+
+/// Determines whether two values are equal.
+fn equals(a: &Bson, b: &Bson) -> bool {
+    compare(a, b) == Some(Ordering::Equal) || a == b
+}
+
+/// Applies the update operators to the document.
+///
+/// `$setOnInsert` is only applied if the document is being inserted.
+fn apply_update(
+    doc: &mut Document,
+    filter: &Document,
+    update: &Document,
+    inserting: bool,
+) -> mongodb::Result<()> {
+    for (operator, fields) in update.iter() {
+        let fields = match fields {
+            Bson::Document(fields) => fields,
+            _ => return Err(unsupported("non-document update operands")),
+        };
+
+        for (path, value) in fields.iter() {
+            let path = resolve_positional(doc, filter, path)?;
+
+            match operator.as_str() {
+                "$set" => *entry_at_path(doc, &path)? = value.clone(),
+                "$setOnInsert" => {
+                    if inserting {
+                        *entry_at_path(doc, &path)? = value.clone();
+                    }
+                }
+                "$unset" => unset_path(doc, &path),
+                "$inc" => {
+                    let entry = entry_at_path(doc, &path)?;
+                    let current = number_of(entry).unwrap_or(0.0);
+                    let increment =
+                        number_of(value).ok_or_else(|| unsupported("non-numeric `$inc`"))?;
+
+                    *entry = match (&*entry, value) {
+                        (Bson::I32(_), Bson::I32(_)) | (Bson::Null, Bson::I32(_)) => {
+                            Bson::I32((current + increment) as i32)
+                        }
+                        (Bson::I64(_), _) | (Bson::Null, Bson::I64(_)) => {
+                            Bson::I64((current + increment) as i64)
+                        }
+                        _ => Bson::FloatingPoint(current + increment),
+                    };
+                }
+                "$push" | "$addToSet" => {
+                    let new_elements = match value {
+                        Bson::Document(modifiers) if modifiers.contains_key("$each") => {
+                            condition_list(modifiers.get("$each").unwrap())?.clone()
+                        }
+                        value => vec![value.clone()],
+                    };
+
+                    let entry = entry_at_path(doc, &path)?;
+                    if let Bson::Null = entry {
+                        *entry = Bson::Array(Vec::new());
+                    }
+
+                    match entry {
+                        Bson::Array(elements) => {
+                            for element in new_elements {
+                                if operator == "$push" || !elements.contains(&element) {
+                                    elements.push(element);
+                                }
+                            }
+
+                            // `$slice` keeps the first or, if it is negative, the last elements.
+                            if let Some(slice) = value
+                                .as_document()
+                                .and_then(|modifiers| modifiers.get("$slice"))
+                            {
+                                let slice = number_of(slice)
+                                    .ok_or_else(|| unsupported("non-numeric `$slice`"))?
+                                    as i64;
+                                let kept = slice.unsigned_abs().min(elements.len() as u64) as usize;
+
+                                if slice < 0 {
+                                    elements.drain(..elements.len() - kept);
+                                } else {
+                                    elements.truncate(kept);
+                                }
+                            }
+                        }
+                        _ => return Err(unsupported("pushing to non-array fields")),
+                    }
+                }
+                operator => return Err(unsupported(operator)),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces the positional operator `$` in an update path with the index of the first matching array element.
+///
+/// The matching element is determined by the conditions of the filter on the same array, including an `$elemMatch`
+/// condition on the array itself.
+fn resolve_positional(doc: &Document, filter: &Document, path: &str) -> mongodb::Result<String> {
+    let position = match path.find(".$") {
+        Some(position) => position,
+        None => return Ok(String::from(path)),
+    };
+
+    let array_path = &path[..position];
+    let prefix = format!("{}.", array_path);
+
+    let elements = match lookup(doc, array_path).into_iter().next() {
+        Some(Bson::Array(elements)) => elements,
+        _ => return Err(unsupported("the positional operator on non-array fields")),
+    };
+
+    let element_filter = filter
+        .get(array_path)
+        .and_then(Bson::as_document)
+        .and_then(|condition| condition.get("$elemMatch"))
+        .and_then(Bson::as_document);
+
+    for (index, element) in elements.iter().enumerate() {
+        let mut element_matches = match (element_filter, element) {
+            (Some(element_filter), Bson::Document(element)) => matches(element, element_filter)?,
+            (Some(_), _) => false,
+            (None, _) => true,
+        };
+
+        for (key, condition) in filter.iter() {
+            if key.starts_with(&prefix) {
+                let mut values = Vec::new();
+                let components: Vec<&str> = key[prefix.len()..].split('.').collect();
+                collect_values(element, &components, &mut values);
+
+                element_matches = element_matches && matches_condition(&values, condition)?;
+            }
+        }
+
+        if element_matches {
+            return Ok(format!("{}.{}{}", array_path, index, &path[position + 2..]));
+        }
+    }
+
+    Err(unsupported(
+        "the positional operator without a matching array element",
+    ))
+}
+
+/// Returns the value at the dotted path, creating it and all missing parent documents if necessary.
+///
+/// Newly created values are `null`.
+pub(super) fn entry_at_path<'a>(
+    doc: &'a mut Document,
+    path: &str,
+) -> mongodb::Result<&'a mut Bson> {
+    let mut components = path.split('.');
+    let first = components.next().unwrap_or("");
+
+    if !doc.contains_key(first) {
+        doc.insert(first, Bson::Null);
+    }
+    let mut current = doc.get_mut(first).unwrap();
+
+    for component in components {
+        if let Bson::Null = current {
+            *current = Bson::Document(Document::new());
+        }
+
+        current = match current {
+            Bson::Document(inner) => {
+                if !inner.contains_key(component) {
+                    inner.insert(component, Bson::Null);
+                }
+                inner.get_mut(component).unwrap()
+            }
+            Bson::Array(elements) => {
+                let index = component
+                    .parse::<usize>()
+                    .map_err(|_| unsupported("non-numeric array paths"))?;
+                while elements.len() <= index {
+                    elements.push(Bson::Null);
+                }
+                &mut elements[index]
+            }
+            _ => return Err(unsupported("paths through scalar values")),
+        };
+    }
+
+    Ok(current)
+}
+
+/// Removes the value at the dotted path, if it exists.
+fn unset_path(doc: &mut Document, path: &str) {
+    match path.rfind('.') {
+        None => {
+            doc.remove(path);
+        }
+        Some(position) => {
+            let (parent_path, key) = (&path[..position], &path[position + 1..]);
+
+            if lookup(doc, parent_path).is_empty() {
+                return;
+            }
+
+            if let Ok(parent) = entry_at_path(doc, parent_path) {
+                match parent {
+                    Bson::Document(parent) => {
+                        parent.remove(key);
+                    }
+                    Bson::Array(elements) => {
+                        // MongoDB sets array elements to `null` instead of removing them.
+                        if let Some(element) = key
+                            .parse::<usize>()
+                            .ok()
+                            .and_then(|index| elements.get_mut(index))
+                        {
+                            *element = Bson::Null;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+}
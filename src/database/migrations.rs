@@ -0,0 +1,131 @@
+//! Contains one-off migrations of the data in the database.
+//!
+//! Migrations are run at startup if the corresponding configuration variable is set.
+
+use chrono::Utc;
+use log::warn;
+use rocket_contrib::databases::mongodb::{self, bson, coll::Collection, doc, Bson, Document};
+
+use crate::facilities::{attribute_values, parse_timestamp, push_vote, vote, votes_path};
+
+/// Converts `lastUpdated` values stored as strings into dates.
+///
+/// Previously `lastUpdated` was stored in the format `1999-12-31 23:59:59.578783 UTC`, which
+/// can't be compared with dates in queries. Any other format accepted by `parse_timestamp` is converted as well.
+/// Facilities whose timestamp can't be parsed are logged and left unchanged.
+///
+/// Returns the number of converted and skipped facilities.
+pub fn convert_string_timestamps(collection: &Collection) -> mongodb::Result<(usize, usize)> {
+    let mut converted = 0;
+    let mut skipped = 0;
+
+    for doc in collection.find(Some(doc! { "lastUpdated": { "$type": "string" } }), None)? {
+        let doc = doc?;
+
+        let id = match doc.get("_id") {
+            Some(id) => id.clone(),
+            None => continue,
+        };
+        let timestamp = doc.get_str("lastUpdated").unwrap_or_default();
+
+        let date = match parse_timestamp(timestamp) {
+            Some(date) => date,
+            None => {
+                warn!(
+                    "Could not convert `lastUpdated` of facility {} ({:?}), leaving it unchanged.",
+                    id, timestamp
+                );
+                skipped += 1;
+                continue;
+            }
+        };
+
+        collection.update_one(
+            doc! { "_id": id },
+            doc! { "$set": { "lastUpdated": Bson::UtcDatetime(date) } },
+            None,
+        )?;
+
+        converted += 1;
+    }
+
+    Ok((converted, skipped))
+}
+
+/// Moves `fee` values from `properties.accesibility.fee` to `properties.accessibility.fee`.
+///
+/// Previously the value was stored under the misspelled path. Values stored under the correct path are newer, so
+/// they are kept and only the misspelled values of facilities without one are moved.
+///
+/// Returns the number of facilities whose value was moved.
+pub fn move_misspelled_fee(collection: &Collection) -> mongodb::Result<i32> {
+    let moved = collection
+        .update_many(
+            doc! {
+                "properties.accesibility.fee": { "$exists": true },
+                "properties.accessibility.fee": { "$exists": false }
+            },
+            doc! { "$rename": { "properties.accesibility.fee": "properties.accessibility.fee" } },
+            None,
+        )?
+        .modified_count;
+
+    // The misspelled object only ever contained the fee.
+    collection.update_many(
+        doc! { "properties.accesibility": { "$exists": true } },
+        doc! { "$unset": { "properties.accesibility": "" } },
+        None,
+    )?;
+
+    Ok(moved)
+}
+
+/// Converts the attributes listed in `properties.verifiedAttributes` into votes for their current values.
+///
+/// Previously verifying attributes only added their names to that list, which no longer counts. The time of the
+/// verification wasn't stored, so the votes are dated to the last update of the facility.
+///
+/// Returns the number of converted facilities.
+pub fn convert_verified_attributes(collection: &Collection) -> mongodb::Result<usize> {
+    let mut converted = 0;
+
+    for doc in collection.find(
+        Some(doc! { "properties.verifiedAttributes": { "$exists": true } }),
+        None,
+    )? {
+        let doc = doc?;
+
+        let id = match doc.get("_id") {
+            Some(id) => id.clone(),
+            None => continue,
+        };
+        let verified_attributes: Vec<&str> = doc
+            .get_document("properties")
+            .and_then(|properties| properties.get_array("verifiedAttributes"))
+            .map(|names| names.iter().filter_map(Bson::as_str).collect())
+            .unwrap_or_default();
+        let timestamp = doc
+            .get_utc_datetime("lastUpdated")
+            .map(|timestamp| *timestamp)
+            .unwrap_or_else(|_| Utc::now());
+
+        let facility = serde_json::Value::from(Bson::Document(doc.clone()));
+        let mut votes = Document::new();
+        for (attribute, value) in attribute_values(&facility) {
+            if verified_attributes.contains(&attribute.get_name()) {
+                votes.insert(votes_path(attribute), push_vote(vote(&value, timestamp)));
+            }
+        }
+
+        let mut update = doc! { "$unset": { "properties.verifiedAttributes": "" } };
+        if !votes.is_empty() {
+            update.insert("$push", votes);
+        }
+
+        collection.update_one(doc! { "_id": id }, update, None)?;
+
+        converted += 1;
+    }
+
+    Ok(converted)
+}
@@ -0,0 +1,63 @@
+//! Implements the facility store on top of a MongoDB collection.
+
+use rocket_contrib::databases::mongodb::{
+    self,
+    coll::{
+        options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument},
+        Collection,
+    },
+    Document,
+};
+
+use super::FacilityStore;
+
+/// A facility store that keeps all facilities in a MongoDB collection.
+pub struct MongoFacilityStore(Collection);
+
+impl MongoFacilityStore {
+    /// Creates a store for the given collection.
+    pub fn new(collection: Collection) -> MongoFacilityStore {
+        MongoFacilityStore(collection)
+    }
+}
+
+impl FacilityStore for MongoFacilityStore {
+    fn find(
+        &self,
+        filter: Option<Document>,
+        options: Option<FindOptions>,
+    ) -> mongodb::Result<Box<dyn Iterator<Item = Document>>> {
+        Ok(Box::new(
+            self.0.find(filter, options)?.filter_map(|val| val.ok()),
+        ))
+    }
+
+    fn count(&self, filter: Document) -> mongodb::Result<u64> {
+        self.0.count(Some(filter), None).map(|count| count as u64)
+    }
+
+    fn aggregate(&self, pipeline: Vec<Document>) -> mongodb::Result<Vec<Document>> {
+        Ok(self
+            .0
+            .aggregate(pipeline, None)?
+            .filter_map(|val| val.ok())
+            .collect())
+    }
+
+    fn find_one_and_update(
+        &self,
+        filter: Document,
+        update: Document,
+        upsert: bool,
+    ) -> mongodb::Result<Option<Document>> {
+        let mut options = FindOneAndUpdateOptions::new();
+        options.upsert = Some(upsert);
+        options.return_document = Some(ReturnDocument::After);
+
+        self.0.find_one_and_update(filter, update, Some(options))
+    }
+
+    fn insert_one(&self, doc: Document) -> mongodb::Result<()> {
+        self.0.insert_one(doc, None).map(|_| ())
+    }
+}
@@ -0,0 +1,279 @@
+//! Provides the error type that is returned by all routes.
+//!
+//! Every error is answered with an HTTP status matching its code and a JSON body of the following form:
+//!
+//! ```json
+//! {
+//!     "result": "failure",
+//!     "error": {
+//!         "code": "invalidRequest",
+//!         "message": "The limit must be greater than zero.",
+//!         "requestId": "4c7c5e0e-3b5a-4f43-9c1e-5d6e1f0c1c55"
+//!     }
+//! }
+//! ```
+//!
+//! `result` is kept for clients that predate the error codes. It is `entryNotFound` for errors with the code
+//! `notFound` and `failure` for all others. Some errors contain additional fields next to `error`, such as the
+//! `unknownAttributes` of an invalid filter.
+
+use log::error;
+use rocket::{
+    catch, catchers,
+    fairing::AdHoc,
+    http::{Header, Status},
+    request::Request,
+    response::{self, Responder, Response},
+    Catcher,
+};
+use rocket_contrib::{databases::mongodb, json, json::JsonValue};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::facilities::OperationResult;
+
+/// The header that contains the ID of the request in every response.
+const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// The machine-readable codes of errors.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[allow(non_camel_case_types)]
+pub enum ErrorCode {
+    /// The parameters or the body of the request are invalid.
+    invalidRequest,
+    /// The body of the request could not be parsed.
+    invalidBody,
+    /// The request requires administrator privileges.
+    unauthorized,
+    /// The requested feature is disabled.
+    forbidden,
+    /// The requested facility or part of it does not exist.
+    notFound,
+    /// The body of the request is too large.
+    payloadTooLarge,
+    /// The format of the body of the request is not supported.
+    unsupportedMediaType,
+    /// Something went wrong on the server, most likely while accessing the database.
+    internalError,
+}
+
+impl ErrorCode {
+    /// Returns the HTTP status that errors with this code are answered with.
+    pub fn status(self) -> Status {
+        match self {
+            ErrorCode::invalidRequest => Status::BadRequest,
+            ErrorCode::invalidBody => Status::UnprocessableEntity,
+            ErrorCode::unauthorized => Status::Unauthorized,
+            ErrorCode::forbidden => Status::Forbidden,
+            ErrorCode::notFound => Status::NotFound,
+            ErrorCode::payloadTooLarge => Status::PayloadTooLarge,
+            ErrorCode::unsupportedMediaType => Status::UnsupportedMediaType,
+            ErrorCode::internalError => Status::InternalServerError,
+        }
+    }
+}
+
+/// An error that occurred while handling a request.
+#[derive(Debug)]
+pub struct Error {
+    /// The machine-readable code of the error.
+    code: ErrorCode,
+    /// The message describing the error to humans.
+    message: String,
+    /// Additional fields of the response, such as the invalid values of a request.
+    details: serde_json::Map<String, serde_json::Value>,
+    /// The underlying error, which is logged instead of being sent to the client.
+    cause: Option<String>,
+}
+
+impl Error {
+    /// Creates an error with the given code and message.
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Error {
+        Error {
+            code,
+            message: message.into(),
+            details: serde_json::Map::new(),
+            cause: None,
+        }
+    }
+
+    /// Creates an error for a request with invalid parameters or an invalid body.
+    pub fn invalid_request(message: impl Into<String>) -> Error {
+        Error::new(ErrorCode::invalidRequest, message)
+    }
+
+    /// Creates an error for a facility or part of a facility that does not exist.
+    pub fn not_found(message: impl Into<String>) -> Error {
+        Error::new(ErrorCode::notFound, message)
+    }
+
+    /// Creates an error for something that went wrong on the server.
+    pub fn internal(message: impl Into<String>) -> Error {
+        Error::new(ErrorCode::internalError, message)
+    }
+
+    /// Adds a field to the response.
+    pub fn with_detail(mut self, key: &str, value: impl Serialize) -> Error {
+        self.details.insert(
+            String::from(key),
+            serde_json::to_value(value).unwrap_or(serde_json::Value::Null),
+        );
+
+        self
+    }
+
+    /// Returns the result of an operation that failed with this error.
+    ///
+    /// This is used for operations that are part of a larger request, such as a single answer of many.
+    pub fn to_result(&self) -> JsonValue {
+        let result = match self.code {
+            ErrorCode::notFound => OperationResult::entryNotFound,
+            _ => OperationResult::failure,
+        };
+
+        let mut body = json!({
+            "result": result,
+            "error": { "code": self.code, "message": self.message }
+        });
+
+        for (key, value) in &self.details {
+            body[key.as_str()] = value.clone();
+        }
+
+        body
+    }
+}
+
+impl From<mongodb::Error> for Error {
+    fn from(error: mongodb::Error) -> Error {
+        Error {
+            cause: Some(error.to_string()),
+            ..Error::internal("The database could not be accessed.")
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Error {
+        Error {
+            cause: Some(error.to_string()),
+            ..Error::internal("The image storage could not be accessed.")
+        }
+    }
+}
+
+impl<'r> Responder<'r> for Error {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        let request_id = RequestId::of(request);
+
+        if let Some(cause) = &self.cause {
+            error!("Request {} failed: {}", request_id.0, cause);
+        }
+
+        let mut body = self.to_result();
+        body["error"]["requestId"] = serde_json::Value::from(request_id.0.clone());
+
+        Response::build_from(body.respond_to(request)?)
+            .status(self.code.status())
+            .ok()
+    }
+}
+
+/// The ID of a request, which is sent in the `X-Request-Id` header of the response and in every error.
+///
+/// Clients can report it, so that the request can be found in the logs.
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Returns the ID of the request, generating it on first use.
+    pub fn of<'a>(request: &'a Request) -> &'a RequestId {
+        request.local_cache(|| RequestId(Uuid::new_v4().to_string()))
+    }
+}
+
+/// Returns the fairing that adds the `X-Request-Id` header to every response.
+pub fn request_id_fairing() -> AdHoc {
+    AdHoc::on_response("Request ID", |request, response| {
+        response.set_header(Header::new(
+            REQUEST_ID_HEADER,
+            RequestId::of(request).0.clone(),
+        ));
+    })
+}
+
+/// Answers requests that Rocket rejected as malformed.
+#[catch(400)]
+fn bad_request() -> Error {
+    Error::invalid_request("The request is invalid.")
+}
+
+/// Answers requests with a body that could not be parsed.
+#[catch(422)]
+fn unprocessable_entity() -> Error {
+    Error::new(
+        ErrorCode::invalidBody,
+        "The body of the request could not be parsed.",
+    )
+}
+
+/// Answers requests to administrator routes without the correct token.
+#[catch(401)]
+fn unauthorized() -> Error {
+    Error::new(
+        ErrorCode::unauthorized,
+        "The request requires a valid administrator token.",
+    )
+}
+
+/// Answers requests to administrator routes if no administrator token is configured.
+#[catch(403)]
+fn forbidden() -> Error {
+    Error::new(
+        ErrorCode::forbidden,
+        "Administrator requests are disabled on this server.",
+    )
+}
+
+/// Answers requests to unknown routes and for images that don't exist.
+#[catch(404)]
+fn not_found() -> Error {
+    Error::not_found("The requested resource does not exist.")
+}
+
+/// Answers requests with a body that is too large.
+#[catch(413)]
+fn payload_too_large() -> Error {
+    Error::new(
+        ErrorCode::payloadTooLarge,
+        "The body of the request is too large.",
+    )
+}
+
+/// Answers requests with a body in an unsupported format.
+#[catch(415)]
+fn unsupported_media_type() -> Error {
+    Error::new(
+        ErrorCode::unsupportedMediaType,
+        "The format of the body of the request is not supported.",
+    )
+}
+
+/// Answers requests that failed unexpectedly.
+#[catch(500)]
+fn internal_server_error() -> Error {
+    Error::internal("Something went wrong on the server.")
+}
+
+/// Returns the catchers that answer the errors of Rocket with the error format of the API.
+pub fn error_catchers() -> Vec<Catcher> {
+    catchers![
+        bad_request,
+        unprocessable_entity,
+        unauthorized,
+        forbidden,
+        not_found,
+        payload_too_large,
+        unsupported_media_type,
+        internal_server_error
+    ]
+}
@@ -1,29 +1,85 @@
 //! Implements the API for retrieving information about facilities.
 
+mod area;
 mod attributes;
+mod clustering;
+mod filter;
+mod mvt;
+mod pagination;
 mod query;
 mod questions;
 mod update;
+mod verification;
+mod visits;
 
 use rocket::{routes, Route};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+pub use self::{
+    query::parse_timestamp,
+    verification::{
+        add_confidence_scores, attribute_values, is_recent_vote, majority_proposal, push_vote,
+        vote, votes_path,
+    },
+};
 
 /// Returns the routes of the facilities API.
 pub fn facilites_routes() -> Vec<Route> {
     routes![
+        attributes::attributes,
         query::by_tile,
+        query::by_tile_mvt,
+        query::within,
         query::by_radius,
+        query::nearest,
+        query::search,
         query::by_id,
         query::by_source_id,
+        query::history,
         query::updated_since,
         update::set_facility::set_facility,
         update::will_visit::will_visit,
+        update::answer::answer,
         update::comments::add_comment,
         update::comments::flag_comment,
         update::verify_attributes::verify_attributes,
+        update::dispute_attribute::dispute_attribute,
+        update::revert::revert_change,
+        update::revert::restore_facility,
+        visits::visit_statistics,
+        visits::visits,
+        visits::most_visited,
     ]
 }
 
+/// Loads the attribute catalogue, so that problems with it are reported at startup.
+pub fn load_attribute_catalogue() {
+    lazy_static::initialize(&attributes::ATTRIBUTES);
+}
+
+/// Encodes the value into an opaque string that clients can pass back to the server.
+fn encode_token<T: Serialize>(value: &T) -> String {
+    serde_json::to_vec(value)
+        .unwrap_or_default()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Decodes a value that was previously encoded with `encode_token`.
+fn decode_token<T: DeserializeOwned>(encoded: &str) -> Option<T> {
+    if encoded.len() % 2 != 0 || !encoded.is_ascii() {
+        return None;
+    }
+
+    let bytes = (0..encoded.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&encoded[index..index + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+
+    serde_json::from_slice(&bytes).ok()
+}
+
 /// Represents an ID for entries in the database.
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[allow(non_snake_case)]
@@ -0,0 +1,156 @@
+//! Describes arbitrary areas that facilities can be searched in.
+//!
+//! An area is either a GeoJSON `Polygon` or `MultiPolygon` geometry or a bounding box.
+
+use rocket_contrib::databases::mongodb::{bson, doc, Bson, Document};
+use serde::Deserialize;
+
+use crate::error::Error;
+
+/// A position consisting of longitude and latitude, just like in GeoJSON.
+type Position = Vec<f64>;
+
+/// A closed ring of positions.
+type Ring = Vec<Position>;
+
+/// The area sent by a client.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(super) enum Area {
+    /// A bounding box given as `[west, south, east, north]`.
+    BoundingBox {
+        /// The corners of the bounding box.
+        bbox: Vec<f64>,
+    },
+    /// A GeoJSON geometry.
+    Geometry(Geometry),
+}
+
+/// The GeoJSON geometries that can be used as an area.
+#[derive(Deserialize)]
+#[serde(tag = "type", content = "coordinates")]
+pub(super) enum Geometry {
+    /// A polygon consisting of an outer ring and optionally holes.
+    Polygon(Vec<Ring>),
+    /// Multiple polygons.
+    MultiPolygon(Vec<Vec<Ring>>),
+}
+
+/// Creates the error for an invalid area.
+fn invalid_area(reason: &str) -> Error {
+    Error::invalid_request(reason)
+}
+
+/// Checks that a position is a pair of coordinates in the legal range.
+fn validate_position(position: &[f64]) -> Result<(), Error> {
+    match position {
+        [longitude, latitude] | [longitude, latitude, _]
+            if longitude.is_finite()
+                && latitude.is_finite()
+                && *longitude >= -180.0
+                && *longitude <= 180.0
+                && *latitude >= -90.0
+                && *latitude <= 90.0 =>
+        {
+            Ok(())
+        }
+        _ => Err(invalid_area("The coordinates were not in the legal range.")),
+    }
+}
+
+/// Checks that the rings form a valid polygon.
+///
+/// Every ring needs at least four positions and must end where it starts.
+fn validate_polygon(rings: &[Ring]) -> Result<(), Error> {
+    if rings.is_empty() {
+        return Err(invalid_area("A polygon needs at least one ring."));
+    }
+
+    for ring in rings {
+        if ring.len() < 4 || ring.first() != ring.last() {
+            return Err(invalid_area(
+                "Every ring of a polygon needs at least four positions and must be closed.",
+            ));
+        }
+
+        for position in ring {
+            validate_position(position)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts rings into their BSON representation.
+fn rings_to_bson(rings: &[Ring]) -> Bson {
+    Bson::Array(
+        rings
+            .iter()
+            .map(|ring| {
+                Bson::Array(
+                    ring.iter()
+                        .map(|position| {
+                            Bson::Array(position.iter().map(|&c| Bson::FloatingPoint(c)).collect())
+                        })
+                        .collect(),
+                )
+            })
+            .collect(),
+    )
+}
+
+impl Area {
+    /// Validates the area and creates the condition for the location of the facilities within it.
+    pub fn to_condition(&self) -> Result<Document, Error> {
+        match self {
+            Area::BoundingBox { bbox } => {
+                let (west, south, east, north) = match bbox.as_slice() {
+                    [west, south, east, north] => (*west, *south, *east, *north),
+                    _ => return Err(invalid_area(
+                        "A bounding box needs exactly four numbers: west, south, east and north.",
+                    )),
+                };
+
+                validate_position(&[west, south])?;
+                validate_position(&[east, north])?;
+
+                if west > east || south > north {
+                    return Err(invalid_area(
+                        "The south west corner of the bounding box must be below and left of the north east corner.",
+                    ));
+                }
+
+                // Refer to https://docs.mongodb.com/manual/reference/operator/query/box/#op._S_box
+                Ok(doc! { "$geoWithin": { "$box": [[west, south], [east, north]] } })
+            }
+            Area::Geometry(Geometry::Polygon(rings)) => {
+                validate_polygon(rings)?;
+
+                // Refer to https://docs.mongodb.com/manual/reference/operator/query/geoWithin/
+                Ok(doc! {
+                    "$geoWithin": {
+                        "$geometry": { "type": "Polygon", "coordinates": rings_to_bson(rings) }
+                    }
+                })
+            }
+            Area::Geometry(Geometry::MultiPolygon(polygons)) => {
+                if polygons.is_empty() {
+                    return Err(invalid_area("A multi polygon needs at least one polygon."));
+                }
+
+                for rings in polygons {
+                    validate_polygon(rings)?;
+                }
+
+                let coordinates =
+                    Bson::Array(polygons.iter().map(|rings| rings_to_bson(rings)).collect());
+
+                Ok(doc! {
+                    "$geoWithin": {
+                        "$geometry": { "type": "MultiPolygon", "coordinates": coordinates }
+                    }
+                })
+            }
+        }
+    }
+}
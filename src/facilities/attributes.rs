@@ -1,27 +1,71 @@
 //! Describes all the attributes the server manages.
+//!
+//! The attributes are loaded from a catalogue at startup. Unless `TONARI_ATTRIBUTE_CATALOGUE` points to a JSON file,
+//! the catalogue in `attributes.json` next to this module is used.
+
+use lazy_static::lazy_static;
+use rocket::get;
+use rocket_contrib::{json, json::JsonValue};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+use super::OperationResult;
+use crate::{configuration::ATTRIBUTE_CATALOGUE, database::paths_overlap};
+
+/// The catalogue that is used if no other catalogue is configured.
+const DEFAULT_CATALOGUE: &str = include_str!("attributes.json");
+
+lazy_static! {
+    /// Represents all the attributes the server manages.
+    pub static ref ATTRIBUTES: Vec<AttributeDescription> = load_catalogue();
+}
 
 /// Provides a description of an attribute.
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct AttributeDescription {
     /// The name of the attribute for the front end.
-    name: &'static str,
+    name: String,
     /// The values the attribute can have.
     domain: AttributeDomain,
-    /// The path to the attribute in the database.
-    database_path: &'static [&'static str],
+    /// The path to the attribute in the database in dotted notation.
+    database_path: String,
+    /// How important it is to ask users about the attribute.
+    ///
+    /// Questions about attributes with a higher priority are asked first.
+    #[serde(default)]
+    question_priority: i32,
+    /// The name of the attribute for users by language code.
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
 }
 
 impl AttributeDescription {
     /// Returns the name of this attribute.
     pub fn get_name(&self) -> &str {
-        self.name
+        &self.name
+    }
+
+    /// Returns the values this attribute can have.
+    pub fn get_domain(&self) -> &AttributeDomain {
+        &self.domain
+    }
+
+    /// Returns the path to the attribute in the database in dotted notation.
+    pub fn get_database_path(&self) -> String {
+        self.database_path.clone()
+    }
+
+    /// Returns how important it is to ask users about this attribute.
+    pub fn get_question_priority(&self) -> i32 {
+        self.question_priority
     }
 
     /// Returns the value of the attribute within the facility, if it exists.
     pub fn get_value(&self, facility: &serde_json::Value) -> Option<serde_json::Value> {
         let mut current_path = facility;
 
-        for path_component in self.database_path {
+        for path_component in self.database_path.split('.') {
             current_path = &current_path[path_component];
         }
 
@@ -34,97 +78,246 @@ impl AttributeDescription {
 }
 
 /// Represents all values that the attribute can have.
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
 pub enum AttributeDomain {
-    /// The attribute values must be one of the elements in the given slice.
-    Enum(&'static [&'static str]),
+    /// The attribute values must be one of the given values.
+    Enum {
+        /// The possible values.
+        values: Vec<String>,
+    },
     /// The attribute values may either be `true` or `false`.
     Boolean,
+    /// The attribute values are measurements, such as the width of a door.
+    Number {
+        /// The smallest possible value.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        min: Option<f64>,
+        /// The largest possible value.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max: Option<f64>,
+        /// The unit of the values, such as `cm`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        unit: Option<String>,
+    },
+    /// The attribute values are whole numbers, such as the number of steps.
+    Integer {
+        /// The smallest possible value.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        min: Option<i64>,
+        /// The largest possible value.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max: Option<i64>,
+        /// The unit of the values.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        unit: Option<String>,
+    },
+}
+
+/// The bounds and the unit of the values of a numeric attribute.
+pub struct NumericRange<'a> {
+    /// The smallest possible value.
+    pub min: Option<f64>,
+    /// The largest possible value.
+    pub max: Option<f64>,
+    /// The unit of the values.
+    pub unit: Option<&'a str>,
 }
 
 impl AttributeDomain {
     /// Determines if the value is valid for the given attribute.
     pub fn is_valid_value(&self, value: &serde_json::Value) -> bool {
         match self {
-            AttributeDomain::Enum(possible_values) if value.is_string() => {
-                possible_values.contains(&value.as_str().unwrap())
+            AttributeDomain::Enum { values } => value.as_str().map_or(false, |value| {
+                values.iter().any(|possible| possible == value)
+            }),
+            AttributeDomain::Boolean => value.is_boolean(),
+            AttributeDomain::Number { min, max, .. } => value.as_f64().map_or(false, |value| {
+                value.is_finite()
+                    && min.map_or(true, |min| value >= min)
+                    && max.map_or(true, |max| value <= max)
+            }),
+            AttributeDomain::Integer { min, max, .. } => value.as_i64().map_or(false, |value| {
+                min.map_or(true, |min| value >= min) && max.map_or(true, |max| value <= max)
+            }),
+        }
+    }
+
+    /// Parses a value given as a string, for example in a query parameter.
+    ///
+    /// Returns `None` if the value is not valid for the attribute.
+    pub fn parse_value(&self, value: &str) -> Option<serde_json::Value> {
+        let value = match self {
+            AttributeDomain::Enum { .. } => serde_json::Value::from(value),
+            AttributeDomain::Boolean => serde_json::Value::from(value.parse::<bool>().ok()?),
+            AttributeDomain::Number { .. } => serde_json::Value::from(value.parse::<f64>().ok()?),
+            AttributeDomain::Integer { .. } => serde_json::Value::from(value.parse::<i64>().ok()?),
+        };
+
+        if self.is_valid_value(&value) {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the bounds and the unit of numeric domains.
+    ///
+    /// Returns `None` for domains that aren't numeric.
+    pub fn numeric_range(&self) -> Option<NumericRange> {
+        match self {
+            AttributeDomain::Number { min, max, unit } => Some(NumericRange {
+                min: *min,
+                max: *max,
+                unit: unit.as_ref().map(String::as_str),
+            }),
+            AttributeDomain::Integer { min, max, unit } => Some(NumericRange {
+                min: min.map(|min| min as f64),
+                max: max.map(|max| max as f64),
+                unit: unit.as_ref().map(String::as_str),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Describes the valid values for error messages.
+    ///
+    /// Numeric domains are described by their type, bounds and unit.
+    pub fn describe(&self) -> serde_json::Value {
+        match self {
+            AttributeDomain::Enum { values } => serde_json::json!(values),
+            AttributeDomain::Boolean => serde_json::json!([true, false]),
+            AttributeDomain::Number { .. } | AttributeDomain::Integer { .. } => {
+                serde_json::to_value(self).unwrap_or_default()
             }
-            AttributeDomain::Boolean if value.is_boolean() => true,
-            _ => false,
         }
     }
 }
 
-/// Represents all the attributes the server manages.
-pub static ATTRIBUTES: [AttributeDescription; 13] = [
-    AttributeDescription {
-        name: "wheelchairAccess",
-        domain: AttributeDomain::Enum(&["noSteps", "oneStep", "multipleSteps"]),
-        database_path: &[
-            "properties",
-            "accessibility",
-            "accessibleWith",
-            "wheelchair",
-        ],
-    },
-    AttributeDescription {
-        name: "gender",
-        domain: AttributeDomain::Enum(&["female", "male", "unisex"]),
-        database_path: &["properties", "accessibility", "gender"],
-    },
-    AttributeDescription {
-        name: "facilityType",
-        domain: AttributeDomain::Enum(&["public", "private"]),
-        database_path: &["properties", "accessibility", "facilityType"],
-    },
-    AttributeDescription {
-        name: "key",
-        domain: AttributeDomain::Enum(&["euroKey", "radarKey", "askStaff", "none"]),
-        database_path: &["properties", "accessibility", "key"],
-    },
-    AttributeDescription {
-        name: "fee",
-        domain: AttributeDomain::Boolean,
-        database_path: &["properties", "accesibility", "fee"],
-    },
-    AttributeDescription {
-        name: "spacious",
-        domain: AttributeDomain::Boolean,
-        database_path: &["properties", "accessibility", "spacious"],
-    },
-    AttributeDescription {
-        name: "grabRail",
-        domain: AttributeDomain::Enum(&["both", "left", "right", "none"]),
-        database_path: &["properties", "accessibility", "grabRail"],
-    },
-    AttributeDescription {
-        name: "lateralAccess",
-        domain: AttributeDomain::Boolean,
-        database_path: &["properties", "accessibility", "lateralAccess"],
-    },
-    AttributeDescription {
-        name: "bottomClearance",
-        domain: AttributeDomain::Boolean,
-        database_path: &["properties", "accessibility", "bottomClearance"],
-    },
-    AttributeDescription {
-        name: "sinkInsideCabin",
-        domain: AttributeDomain::Boolean,
-        database_path: &["properties", "accessibility", "sinkInsideCabin"],
-    },
-    AttributeDescription {
-        name: "reachableControls",
-        domain: AttributeDomain::Boolean,
-        database_path: &["properties", "accessibility", "reachableControls"],
-    },
-    AttributeDescription {
-        name: "emergencyCall",
-        domain: AttributeDomain::Boolean,
-        database_path: &["properties", "accessibility", "emergencyCall"],
-    },
-    AttributeDescription {
-        name: "shower",
-        domain: AttributeDomain::Boolean,
-        database_path: &["properties", "accessibility", "shower"],
-    },
-];
+/// The prefix of the database paths of all attributes.
+const ACCESSIBILITY_PATH_PREFIX: &str = "properties.accessibility.";
+
+/// Checks that the attributes form a consistent catalogue.
+///
+/// Returns a description of the first problem found.
+fn validate_catalogue(attributes: &[AttributeDescription]) -> Result<(), String> {
+    let mut names = HashSet::new();
+    let mut paths: Vec<(&String, &String)> = Vec::new();
+
+    for attribute in attributes {
+        if attribute.name.is_empty() {
+            return Err(String::from("An attribute has an empty name."));
+        }
+
+        if !names.insert(&attribute.name) {
+            return Err(format!(
+                "The attribute `{}` is defined twice.",
+                attribute.name
+            ));
+        }
+
+        if !attribute
+            .database_path
+            .starts_with(ACCESSIBILITY_PATH_PREFIX)
+            || attribute
+                .database_path
+                .split('.')
+                .any(|component| component.is_empty() || component.starts_with('$'))
+        {
+            return Err(format!(
+                "The database path `{}` of the attribute `{}` must be a dotted path below `{}`.",
+                attribute.database_path,
+                attribute.name,
+                ACCESSIBILITY_PATH_PREFIX.trim_end_matches('.')
+            ));
+        }
+
+        // Attributes on the same path or on paths within each other would overwrite each other's values.
+        if let Some(other) = paths
+            .iter()
+            .find(|(path, _)| paths_overlap(path, &attribute.database_path))
+        {
+            return Err(format!(
+                "The database path `{}` of the attribute `{}` overlaps the path `{}` of the attribute `{}`.",
+                attribute.database_path, attribute.name, other.0, other.1
+            ));
+        }
+
+        paths.push((&attribute.database_path, &attribute.name));
+
+        if let AttributeDomain::Enum { values } = &attribute.domain {
+            if values.is_empty() {
+                return Err(format!(
+                    "The attribute `{}` has no possible values.",
+                    attribute.name
+                ));
+            }
+
+            if values.iter().collect::<HashSet<_>>().len() != values.len() {
+                return Err(format!(
+                    "The attribute `{}` has duplicate values.",
+                    attribute.name
+                ));
+            }
+        }
+
+        if let Some(range) = attribute.domain.numeric_range() {
+            if let (Some(min), Some(max)) = (range.min, range.max) {
+                if min > max {
+                    return Err(format!(
+                        "The minimum of the attribute `{}` is larger than its maximum.",
+                        attribute.name
+                    ));
+                }
+            }
+        }
+
+        if attribute.labels.keys().any(String::is_empty) {
+            return Err(format!(
+                "The attribute `{}` has a label without a language.",
+                attribute.name
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Loads and validates the configured attribute catalogue.
+///
+/// Panics with a description of the problem if the catalogue can't be used.
+fn load_catalogue() -> Vec<AttributeDescription> {
+    let (source, catalogue) = if ATTRIBUTE_CATALOGUE.is_empty() {
+        (
+            String::from("built-in attribute catalogue"),
+            String::from(DEFAULT_CATALOGUE),
+        )
+    } else {
+        let catalogue = std::fs::read_to_string(&*ATTRIBUTE_CATALOGUE).unwrap_or_else(|err| {
+            panic!(
+                "Could not read the attribute catalogue `{}`: {}",
+                *ATTRIBUTE_CATALOGUE, err
+            )
+        });
+
+        (
+            format!("attribute catalogue `{}`", *ATTRIBUTE_CATALOGUE),
+            catalogue,
+        )
+    };
+
+    let attributes: Vec<AttributeDescription> = serde_json::from_str(&catalogue)
+        .unwrap_or_else(|err| panic!("The {} is malformed: {}", source, err));
+
+    if let Err(problem) = validate_catalogue(&attributes) {
+        panic!("The {} is invalid: {}", source, problem);
+    }
+
+    attributes
+}
+
+/// Returns all attributes the server manages.
+#[get("/attributes")]
+pub(super) fn attributes() -> JsonValue {
+    json!({ "result": OperationResult::success, "attributes": &*ATTRIBUTES })
+}
@@ -0,0 +1,136 @@
+//! Aggregates facilities into clusters for map tiles at low zoom levels.
+//!
+//! At low zoom levels a single tile can contain thousands of facilities. Instead of returning all of them,
+//! the tile is divided into a grid of its child tiles and every cell with facilities becomes a single cluster.
+
+use rocket_contrib::databases::mongodb::{coll::options::FindOptions, Document};
+use slippy_map_tilenames::lonlat2tile;
+use std::collections::BTreeMap;
+
+use super::attributes::{AttributeDescription, ATTRIBUTES};
+use crate::configuration::{
+    CLUSTER_BREAKDOWN_ATTRIBUTES, CLUSTER_GRID_DEPTH, CLUSTER_MAX_ZOOM, CLUSTER_MIN_FEATURES,
+};
+
+/// The highest zoom level that slippy map tiles are commonly available in.
+const MAX_ZOOM: u8 = 19;
+
+/// The facilities within one cell of the clustering grid.
+#[derive(Default)]
+struct Cluster {
+    /// The sum of the longitudes of all facilities.
+    lon_sum: f64,
+    /// The sum of the latitudes of all facilities.
+    lat_sum: f64,
+    /// The number of facilities.
+    count: u64,
+    /// The number of facilities per value for every attribute in the breakdown.
+    breakdown: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+/// Determines whether tiles at the zoom level may be answered with clusters.
+pub fn is_cluster_zoom(z: u8) -> bool {
+    z <= *CLUSTER_MAX_ZOOM
+}
+
+/// Determines whether a tile with the given number of facilities has enough of them to be worth clustering.
+pub fn has_cluster_count(count: u64) -> bool {
+    count >= *CLUSTER_MIN_FEATURES
+}
+
+/// Returns the attributes whose values are counted for every cluster.
+fn breakdown_attributes() -> Vec<&'static AttributeDescription> {
+    ATTRIBUTES
+        .iter()
+        .filter(|attribute| {
+            CLUSTER_BREAKDOWN_ATTRIBUTES
+                .split(',')
+                .any(|name| name.trim() == attribute.get_name())
+        })
+        .collect()
+}
+
+/// Returns the options to query the facilities of a tile with, so that only the fields needed for clustering
+/// are loaded.
+pub fn cluster_query_options() -> FindOptions {
+    let mut projection = Document::new();
+    projection.insert("geometry", 1);
+
+    for attribute in breakdown_attributes() {
+        projection.insert(attribute.get_database_path(), 1);
+    }
+
+    let mut options = FindOptions::new();
+    options.projection = Some(projection);
+
+    options
+}
+
+/// Returns the value of an attribute as a key for the breakdown.
+fn breakdown_key(value: Option<serde_json::Value>) -> String {
+    match value {
+        Some(serde_json::Value::String(value)) => value,
+        Some(value) => value.to_string(),
+        None => String::from("unknown"),
+    }
+}
+
+/// Aggregates the facilities in the tile at zoom level `z` into clusters.
+///
+/// The clusters are formed on the grid of the descendants of the tile `CLUSTER_GRID_DEPTH` zoom levels below it.
+/// Every cluster is a GeoJSON point feature at the centroid of its facilities.
+///
+/// The facilities only need to contain the fields queried with `cluster_query_options`.
+pub fn cluster_features(features: &[serde_json::Value], z: u8) -> Vec<serde_json::Value> {
+    let grid_zoom = z.saturating_add(*CLUSTER_GRID_DEPTH).min(MAX_ZOOM);
+    let breakdown_attributes = breakdown_attributes();
+
+    let mut clusters: BTreeMap<(u32, u32), Cluster> = BTreeMap::new();
+
+    for feature in features {
+        let coordinates = &feature["geometry"]["coordinates"];
+        let (lon, lat) = match (coordinates[0].as_f64(), coordinates[1].as_f64()) {
+            (Some(lon), Some(lat)) => (lon, lat),
+            _ => continue,
+        };
+
+        let cluster = clusters
+            .entry(lonlat2tile(lon, lat, grid_zoom))
+            .or_insert_with(Cluster::default);
+
+        cluster.lon_sum += lon;
+        cluster.lat_sum += lat;
+        cluster.count += 1;
+
+        for attribute in &breakdown_attributes {
+            *cluster
+                .breakdown
+                .entry(String::from(attribute.get_name()))
+                .or_insert_with(BTreeMap::new)
+                .entry(breakdown_key(attribute.get_value(feature)))
+                .or_insert(0) += 1;
+        }
+    }
+
+    clusters
+        .into_iter()
+        .map(|((x, y), cluster)| {
+            serde_json::json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "Point",
+                    "coordinates": [
+                        cluster.lon_sum / cluster.count as f64,
+                        cluster.lat_sum / cluster.count as f64
+                    ]
+                },
+                "properties": {
+                    "cluster": true,
+                    "count": cluster.count,
+                    "tile": { "x": x, "y": y, "z": grid_zoom },
+                    "breakdown": cluster.breakdown
+                }
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,196 @@
+//! Handles filtering facilities by the values of their attributes.
+
+use rocket::request::{FromQuery, Query};
+use rocket_contrib::{
+    databases::mongodb::{Bson, Document},
+    json,
+};
+use std::convert::Infallible;
+
+use super::attributes::ATTRIBUTES;
+use crate::error::Error;
+
+/// The attribute values a client asked for in the query string, such as `?wheelchairAccess=noSteps&fee=false`.
+///
+/// The values are only validated when they are converted into a database filter, so that invalid values can
+/// be reported back to the client.
+pub struct AttributeQuery(Vec<(String, String)>);
+
+impl<'q> FromQuery<'q> for AttributeQuery {
+    type Error = Infallible;
+
+    fn from_query(query: Query<'q>) -> Result<AttributeQuery, Infallible> {
+        Ok(AttributeQuery(
+            query.map(|item| item.key_value_decoded()).collect(),
+        ))
+    }
+}
+
+/// How the attribute value of a facility is compared with a value in the query string.
+#[derive(Clone, Copy, PartialEq)]
+enum Comparison {
+    /// `attribute=value`
+    Equal,
+    /// `attribute>value`
+    Greater,
+    /// `attribute>=value`
+    GreaterOrEqual,
+    /// `attribute<value`
+    Less,
+    /// `attribute<=value`
+    LessOrEqual,
+}
+
+impl Comparison {
+    /// Splits a query item into the name of the attribute, the comparison and the value.
+    ///
+    /// The query string `doorWidth>=90` is split into the key `doorWidth>` and the value `90`, while
+    /// `doorWidth>90` has no value at all, so both cases need to be handled.
+    fn split<'a>(key: &'a str, value: &'a str) -> (&'a str, Comparison, &'a str) {
+        if let Some(key) = key.strip_suffix('>') {
+            return (key, Comparison::GreaterOrEqual, value);
+        }
+
+        if let Some(key) = key.strip_suffix('<') {
+            return (key, Comparison::LessOrEqual, value);
+        }
+
+        if value.is_empty() {
+            if let Some(index) = key.find(|c| c == '>' || c == '<') {
+                let comparison = if key[index..].starts_with('>') {
+                    Comparison::Greater
+                } else {
+                    Comparison::Less
+                };
+
+                return (&key[..index], comparison, &key[index + 1..]);
+            }
+        }
+
+        (key, Comparison::Equal, value)
+    }
+
+    /// Returns the query operator for the comparison.
+    fn operator(self) -> &'static str {
+        match self {
+            Comparison::Equal => "$eq",
+            Comparison::Greater => "$gt",
+            Comparison::GreaterOrEqual => "$gte",
+            Comparison::Less => "$lt",
+            Comparison::LessOrEqual => "$lte",
+        }
+    }
+
+    /// Returns the comparison as it is written in the query string.
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Equal => "=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+        }
+    }
+}
+
+/// The conditions for a single attribute.
+#[derive(Default)]
+struct Condition {
+    /// The values of which the attribute must have any.
+    values: Vec<Bson>,
+    /// The range operators such as `$gte` with their operands.
+    ranges: Document,
+}
+
+impl AttributeQuery {
+    /// Converts the requested attribute values into a database filter.
+    ///
+    /// If an attribute is given multiple times, facilities with any of the values match.
+    /// Numeric attributes can also be compared, as in `doorWidth>=90`. All comparisons of an attribute must hold.
+    /// Returns an error response listing every unknown attribute and invalid value.
+    pub fn to_filter(&self) -> Result<Document, Error> {
+        let mut conditions: Vec<(String, Condition)> = Vec::new();
+        let mut unknown_attributes = Vec::new();
+        let mut invalid_values = Vec::new();
+
+        for (key, value) in &self.0 {
+            let (name, comparison, value) = Comparison::split(key, value);
+
+            let attribute = match ATTRIBUTES
+                .iter()
+                .find(|attribute| attribute.get_name() == name)
+            {
+                Some(attribute) => attribute,
+                None => {
+                    unknown_attributes.push(String::from(name));
+                    continue;
+                }
+            };
+
+            let is_comparable = attribute.get_domain().numeric_range().is_some();
+            let value = match attribute.get_domain().parse_value(value) {
+                Some(value) if comparison == Comparison::Equal || is_comparable => {
+                    Bson::from(value)
+                }
+                _ => {
+                    invalid_values.push(json!({
+                        "attribute": name,
+                        "value": if comparison == Comparison::Equal {
+                            String::from(value)
+                        } else {
+                            format!("{}{}", comparison.symbol(), value)
+                        },
+                        "allowedValues": attribute.get_domain().describe()
+                    }));
+                    continue;
+                }
+            };
+
+            let path = attribute.get_database_path();
+            let condition = match conditions
+                .iter_mut()
+                .position(|(existing, _)| existing == &path)
+            {
+                Some(index) => &mut conditions[index].1,
+                None => {
+                    conditions.push((path, Condition::default()));
+                    &mut conditions.last_mut().unwrap().1
+                }
+            };
+
+            if comparison == Comparison::Equal {
+                condition.values.push(value);
+            } else {
+                condition.ranges.insert(comparison.operator(), value);
+            }
+        }
+
+        if !unknown_attributes.is_empty() || !invalid_values.is_empty() {
+            return Err(Error::invalid_request(
+                "The attribute filter contains unknown attributes or invalid values.",
+            )
+            .with_detail("unknownAttributes", unknown_attributes)
+            .with_detail("invalidValues", invalid_values));
+        }
+
+        let mut filter = Document::new();
+        for (path, condition) in conditions {
+            let Condition {
+                mut values,
+                mut ranges,
+            } = condition;
+
+            if values.len() == 1 && ranges.is_empty() {
+                filter.insert(path, values.remove(0));
+            } else {
+                if !values.is_empty() {
+                    ranges.insert("$in", values);
+                }
+
+                filter.insert(path, ranges);
+            }
+        }
+
+        Ok(filter)
+    }
+}
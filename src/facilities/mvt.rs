@@ -0,0 +1,265 @@
+//! Encodes facilities as Mapbox Vector Tiles.
+//!
+//! Refer to https://github.com/mapbox/vector-tile-spec/tree/master/2.1 for the format.
+
+use rocket::{http::RawStr, request::FromParam};
+use std::f64::consts::PI;
+
+use super::attributes::ATTRIBUTES;
+
+/// The number of units along each side of a tile.
+const EXTENT: u32 = 4096;
+
+/// The name of the layer that contains the facilities.
+const LAYER_NAME: &str = "facilities";
+
+/// The name of the layer that contains the clusters of facilities.
+const CLUSTER_LAYER_NAME: &str = "clusters";
+
+/// The properties of a facility that are included in a tile in addition to its attributes.
+const PROPERTIES: [&str; 3] = ["sourceId", "originalId", "name"];
+
+/// A zoom level followed by the `.mvt` extension, as in `/by-tile/<x>/<y>/<z>.mvt`.
+pub struct MvtZoom(pub u8);
+
+impl<'a> FromParam<'a> for MvtZoom {
+    type Error = &'a RawStr;
+
+    fn from_param(param: &'a RawStr) -> Result<MvtZoom, &'a RawStr> {
+        let param_str = param.as_str();
+
+        if !param_str.ends_with(".mvt") {
+            return Err(param);
+        }
+
+        param_str[..param_str.len() - ".mvt".len()]
+            .parse()
+            .map(MvtZoom)
+            .map_err(|_| param)
+    }
+}
+
+/// Writes messages in the protocol buffers wire format.
+#[derive(Default)]
+struct ProtobufWriter(Vec<u8>);
+
+impl ProtobufWriter {
+    /// Writes a variable length integer.
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+
+        self.0.push(value as u8);
+    }
+
+    /// Writes the key of a field.
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.varint(u64::from(field) << 3 | u64::from(wire_type));
+    }
+
+    /// Writes an unsigned integer field.
+    fn uint(&mut self, field: u32, value: u64) {
+        self.key(field, 0);
+        self.varint(value);
+    }
+
+    /// Writes a double field.
+    fn double(&mut self, field: u32, value: f64) {
+        self.key(field, 1);
+        self.0.extend_from_slice(&value.to_bits().to_le_bytes());
+    }
+
+    /// Writes a length delimited field, such as a string or a nested message.
+    fn bytes(&mut self, field: u32, bytes: &[u8]) {
+        self.key(field, 2);
+        self.varint(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+
+    /// Writes a packed repeated field of unsigned integers.
+    fn packed(&mut self, field: u32, values: &[u64]) {
+        let mut packed = ProtobufWriter::default();
+        for &value in values {
+            packed.varint(value);
+        }
+
+        self.bytes(field, &packed.0);
+    }
+}
+
+/// Encodes a signed integer as an unsigned integer, so that small negative numbers stay small.
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Converts a location into the coordinates within the given tile.
+///
+/// This uses the Web Mercator projection, just like the slippy map tiles.
+fn tile_coordinates(lon: f64, lat: f64, x: u32, y: u32, z: u8) -> (i64, i64) {
+    let tiles = f64::from(1u32 << z);
+    let lat = lat.to_radians();
+
+    let global_x = (lon + 180.0) / 360.0 * tiles;
+    let global_y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0 * tiles;
+
+    (
+        ((global_x - f64::from(x)) * f64::from(EXTENT)).round() as i64,
+        ((global_y - f64::from(y)) * f64::from(EXTENT)).round() as i64,
+    )
+}
+
+/// Returns the whitelisted properties of a facility that are included in a tile.
+fn tile_properties(facility: &serde_json::Value) -> Vec<(String, serde_json::Value)> {
+    let mut properties: Vec<_> = PROPERTIES
+        .iter()
+        .filter_map(|&name| {
+            let value = &facility["properties"][name];
+
+            if value.is_null() {
+                None
+            } else {
+                Some((String::from(name), value.clone()))
+            }
+        })
+        .collect();
+
+    for attribute in ATTRIBUTES.iter() {
+        if let Some(value) = attribute.get_value(facility) {
+            properties.push((String::from(attribute.get_name()), value));
+        }
+    }
+
+    properties
+}
+
+/// Returns the properties of a cluster that are included in a tile.
+///
+/// Nested objects such as the breakdown are flattened into properties with dotted names, such as
+/// `breakdown.wheelchairAccess.noSteps`.
+fn cluster_properties(cluster: &serde_json::Value) -> Vec<(String, serde_json::Value)> {
+    fn flatten(
+        prefix: &str,
+        value: &serde_json::Value,
+        properties: &mut Vec<(String, serde_json::Value)>,
+    ) {
+        match value {
+            serde_json::Value::Object(object) => {
+                for (key, value) in object {
+                    let name = if prefix.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", prefix, key)
+                    };
+
+                    flatten(&name, value, properties);
+                }
+            }
+            value => properties.push((String::from(prefix), value.clone())),
+        }
+    }
+
+    let mut properties = Vec::new();
+    flatten("", &cluster["properties"], &mut properties);
+
+    properties
+}
+
+/// Encodes a property value as a `Value` message.
+///
+/// Returns `None` for values that can't be represented in a tile, such as arrays.
+fn encode_value(value: &serde_json::Value) -> Option<Vec<u8>> {
+    let mut message = ProtobufWriter::default();
+
+    match value {
+        serde_json::Value::String(string) => message.bytes(1, string.as_bytes()),
+        serde_json::Value::Number(number) => message.double(3, number.as_f64()?),
+        serde_json::Value::Bool(boolean) => message.uint(7, *boolean as u64),
+        _ => return None,
+    }
+
+    Some(message.0)
+}
+
+/// Returns the index of the element in the list, adding it if necessary.
+fn index_of<T: PartialEq>(list: &mut Vec<T>, element: T) -> u64 {
+    match list.iter().position(|existing| existing == &element) {
+        Some(index) => index as u64,
+        None => {
+            list.push(element);
+            (list.len() - 1) as u64
+        }
+    }
+}
+
+/// Encodes the facilities in the given tile as a Mapbox Vector Tile.
+///
+/// Each facility becomes a point feature in the layer `facilities`.
+/// Facilities without valid coordinates are skipped.
+pub fn encode_tile(x: u32, y: u32, z: u8, facilities: &[serde_json::Value]) -> Vec<u8> {
+    encode_layer(LAYER_NAME, x, y, z, facilities, tile_properties)
+}
+
+/// Encodes the clusters returned by `cluster_features` for the given tile as a Mapbox Vector Tile.
+///
+/// Each cluster becomes a point feature in the layer `clusters`.
+pub fn encode_cluster_tile(x: u32, y: u32, z: u8, clusters: &[serde_json::Value]) -> Vec<u8> {
+    encode_layer(CLUSTER_LAYER_NAME, x, y, z, clusters, cluster_properties)
+}
+
+/// Encodes the features as a tile with a single layer of point features.
+fn encode_layer(
+    name: &str,
+    x: u32,
+    y: u32,
+    z: u8,
+    features: &[serde_json::Value],
+    properties: fn(&serde_json::Value) -> Vec<(String, serde_json::Value)>,
+) -> Vec<u8> {
+    let mut keys: Vec<String> = Vec::new();
+    let mut values: Vec<Vec<u8>> = Vec::new();
+    let mut layer = ProtobufWriter::default();
+
+    layer.uint(15, 2); // version
+    layer.bytes(1, name.as_bytes()); // name
+
+    for facility in features {
+        let coordinates = &facility["geometry"]["coordinates"];
+        let (lon, lat) = match (coordinates[0].as_f64(), coordinates[1].as_f64()) {
+            (Some(lon), Some(lat)) => (lon, lat),
+            _ => continue,
+        };
+        let (tile_x, tile_y) = tile_coordinates(lon, lat, x, y, z);
+
+        let mut tags = Vec::new();
+        for (key, value) in properties(facility) {
+            if let Some(encoded_value) = encode_value(&value) {
+                tags.push(index_of(&mut keys, key));
+                tags.push(index_of(&mut values, encoded_value));
+            }
+        }
+
+        let mut feature = ProtobufWriter::default();
+        feature.packed(2, &tags); // tags
+        feature.uint(3, 1); // type = POINT
+        feature.packed(4, &[(1 << 3) | 1, zigzag(tile_x), zigzag(tile_y)]); // geometry = MoveTo(x, y)
+
+        layer.bytes(2, &feature.0); // features
+    }
+
+    for key in &keys {
+        layer.bytes(3, key.as_bytes()); // keys
+    }
+
+    for value in &values {
+        layer.bytes(4, value); // values
+    }
+
+    layer.uint(5, u64::from(EXTENT)); // extent
+
+    let mut tile = ProtobufWriter::default();
+    tile.bytes(3, &layer.0); // layers
+
+    tile.0
+}
@@ -0,0 +1,201 @@
+//! Handles splitting large query results into pages.
+//!
+//! A client asks for at most `limit` facilities and receives a `nextCursor` along with them.
+//! Passing that cursor to the same query returns the following page.
+
+use rocket_contrib::{
+    databases::mongodb::{bson, coll::options::FindOptions, doc, oid::ObjectId, Document},
+    json,
+    json::JsonValue,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{decode_token, encode_token, OperationResult};
+use crate::{configuration::MAX_PAGE_SIZE, error::Error};
+
+/// The field in which the results of a search by distance contain the distance calculated by the database.
+///
+/// The distance in `properties.distance` is calculated by the backend with a slightly different formula, so pages by
+/// distance continue after the distance in this field instead.
+pub const DATABASE_DISTANCE_FIELD: &str = "databaseDistance";
+
+/// Marks the position in a result set at which the next page starts.
+///
+/// Clients only ever see the encoded form, so the representation may change without breaking them.
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Cursor {
+    /// The database ID of the last facility on the previous page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    after: Option<String>,
+    /// The distance of the last facility on the previous page, as calculated by the database.
+    ///
+    /// This is used for results ordered by distance, whose ties are ordered by ID.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    distance: Option<f64>,
+}
+
+impl Cursor {
+    /// Encodes the cursor into an opaque string.
+    fn encode(&self) -> String {
+        encode_token(self)
+    }
+
+    /// Decodes a cursor that was previously encoded with `encode`.
+    fn decode(encoded: &str) -> Option<Cursor> {
+        decode_token(encoded)
+    }
+}
+
+/// Describes the page of results a client asked for.
+pub struct PageRequest {
+    /// The maximum number of facilities on the page.
+    limit: Option<u64>,
+    /// The position at which the page starts.
+    cursor: Cursor,
+}
+
+impl PageRequest {
+    /// Creates a page request from the `limit` and `cursor` query parameters.
+    ///
+    /// Returns an error if the parameters are invalid.
+    pub fn new(limit: Option<u64>, cursor: Option<String>) -> Result<PageRequest, Error> {
+        if limit == Some(0) {
+            return Err(Error::invalid_request(
+                "The limit must be greater than zero.",
+            ));
+        }
+
+        let cursor = match cursor {
+            Some(cursor) => Cursor::decode(&cursor)
+                .ok_or_else(|| Error::invalid_request("The cursor is invalid."))?,
+            None => Cursor::default(),
+        };
+
+        let limit = match (limit, *MAX_PAGE_SIZE) {
+            (limit, 0) => limit,
+            (Some(limit), max) => Some(limit.min(max)),
+            (None, max) => Some(max),
+        };
+
+        Ok(PageRequest { limit, cursor })
+    }
+
+    /// Restricts the filter to the requested page of results ordered by ID and returns the options to query it with.
+    ///
+    /// Note that one more facility than requested is queried to find out whether there is another page.
+    pub fn restrict(&self, filter: &mut Document) -> Result<FindOptions, Error> {
+        let mut options = FindOptions::new();
+        options.sort = Some(doc! { "_id": 1 });
+        options.limit = self.query_limit();
+
+        if let Some(after) = self.after()? {
+            filter.insert("_id", doc! { "$gt": after });
+        }
+
+        Ok(options)
+    }
+
+    /// Returns the aggregation stages that select the requested page of results ordered by distance.
+    ///
+    /// The results must contain the distance calculated by the database in `DATABASE_DISTANCE_FIELD`.
+    /// Facilities at the same distance are ordered by their ID, so that pages neither repeat nor skip them.
+    /// Note that one more facility than requested is selected to find out whether there is another page.
+    pub fn distance_stages(&self) -> Result<Vec<Document>, Error> {
+        let mut stages = Vec::new();
+
+        if let (Some(distance), Some(after)) = (self.cursor.distance, self.after()?) {
+            stages.push(doc! { "$match": { "$or": [
+                { DATABASE_DISTANCE_FIELD: { "$gt": distance } },
+                { DATABASE_DISTANCE_FIELD: distance, "_id": { "$gt": after } }
+            ] } });
+        }
+
+        stages.push(doc! { "$sort": { DATABASE_DISTANCE_FIELD: 1, "_id": 1 } });
+
+        if let Some(limit) = self.query_limit() {
+            stages.push(doc! { "$limit": limit });
+        }
+
+        Ok(stages)
+    }
+
+    /// Returns the distance in meters at which a page of results ordered by distance starts.
+    pub fn min_distance(&self) -> Option<f64> {
+        self.cursor.distance
+    }
+
+    /// Returns the number of facilities to query for the page, which is one more than requested.
+    fn query_limit(&self) -> Option<i64> {
+        self.limit.map(|limit| limit as i64 + 1)
+    }
+
+    /// Returns the database ID of the last facility on the previous page.
+    fn after(&self) -> Result<Option<ObjectId>, Error> {
+        self.cursor
+            .after
+            .as_ref()
+            .map(|after| ObjectId::with_string(after))
+            .transpose()
+            .map_err(|_| Error::invalid_request("The cursor is invalid."))
+    }
+
+    /// Builds the response for the features that were queried with the options from `restrict`.
+    pub fn respond(&self, features: Vec<serde_json::Value>) -> JsonValue {
+        self.respond_with(features, |features| Cursor {
+            after: last_id(features),
+            ..Cursor::default()
+        })
+    }
+
+    /// Builds the response for the features that were queried with the stages from `distance_stages`.
+    ///
+    /// The distance calculated by the database is only used for the cursor and removed from the features.
+    pub fn respond_by_distance(&self, mut features: Vec<serde_json::Value>) -> JsonValue {
+        let distances: Vec<Option<f64>> = features
+            .iter_mut()
+            .map(|feature| {
+                feature
+                    .as_object_mut()
+                    .and_then(|feature| feature.remove(DATABASE_DISTANCE_FIELD))
+                    .and_then(|distance| distance.as_f64())
+            })
+            .collect();
+
+        self.respond_with(features, |features| Cursor {
+            after: last_id(features),
+            distance: distances.get(features.len() - 1).cloned().flatten(),
+        })
+    }
+
+    /// Builds the response for the queried features, which contain one more facility than requested if there is
+    /// another page.
+    fn respond_with(
+        &self,
+        mut features: Vec<serde_json::Value>,
+        next_cursor: impl FnOnce(&[serde_json::Value]) -> Cursor,
+    ) -> JsonValue {
+        let next_cursor = match self.limit {
+            Some(limit) if features.len() as u64 > limit => {
+                features.truncate(limit as usize);
+
+                Some(next_cursor(&features).encode())
+            }
+            _ => None,
+        };
+
+        json!({
+            "result": OperationResult::success,
+            "features": features,
+            "featureCount": features.len(),
+            "nextCursor": next_cursor
+        })
+    }
+}
+
+/// Returns the database ID of the last feature.
+fn last_id(features: &[serde_json::Value]) -> Option<String> {
+    features
+        .last()
+        .and_then(|feature| feature["properties"]["_id"].as_str())
+        .map(String::from)
+}
@@ -1,132 +1,467 @@
 //! This modules contains all the routes for querying facilities.
 
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use geoutils::Location;
-use rocket::get;
+use rocket::{get, http::ContentType, post, response::Content};
 use rocket_contrib::{
-    databases::mongodb::{bson, doc},
+    databases::mongodb::{bson, coll::options::FindOptions, doc, Bson, Document},
     json,
-    json::JsonValue,
+    json::{Json, JsonValue},
 };
 use slippy_map_tilenames::tile2lonlat;
 
-use super::OperationResult;
-use crate::database::FacilityCollection;
+use super::{
+    area::Area,
+    clustering::{cluster_features, cluster_query_options, has_cluster_count, is_cluster_zoom},
+    filter::AttributeQuery,
+    mvt::{encode_cluster_tile, encode_tile, MvtZoom},
+    pagination::{PageRequest, DATABASE_DISTANCE_FIELD},
+    IDPair, OperationResult,
+};
+use crate::{
+    configuration::{MAX_NEAREST_COUNT, MAX_SEARCH_RESULTS, SEARCH_DISTANCE_SCALE},
+    database::FacilityCollection,
+    error::Error,
+};
+
+/// The number of facilities returned by a nearest search if the client does not specify it.
+const DEFAULT_NEAREST_COUNT: u64 = 10;
+
+/// The number of facilities returned by a text search if the client does not specify it.
+const DEFAULT_SEARCH_COUNT: u64 = 20;
+
+/// The highest zoom level tiles can be requested for.
+///
+/// The number of tiles along each side of the map is `2^z`, which must fit into the tile coordinates.
+const MAX_TILE_ZOOM: u8 = 30;
+
+/// How many times more facilities than requested are ranked by distance in a text search biased by a location.
+///
+/// Facilities with a weaker text match than these candidates are never returned, see the API documentation.
+const SEARCH_CANDIDATE_FACTOR: u64 = 5;
+
+/// Performs the query for the requested page and builds the response.
+fn paginated_query(
+    mut filter: Document,
+    page: PageRequest,
+    collection: &FacilityCollection,
+) -> Result<JsonValue, Error> {
+    let options = page.restrict(&mut filter)?;
+
+    let features: Vec<serde_json::Value> = collection
+        .perform_json_query(Some(filter), Some(options))?
+        .collect();
+
+    Ok(page.respond(features))
+}
+
+/// Creates the filter for all facilities in the specified map tile that match the attribute filters.
+///
+/// Returns an error if the tile does not exist.
+fn tile_filter(x: u32, y: u32, z: u8, filters: &AttributeQuery) -> Result<Document, Error> {
+    if z > MAX_TILE_ZOOM || u64::from(x) >= 1 << z || u64::from(y) >= 1 << z {
+        return Err(Error::invalid_request(format!(
+            "The zoom level must be at most {} and the tile must lie within the map.",
+            MAX_TILE_ZOOM
+        )));
+    }
+
+    let mut filter = filters.to_filter()?;
+
+    let (bottom_left_lon, bottom_left_lat) = tile2lonlat(x, y + 1, z);
+    let (top_right_lon, top_right_lat) = tile2lonlat(x + 1, y, z);
+
+    // Refer to https://docs.mongodb.com/manual/reference/operator/query/box/#op._S_box for more information about the specific query syntax
+    filter.insert(
+        "geometry",
+        doc! { "$geoWithin": { "$box" : [[bottom_left_lon, bottom_left_lat], [top_right_lon, top_right_lat]] } },
+    );
+
+    Ok(filter)
+}
 
 /// Returns all facilities in the specified map tile.
-#[get("/by-tile/<x>/<y>/<z>")]
+///
+/// At low zoom levels, tiles with many facilities are answered with clusters of facilities instead,
+/// unless `cluster` is `false`. Clustered results ignore `limit` and `cursor`.
+/// All other query parameters are used to filter the facilities by their attributes.
+#[get("/by-tile/<x>/<y>/<z>?<limit>&<cursor>&<cluster>&<filters..>")]
+#[allow(clippy::too_many_arguments)]
 pub(super) fn by_tile(
     x: u32,
     y: u32,
     z: u8,
+    limit: Option<u64>,
+    cursor: Option<String>,
+    cluster: Option<bool>,
+    filters: AttributeQuery,
     collection: FacilityCollection,
-) -> Result<JsonValue, JsonValue> {
-    let (bottom_left_lon, bottom_left_lat) = tile2lonlat(x, y + 1, z);
-    let (top_right_lon, top_right_lat) = tile2lonlat(x + 1, y, z);
+) -> Result<JsonValue, Error> {
+    let page = PageRequest::new(limit, cursor)?;
+    let filter = tile_filter(x, y, z, &filters)?;
 
-    let features: Vec<serde_json::Value> = collection.perform_json_query(
-        // Refer to https://docs.mongodb.com/manual/reference/operator/query/box/#op._S_box for more information about the specific query syntax
-        Some(doc! { "geometry": { "$geoWithin": { "$box" : [[bottom_left_lon, bottom_left_lat], [top_right_lon, top_right_lat]] } } }),
-    )
-    .map_err(|_| json!({ "result": OperationResult::failure }))?
-    .collect();
+    // The facilities are only counted first, so that sparse tiles aren't loaded completely.
+    if cluster != Some(false)
+        && is_cluster_zoom(z)
+        && has_cluster_count(collection.count(filter.clone())?)
+    {
+        let features: Vec<serde_json::Value> = collection
+            .find_raw(Some(filter), Some(cluster_query_options()))?
+            .collect();
+        let clusters = cluster_features(&features, z);
 
-    Ok(
-        json!({ "result": OperationResult::success, "features": features, "featureCount": features.len() }),
-    )
+        return Ok(json!({
+            "result": OperationResult::success,
+            "features": clusters,
+            "featureCount": clusters.len(),
+            "nextCursor": null,
+            "clustered": true
+        }));
+    }
+
+    paginated_query(filter, page, &collection)
+}
+
+/// Returns all facilities in the specified map tile as a Mapbox Vector Tile.
+///
+/// This is only tried if the zoom level is not a number, so that it can end in `.mvt`.
+/// Tiles are clustered just like in `by_tile`, so that low zoom levels don't load every facility.
+/// All query parameters are used to filter the facilities by their attributes.
+#[get("/by-tile/<x>/<y>/<z>?<filters..>", rank = 2)]
+pub(super) fn by_tile_mvt(
+    x: u32,
+    y: u32,
+    z: MvtZoom,
+    filters: AttributeQuery,
+    collection: FacilityCollection,
+) -> Result<Content<Vec<u8>>, Error> {
+    let MvtZoom(z) = z;
+    let filter = tile_filter(x, y, z, &filters)?;
+    let content_type = ContentType::new("application", "vnd.mapbox-vector-tile");
+
+    if is_cluster_zoom(z) && has_cluster_count(collection.count(filter.clone())?) {
+        let features: Vec<serde_json::Value> = collection
+            .find_raw(Some(filter), Some(cluster_query_options()))?
+            .collect();
+
+        return Ok(Content(
+            content_type,
+            encode_cluster_tile(x, y, z, &cluster_features(&features, z)),
+        ));
+    }
+
+    let features: Vec<serde_json::Value> =
+        collection.perform_json_query(Some(filter), None)?.collect();
+
+    Ok(Content(content_type, encode_tile(x, y, z, &features)))
+}
+
+/// Returns all facilities within the area sent in the request body.
+///
+/// The area is either a GeoJSON `Polygon` or `MultiPolygon` or a bounding box.
+/// All other query parameters are used to filter the facilities by their attributes.
+#[post(
+    "/within?<limit>&<cursor>&<filters..>",
+    format = "application/json",
+    data = "<area>"
+)]
+pub(super) fn within(
+    area: Json<Area>,
+    limit: Option<u64>,
+    cursor: Option<String>,
+    filters: AttributeQuery,
+    collection: FacilityCollection,
+) -> Result<JsonValue, Error> {
+    let page = PageRequest::new(limit, cursor)?;
+    let mut filter = filters.to_filter()?;
+
+    filter.insert("geometry", area.to_condition()?);
+
+    paginated_query(filter, page, &collection)
 }
 
 /// Returns all facilities in the specified radius around the given coordinates.
 ///
 /// The radius is given in meters.
-#[get("/by-radius/<longitude>/<latitude>/<radius>")]
+/// All other query parameters are used to filter the facilities by their attributes.
+#[get("/by-radius/<longitude>/<latitude>/<radius>?<limit>&<cursor>&<filters..>")]
 pub(super) fn by_radius(
     longitude: f64,
     latitude: f64,
     radius: f64,
+    limit: Option<u64>,
+    cursor: Option<String>,
+    filters: AttributeQuery,
     collection: FacilityCollection,
-) -> Result<JsonValue, JsonValue> {
-    if longitude > 180.0
-        || longitude < -180.0
-        || latitude > 90.0
-        || latitude < -90.0
+) -> Result<JsonValue, Error> {
+    if !(-180.0..=180.0).contains(&longitude)
+        || !(-90.0..=90.0).contains(&latitude)
         || !radius.is_normal() // make sure the radius is a well behaved floating point number
         || radius <= 0.0
     {
-        return Err(
-            json!({ "result": OperationResult::failure, "reason": "The parameters were not in the legal range."}),
-        );
+        return Err(Error::invalid_request(
+            "The parameters were not in the legal range.",
+        ));
     }
 
-    let features: Vec<serde_json::Value> =
-        perform_radius_search(longitude, latitude, radius, &collection)
-            .ok_or_else(|| json!({ "result": OperationResult::failure }))?
-            .collect();
+    let page = PageRequest::new(limit, cursor)?;
+    let filter = filters.to_filter()?;
 
-    Ok(
-        json!({ "result": OperationResult::success, "features": features, "featureCount": features.len() }),
-    )
+    let features: Vec<serde_json::Value> = perform_near_search(
+        longitude,
+        latitude,
+        page.min_distance(),
+        Some(radius),
+        filter,
+        page.distance_stages()?,
+        &collection,
+    )?
+    .collect();
+
+    Ok(page.respond_by_distance(features))
 }
 
 /// Performs a radius search for facilities.
+///
+/// Only facilities that also match the filter are returned.
+/// The results are sorted by their distance to the given coordinates.
 pub fn perform_radius_search(
     longitude: f64,
     latitude: f64,
     radius: f64,
+    filter: Document,
     collection: &FacilityCollection,
-) -> Option<impl Iterator<Item = serde_json::Value>> {
-    if longitude > 180.0
-        || longitude < -180.0
-        || latitude > 90.0
-        || latitude < -90.0
-        || !radius.is_normal() // make sure the radius is a well behaved floating point number
+) -> Result<impl Iterator<Item = serde_json::Value>, Error> {
+    if !radius.is_normal() // make sure the radius is a well behaved floating point number
         || radius <= 0.0
     {
-        return None;
+        return Err(Error::invalid_request(
+            "The parameters were not in the legal range.",
+        ));
+    }
+
+    perform_near_search(
+        longitude,
+        latitude,
+        None,
+        Some(radius),
+        filter,
+        Vec::new(),
+        collection,
+    )
+    .map(|features| features.map(without_database_distance))
+}
+
+/// Searches for the facilities closest to the given coordinates that match the filter.
+///
+/// The results are sorted by their distance to the given coordinates and contain the distance in their properties.
+/// If `min_distance` or `max_distance` are given, only facilities at least or at most that far away in meters are
+/// returned. The `stages` are applied to the results, which contain the distance calculated by the database in
+/// `DATABASE_DISTANCE_FIELD`.
+fn perform_near_search(
+    longitude: f64,
+    latitude: f64,
+    min_distance: Option<f64>,
+    max_distance: Option<f64>,
+    filter: Document,
+    stages: Vec<Document>,
+    collection: &FacilityCollection,
+) -> Result<impl Iterator<Item = serde_json::Value>, Error> {
+    if !(-180.0..=180.0).contains(&longitude) || !(-90.0..=90.0).contains(&latitude) {
+        return Err(Error::invalid_request(
+            "The parameters were not in the legal range.",
+        ));
     }
 
     let search_location = Location::new(latitude, longitude);
 
-    Some(
-        collection
-            .perform_json_query(
-                // Refer to https://docs.mongodb.com/manual/reference/operator/query/nearSphere/
-                Some(doc! {
-                    "geometry": {
-                        "$nearSphere": {
-                            "$geometry": {
-                                "type": "Point",
-                                "coordinates": [longitude, latitude]
-                            },
-                            "$maxDistance": radius
-                        }
-                    }
-                }),
-            )
-            .ok()?
-            // Add distance to the results and filter the results out where adding the distance is impossible
-            .filter_map(move |mut val| {
+    // Refer to https://docs.mongodb.com/manual/reference/operator/aggregation/geoNear/
+    let mut geo_near = doc! {
+        "near": {
+            "type": "Point",
+            "coordinates": [longitude, latitude]
+        },
+        "key": "geometry",
+        "distanceField": DATABASE_DISTANCE_FIELD,
+        "spherical": true,
+        "query": filter
+    };
+
+    if let Some(min_distance) = min_distance {
+        geo_near.insert("minDistance", min_distance);
+    }
+
+    if let Some(max_distance) = max_distance {
+        geo_near.insert("maxDistance", max_distance);
+    }
+
+    let mut pipeline = vec![doc! { "$geoNear": geo_near }];
+    pipeline.extend(stages);
+
+    Ok(collection
+        .perform_json_aggregation(pipeline)?
+        // Add distance to the results and filter the results out where adding the distance is impossible
+        .filter_map(move |mut val| {
+            let coords = val.get("geometry")?.get("coordinates")?;
+            let (lat, lon) = (coords.get(1)?.as_f64()?, coords.get(0)?.as_f64()?);
+            let facility_location = Location::new(lat, lon);
+            let distance = search_location
+                .distance_to(&facility_location)
+                .unwrap_or_else(|_| search_location.haversine_distance_to(&facility_location));
+
+            if let Some(props) = val
+                .as_object_mut()?
+                .get_mut("properties")
+                .and_then(|props| props.as_object_mut())
+            {
+                props.insert(String::from("distance"), distance.into());
+            } else {
+                val.as_object_mut()?.insert(
+                    String::from("properties"),
+                    serde_json::json!({ "distance": distance }),
+                );
+            }
+
+            Some(val)
+        }))
+}
+
+/// Removes the distance calculated by the database from a feature found by `perform_near_search`.
+fn without_database_distance(mut feature: serde_json::Value) -> serde_json::Value {
+    if let Some(feature) = feature.as_object_mut() {
+        feature.remove(DATABASE_DISTANCE_FIELD);
+    }
+
+    feature
+}
+
+/// Returns the `count` facilities closest to the given coordinates, sorted by distance.
+///
+/// If `maxDistance` is given, only facilities within that distance in meters are returned.
+/// All other query parameters are used to filter the facilities by their attributes.
+#[get("/nearest/<longitude>/<latitude>?<count>&<maxDistance>&<filters..>")]
+#[allow(non_snake_case)]
+pub(super) fn nearest(
+    longitude: f64,
+    latitude: f64,
+    count: Option<u64>,
+    maxDistance: Option<f64>,
+    filters: AttributeQuery,
+    collection: FacilityCollection,
+) -> Result<JsonValue, Error> {
+    let count = count.unwrap_or(DEFAULT_NEAREST_COUNT);
+
+    if count == 0
+        || count > *MAX_NEAREST_COUNT
+        || maxDistance.map_or(false, |max_distance| {
+            !max_distance.is_normal() || max_distance <= 0.0
+        })
+    {
+        return Err(Error::invalid_request(
+            "The parameters were not in the legal range.",
+        ));
+    }
+
+    let filter = filters.to_filter()?;
+
+    let features: Vec<serde_json::Value> = perform_near_search(
+        longitude,
+        latitude,
+        None,
+        maxDistance,
+        filter,
+        vec![doc! { "$limit": count as i64 }],
+        &collection,
+    )?
+    .map(without_database_distance)
+    .collect();
+
+    Ok(
+        json!({ "result": OperationResult::success, "features": features, "featureCount": features.len() }),
+    )
+}
+
+/// Searches for facilities by their name and address.
+///
+/// The results are ranked by their relevance. If `lat` and `lon` are given, facilities closer to that location
+/// are ranked higher and contain their distance in their properties.
+/// All other query parameters are used to filter the facilities by their attributes.
+#[get("/search?<q>&<lat>&<lon>&<limit>&<filters..>")]
+pub(super) fn search(
+    q: String,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    limit: Option<u64>,
+    filters: AttributeQuery,
+    collection: FacilityCollection,
+) -> Result<JsonValue, Error> {
+    let limit = limit.unwrap_or(DEFAULT_SEARCH_COUNT);
+
+    let location = match (lat, lon) {
+        (Some(lat), Some(lon))
+            if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon) =>
+        {
+            Some(Location::new(lat, lon))
+        }
+        (None, None) => None,
+        _ => {
+            return Err(Error::invalid_request(
+                "The parameters were not in the legal range.",
+            ))
+        }
+    };
+
+    if q.trim().is_empty() || limit == 0 || limit > *MAX_SEARCH_RESULTS {
+        return Err(Error::invalid_request(
+            "The parameters were not in the legal range.",
+        ));
+    }
+
+    let mut filter = filters.to_filter()?;
+    filter.insert("$text", doc! { "$search": q });
+
+    let mut options = FindOptions::new();
+    options.projection = Some(doc! { "textScore": { "$meta": "textScore" } });
+    options.sort = Some(doc! { "textScore": { "$meta": "textScore" } });
+    options.limit = Some(match location {
+        Some(_) => (limit * SEARCH_CANDIDATE_FACTOR) as i64,
+        None => limit as i64,
+    });
+
+    let mut ranked: Vec<(f64, serde_json::Value)> = collection
+        .perform_json_query(Some(filter), Some(options))?
+        .filter_map(|mut val| {
+            let relevance = val.as_object_mut()?.remove("textScore")?.as_f64()?;
+            let mut rank = relevance;
+
+            if let Some(location) = &location {
                 let coords = val.get("geometry")?.get("coordinates")?;
-                let (lat, lon) = (coords.get(1)?.as_f64()?, coords.get(0)?.as_f64()?);
-                let facility_location = Location::new(lat, lon);
-                let distance = search_location
+                let facility_location =
+                    Location::new(coords.get(1)?.as_f64()?, coords.get(0)?.as_f64()?);
+                let distance = location
                     .distance_to(&facility_location)
-                    .unwrap_or_else(|_| search_location.haversine_distance_to(&facility_location));
-
-                if let Some(props) = val
-                    .as_object_mut()?
-                    .get_mut("properties")
-                    .and_then(|props| props.as_object_mut())
-                {
-                    props.insert(String::from("distance"), distance.into());
-                } else {
-                    val.as_object_mut()?.insert(
-                        String::from("properties"),
-                        serde_json::json!({ "distance": distance }),
-                    );
-                }
-
-                Some(val)
-            }),
+                    .unwrap_or_else(|_| location.haversine_distance_to(&facility_location));
+
+                rank = relevance / (1.0 + distance / *SEARCH_DISTANCE_SCALE);
+                val["properties"]["distance"] = distance.into();
+            }
+
+            val["properties"]["relevance"] = relevance.into();
+
+            Some((rank, val))
+        })
+        .collect();
+
+    ranked.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+
+    let features: Vec<serde_json::Value> = ranked
+        .into_iter()
+        .take(limit as usize)
+        .map(|(_, val)| val)
+        .collect();
+
+    Ok(
+        json!({ "result": OperationResult::success, "features": features, "featureCount": features.len() }),
     )
 }
 
@@ -137,54 +472,119 @@ pub(super) fn by_id(
     sourceId: String,
     originalId: String,
     collection: FacilityCollection,
-) -> Result<JsonValue, JsonValue> {
+) -> Result<JsonValue, Error> {
     collection
-        .perform_json_query(Some(
-            doc! { "properties.sourceId": sourceId, "properties.originalId": originalId },
-        ))
-        .map_err(|_| json!({ "result": OperationResult::failure }))?
+        .perform_json_query(
+            Some(doc! { "properties.sourceId": sourceId, "properties.originalId": originalId }),
+            None,
+        )?
         .next()
         .map(|val| json!({ "result": OperationResult::success, "features": [json!(val)], "featureCount": 1 }))
-        .ok_or_else(|| json!({ "result": OperationResult::success, "features": [], "featureCount": 0 }))
+        .ok_or_else(|| Error::not_found("The facility does not exist."))
 }
 
-/// Returns all facilities in the specified source.
-#[get("/by-source-id/<sourceId>")]
+/// Returns all changes of the facility with the given ID in the order they were made.
+#[get("/history/<sourceId>/<originalId>")]
 #[allow(non_snake_case)]
-pub(super) fn by_source_id(
+pub(super) fn history(
     sourceId: String,
+    originalId: String,
     collection: FacilityCollection,
-) -> Result<JsonValue, JsonValue> {
-    let features: Vec<serde_json::Value> = collection
-        .perform_json_query(Some(doc! { "properties.sourceId": sourceId }))
-        .map_err(|_| json!({ "result": OperationResult::failure }))?
+) -> Result<JsonValue, Error> {
+    let changes: Vec<serde_json::Value> = collection
+        .history(IDPair {
+            sourceId,
+            originalId,
+        })?
         .collect();
 
     Ok(
-        json!({ "result": OperationResult::success, "features": features, "featureCount": features.len() }),
+        json!({ "result": OperationResult::success, "changes": changes, "changeCount": changes.len() }),
+    )
+}
+
+/// Returns all facilities in the specified source.
+#[get("/by-source-id/<sourceId>?<limit>&<cursor>")]
+#[allow(non_snake_case)]
+pub(super) fn by_source_id(
+    sourceId: String,
+    limit: Option<u64>,
+    cursor: Option<String>,
+    collection: FacilityCollection,
+) -> Result<JsonValue, Error> {
+    let page = PageRequest::new(limit, cursor)?;
+
+    paginated_query(doc! { "properties.sourceId": sourceId }, page, &collection)
+}
+
+/// Parses a timestamp given by a client.
+///
+/// Accepts RFC 3339 timestamps (`2019-08-13T12:34:56.789+02:00`), ISO 8601 timestamps without a time zone
+/// which are interpreted as UTC (`2019-08-13 12:34:56.789`, `2019-08-13T12:34:56`, `2019-08-13`) and the number
+/// of milliseconds since the Unix epoch followed by `ms` (`1565699696789ms`). Plain numbers are rejected, since
+/// they could just as well be seconds or a date without separators.
+pub fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    if let Some(milliseconds) = timestamp
+        .strip_suffix("ms")
+        .and_then(|milliseconds| milliseconds.parse::<i64>().ok())
+    {
+        return Utc
+            .timestamp_opt(
+                milliseconds.div_euclid(1000),
+                (milliseconds.rem_euclid(1000) * 1_000_000) as u32,
+            )
+            .single();
+    }
+
+    if let Ok(date) = DateTime::parse_from_rfc3339(timestamp) {
+        return Some(date.with_timezone(&Utc));
+    }
+
+    // Timestamps without time zone, including the format `lastUpdated` was stored in previously.
+    let timestamp = timestamp.trim_end_matches(" UTC");
+
+    [
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(timestamp, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(timestamp, "%Y-%m-%d")
+            .ok()
+            .map(|date| date.and_hms(0, 0, 0))
+    })
+    .map(|date| Utc.from_utc_datetime(&date))
+}
+
+/// Creates the error for a timestamp that `parse_timestamp` could not parse.
+pub fn invalid_timestamp() -> Error {
+    Error::invalid_request(
+        "The timestamp must be an RFC 3339 or ISO 8601 date or the number of milliseconds since the Unix epoch followed by `ms`.",
     )
 }
 
 /// Returns all facilities that have been updated since the given timestamp.
-#[get("/updated-since/<timestamp>?<source_id>")]
+#[get("/updated-since/<timestamp>?<source_id>&<limit>&<cursor>")]
 #[allow(non_snake_case)]
 pub(super) fn updated_since(
     timestamp: String,
     source_id: Option<String>,
+    limit: Option<u64>,
+    cursor: Option<String>,
     collection: FacilityCollection,
-) -> Result<JsonValue, JsonValue> {
-    let mut query = doc! { "lastUpdated": { "$gte": timestamp } };
+) -> Result<JsonValue, Error> {
+    let page = PageRequest::new(limit, cursor)?;
+
+    let timestamp = parse_timestamp(&timestamp).ok_or_else(invalid_timestamp)?;
+
+    let mut query = doc! { "lastUpdated": { "$gte": Bson::UtcDatetime(timestamp) } };
 
     if let Some(source_id) = source_id {
         query.insert("properties.sourceId", source_id);
     }
 
-    let features: Vec<serde_json::Value> = collection
-        .perform_json_query(Some(query))
-        .map_err(|_| json!({ "result": OperationResult::failure }))?
-        .collect();
-
-    Ok(
-        json!({ "result": OperationResult::success, "features": features, "featureCount": features.len() }),
-    )
+    paginated_query(query, page, &collection)
 }
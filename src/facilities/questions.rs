@@ -1,14 +1,22 @@
 //! Handles the generation and answering of questions regarding facilities.
 #![allow(non_snake_case)]
 
-use serde::Serialize;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
 use uuid::Uuid;
 
 use crate::images::ImageLabel;
 
 use crate::{
-    facilities::attributes::ATTRIBUTES,
-    images::{self, ALL_IMAGE_LABELS},
+    configuration::{ADD_IMAGE_QUESTION_PRIORITY, LABEL_IMAGE_QUESTION_PRIORITY},
+    facilities::{
+        attributes::ATTRIBUTES,
+        decode_token, encode_token,
+        verification::{confidence, needs_verification},
+        IDPair,
+    },
+    images::{self, ImageSize, ALL_IMAGE_LABELS},
 };
 
 /// Represents a question to ask the user.
@@ -28,59 +36,219 @@ pub enum Question {
         imageLabel: ImageLabel,
     },
     /// Asks the user about an attribute.
-    askAttribute { attribute: String },
+    ///
+    /// For numeric attributes the bounds and the unit of the value are included.
+    askAttribute {
+        attribute: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        min: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        unit: Option<String>,
+    },
     /// Asks the user to verify the value of an attribute.
+    ///
+    /// For numeric attributes the unit of the value is included.
     verifyAttribute {
         attribute: String,
         value: serde_json::Value,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        unit: Option<String>,
     },
 }
 
-/// Generates questions about a facility.
-pub fn generate_facility_questions(facility: &serde_json::Value) -> Vec<Question> {
+/// What a question is about.
+///
+/// Asking about an attribute and verifying it share the same subject, just like labeling an image and verifying its
+/// label.
+#[derive(Serialize, Deserialize, Clone)]
+#[allow(non_camel_case_types)]
+pub enum QuestionSubject {
+    /// The value of the attribute with the given name.
+    attribute(String),
+    /// An image with the given label that the facility doesn't have yet.
+    addImage(ImageLabel),
+    /// The label of the image with the given URL.
+    image(String),
+}
+
+impl QuestionSubject {
+    /// Returns a key that identifies the subject within its facility.
+    ///
+    /// The key can be used as a field name in the database.
+    pub fn key(&self) -> String {
+        let key = match self {
+            QuestionSubject::attribute(attribute) => format!("attribute:{}", attribute),
+            QuestionSubject::addImage(imageLabel) => format!(
+                "addImage:{}",
+                serde_json::json!(imageLabel).as_str().unwrap_or("")
+            ),
+            QuestionSubject::image(imageURL) => format!("image:{}", imageURL),
+        };
+
+        // Field names in the database must not contain dots or start with a dollar sign.
+        key.replace('%', "%25")
+            .replace('.', "%2E")
+            .replace('$', "%24")
+    }
+}
+
+/// Identifies a question about a facility.
+///
+/// Clients only ever see the encoded form, so the representation may change without breaking them.
+#[derive(Serialize, Deserialize)]
+pub struct QuestionID {
+    /// The facility the question is about.
+    pub id: IDPair,
+    /// What the question is about.
+    pub subject: QuestionSubject,
+}
+
+impl QuestionID {
+    /// Encodes the question ID into an opaque string.
+    pub fn encode(&self) -> String {
+        encode_token(self)
+    }
+
+    /// Decodes a question ID that was previously encoded with `encode`.
+    pub fn decode(encoded: &str) -> Option<QuestionID> {
+        decode_token(encoded)
+    }
+}
+
+/// A question along with how useful it is to ask it.
+#[derive(Serialize)]
+pub struct ScoredQuestion {
+    /// The ID of the question, which is used to answer it.
+    pub id: String,
+    /// The question itself.
+    #[serde(flatten)]
+    pub question: Question,
+    /// How useful it is to ask the question. Questions with a higher score are more useful.
+    pub score: f64,
+}
+
+impl Question {
+    /// Returns what the question is about.
+    pub fn subject(&self) -> QuestionSubject {
+        match self {
+            Question::askAttribute { attribute, .. }
+            | Question::verifyAttribute { attribute, .. } => {
+                QuestionSubject::attribute(attribute.clone())
+            }
+            Question::addImage { imageLabel } => QuestionSubject::addImage(imageLabel.clone()),
+            Question::labelImage { imageURL } | Question::verifyLabel { imageURL, .. } => {
+                QuestionSubject::image(imageURL.clone())
+            }
+        }
+    }
+}
+
+/// Generates questions about a facility, the most useful questions first.
+///
+/// The score of a question is the priority of the question, multiplied by how uncertain we are about the answer and
+/// divided by the number of times users were already asked it plus one. At most `max_questions` are returned.
+pub fn generate_facility_questions(
+    id: &IDPair,
+    facility: &serde_json::Value,
+    max_questions: Option<usize>,
+) -> Vec<ScoredQuestion> {
     let mut questions = Vec::new();
 
-    generate_attribute_questions(&facility, &mut questions);
-    generate_image_questions(&facility, &mut questions);
+    generate_attribute_questions(facility, &mut questions);
+    generate_image_questions(facility, &mut questions);
+
+    let mut questions: Vec<ScoredQuestion> = questions
+        .into_iter()
+        .map(|(question, score)| {
+            let subject = question.subject();
+            let times_asked = facility["properties"]["questionsAsked"][subject.key()]
+                .as_f64()
+                .unwrap_or(0.0);
+
+            ScoredQuestion {
+                id: QuestionID {
+                    id: id.clone(),
+                    subject,
+                }
+                .encode(),
+                question,
+                score: score / (1.0 + times_asked.max(0.0)),
+            }
+        })
+        .collect();
+
+    // The sort is stable, so questions with the same score stay in the order they were generated in.
+    questions.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+
+    if let Some(max_questions) = max_questions {
+        questions.truncate(max_questions);
+    }
 
     questions
 }
 
-/// Generates all questions regarding attributes for the given facility.
-fn generate_attribute_questions(facility: &serde_json::Value, questions: &mut Vec<Question>) {
-    for attribute in &ATTRIBUTES {
+/// Generates all questions regarding attributes for the given facility along with their scores.
+///
+/// Questions about attributes with a higher priority come first.
+/// Attributes whose values we are confident in are not asked about.
+fn generate_attribute_questions(
+    facility: &serde_json::Value,
+    questions: &mut Vec<(Question, f64)>,
+) {
+    let now = Utc::now();
+    let mut attributes: Vec<_> = ATTRIBUTES.iter().collect();
+    attributes.sort_by_key(|attribute| Reverse(attribute.get_question_priority()));
+
+    for attribute in attributes {
         let name = attribute.get_name();
-        let value = attribute.get_value(&facility);
-
-        if let Some(verified_attributes) = facility["properties"]["verifiedAttributes"].as_array() {
-            if verified_attributes
-                .iter()
-                .any(|attribute| attribute.as_str().unwrap_or("") == name)
-            {
-                continue;
-            }
+        let value = attribute.get_value(facility);
+        let range = attribute.get_domain().numeric_range();
+        let unit = range
+            .as_ref()
+            .and_then(|range| range.unit)
+            .map(String::from);
+        let priority = f64::from(attribute.get_question_priority().max(0));
+
+        if value.is_some() && !needs_verification(attribute, facility, now) {
+            continue;
         }
 
         if let Some(value) = value {
-            questions.push(Question::verifyAttribute {
-                attribute: String::from(name),
-                value,
-            })
+            questions.push((
+                Question::verifyAttribute {
+                    attribute: String::from(name),
+                    value,
+                    unit,
+                },
+                priority * (1.0 - confidence(attribute, facility, now)),
+            ))
         } else {
-            questions.push(Question::askAttribute {
-                attribute: String::from(name),
-            })
+            questions.push((
+                Question::askAttribute {
+                    attribute: String::from(name),
+                    min: range.as_ref().and_then(|range| range.min),
+                    max: range.as_ref().and_then(|range| range.max),
+                    unit,
+                },
+                priority,
+            ))
         }
     }
 }
 
-/// Generates all questions regarding images for the given facility.
-fn generate_image_questions(facility: &serde_json::Value, questions: &mut Vec<Question>) {
+/// Generates all questions regarding images for the given facility along with their scores.
+///
+/// Labels that are not verified yet are only half as uncertain as images without a label.
+fn generate_image_questions(facility: &serde_json::Value, questions: &mut Vec<(Question, f64)>) {
     let empty_list = Vec::new();
+    let add_image_priority = f64::from((*ADD_IMAGE_QUESTION_PRIORITY).max(0));
+    let label_image_priority = f64::from((*LABEL_IMAGE_QUESTION_PRIORITY).max(0));
 
     let images = facility["properties"]["images"]
         .as_array()
-        .unwrap_or_else(|| &empty_list);
+        .unwrap_or(&empty_list);
 
     let mut unused_labels = (*ALL_IMAGE_LABELS).clone();
 
@@ -89,24 +257,27 @@ fn generate_image_questions(facility: &serde_json::Value, questions: &mut Vec<Qu
             let url = image["url"]
                 .as_str()
                 .map(|url| url.to_string())
-                .unwrap_or_else(|| images::url_from_id(&id));
+                .unwrap_or_else(|| images::url_from_id(&id, ImageSize::full));
 
             if let Ok(label) = serde_json::from_value::<ImageLabel>(image["label"].clone()) {
                 unused_labels.remove(&label);
 
                 if image["labelVerified"].as_bool() != Some(true) {
-                    questions.push(Question::verifyLabel {
-                        imageURL: url,
-                        imageLabel: label,
-                    });
+                    questions.push((
+                        Question::verifyLabel {
+                            imageURL: url,
+                            imageLabel: label,
+                        },
+                        label_image_priority * 0.5,
+                    ));
                 }
             } else {
-                questions.push(Question::labelImage { imageURL: url });
+                questions.push((Question::labelImage { imageURL: url }, label_image_priority));
             }
         }
     }
 
     for label in unused_labels {
-        questions.push(Question::addImage { imageLabel: label })
+        questions.push((Question::addImage { imageLabel: label }, add_image_priority))
     }
 }
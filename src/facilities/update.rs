@@ -1,6 +1,9 @@
 //! Contains routes for updating facility data.
 
+pub(super) mod answer;
 pub(super) mod comments;
+pub(super) mod dispute_attribute;
+pub(super) mod revert;
 pub(super) mod set_facility;
 pub(super) mod verify_attributes;
 pub(super) mod will_visit;
@@ -0,0 +1,240 @@
+//! Handles answers to the questions returned by a will-visit request.
+
+use chrono::Utc;
+use rocket::post;
+use rocket_contrib::{
+    databases::mongodb::{bson, doc, Bson, Document},
+    json,
+    json::{Json, JsonValue},
+};
+use serde::Deserialize;
+
+use super::{IDPair, OperationResult};
+use crate::{
+    database::{ChangeOperation, FacilityCollection},
+    error::Error,
+    facilities::{
+        attributes::{AttributeDescription, ATTRIBUTES},
+        questions::{QuestionID, QuestionSubject},
+        verification::{push_vote, vote, votes_path},
+        MinimalFacilityData,
+    },
+    images::{self, ImageLabel, ALL_IMAGE_LABELS},
+};
+
+/// The answer to a single question.
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+pub(in crate::facilities) struct Answer {
+    /// The ID of the answered question.
+    questionId: String,
+    /// The value of the attribute, for questions about attributes.
+    value: Option<serde_json::Value>,
+    /// The label of the image, for questions about images.
+    imageLabel: Option<ImageLabel>,
+}
+
+/// The data sent to answer questions.
+#[derive(Deserialize)]
+pub(in crate::facilities) struct AnswerData {
+    /// The answers to the questions.
+    answers: Vec<Answer>,
+    /// The latitude of the facility.
+    ///
+    /// This is only needed if the facility is not stored on the server yet.
+    lat: Option<f64>,
+    /// The longitude of the facility.
+    ///
+    /// This is only needed if the facility is not stored on the server yet.
+    lon: Option<f64>,
+}
+
+/// Answers multiple questions at once.
+///
+/// Every answer is handled on its own, so the result contains the result of every answer in the same order.
+#[post("/answer", format = "application/json", data = "<data>")]
+pub(in crate::facilities) fn answer(
+    data: Json<AnswerData>,
+    collection: FacilityCollection,
+) -> JsonValue {
+    let AnswerData { answers, lat, lon } = data.into_inner();
+
+    let location = match (lat, lon) {
+        (Some(lat), Some(lon)) => Some((lat, lon)),
+        _ => None,
+    };
+
+    let results: Vec<JsonValue> = answers
+        .into_iter()
+        .map(|answer| {
+            let mut result = match handle_answer(&answer, location, &collection) {
+                Ok(()) => json!({ "result": OperationResult::success }),
+                Err(error) => error.to_result(),
+            };
+            result["questionId"] = serde_json::Value::from(answer.questionId);
+
+            result
+        })
+        .collect();
+
+    json!({ "result": OperationResult::success, "results": results })
+}
+
+/// Creates the error for an answer to a facility that is not stored on the server without its location.
+fn missing_location() -> Error {
+    Error::invalid_request(
+        "The facility is not stored on the server, so `lat` and `lon` are required.",
+    )
+}
+
+/// Dispatches an answer according to the subject of its question.
+fn handle_answer(
+    answer: &Answer,
+    location: Option<(f64, f64)>,
+    collection: &FacilityCollection,
+) -> Result<(), Error> {
+    let QuestionID { id, subject } = QuestionID::decode(&answer.questionId)
+        .ok_or_else(|| Error::invalid_request("The question ID is invalid."))?;
+
+    let facility = collection.by_id(id.clone())?;
+
+    // Facilities that are stored on the server already know their location.
+    let location = facility
+        .as_ref()
+        .and_then(|facility| {
+            let coordinates = &facility["geometry"]["coordinates"];
+            Some((coordinates[1].as_f64()?, coordinates[0].as_f64()?))
+        })
+        .or(location);
+    let facility_data = location.map(|(lat, lon)| MinimalFacilityData {
+        sourceId: id.sourceId.clone(),
+        originalId: id.originalId.clone(),
+        lat,
+        lon,
+    });
+
+    match subject {
+        QuestionSubject::attribute(name) => {
+            let attribute = ATTRIBUTES
+                .iter()
+                .find(|attribute| attribute.get_name() == name)
+                .ok_or_else(|| Error::invalid_request("Unknown attribute."))?;
+            let value = answer.value.as_ref().ok_or_else(|| {
+                Error::invalid_request("Questions about attributes are answered with a `value`.")
+            })?;
+
+            answer_attribute(
+                id,
+                attribute,
+                value,
+                facility.as_ref(),
+                facility_data,
+                collection,
+            )
+        }
+        QuestionSubject::image(image_url) => {
+            let label = answer.imageLabel.as_ref().ok_or_else(|| {
+                Error::invalid_request("Questions about images are answered with an `imageLabel`.")
+            })?;
+
+            answer_image_label(
+                id,
+                image_url,
+                label,
+                facility.as_ref(),
+                facility_data,
+                collection,
+            )
+        }
+        QuestionSubject::addImage(_) => Err(Error::invalid_request(
+            "Images can't be added with answers. Upload them with `/images/upload` instead.",
+        )),
+    }
+}
+
+/// Answers a question about an attribute.
+///
+/// Answering with the current value verifies it. Any other value replaces it.
+fn answer_attribute(
+    id: IDPair,
+    attribute: &AttributeDescription,
+    value: &serde_json::Value,
+    facility: Option<&serde_json::Value>,
+    facility_data: Option<MinimalFacilityData>,
+    collection: &FacilityCollection,
+) -> Result<(), Error> {
+    if !attribute.get_domain().is_valid_value(value) {
+        return Err(
+            Error::invalid_request("The value is not valid for the attribute.")
+                .with_detail("allowedValues", attribute.get_domain().describe()),
+        );
+    }
+
+    if facility.is_none() && facility_data.is_none() {
+        return Err(missing_location());
+    }
+
+    let current_value = facility.and_then(|facility| attribute.get_value(facility));
+
+    let mut update = Document::new();
+    let mut push_document = Document::new();
+    push_document.insert(votes_path(attribute), push_vote(vote(value, Utc::now())));
+    update.insert("$push", push_document);
+
+    let operation = if current_value.as_ref() == Some(value) {
+        ChangeOperation::verifyAttributes
+    } else {
+        let mut set_document = Document::new();
+        set_document.insert(attribute.get_database_path(), Bson::from(value.clone()));
+        update.insert("$set", set_document);
+
+        ChangeOperation::setFacility
+    };
+
+    collection.find_one_and_update(
+        doc! { "properties.sourceId": id.sourceId, "properties.originalId": id.originalId },
+        update,
+        facility_data,
+        operation,
+    )?;
+
+    Ok(())
+}
+
+/// Answers a question about the label of an image.
+///
+/// Answering with the current label verifies it. Any other label replaces it.
+fn answer_image_label(
+    id: IDPair,
+    image_url: String,
+    label: &ImageLabel,
+    facility: Option<&serde_json::Value>,
+    facility_data: Option<MinimalFacilityData>,
+    collection: &FacilityCollection,
+) -> Result<(), Error> {
+    if !ALL_IMAGE_LABELS.contains(label) {
+        return Err(Error::invalid_request("Unknown image label."));
+    }
+
+    let label_name = json!(label).as_str().unwrap().to_string();
+    let has_label = facility
+        .and_then(|facility| facility["properties"]["images"].as_array())
+        .map_or(false, |images| {
+            images.iter().any(|image| {
+                image["url"] == image_url.as_str() && image["label"] == label_name.as_str()
+            })
+        });
+
+    if has_label {
+        images::verify_label(collection, id, image_url, &label_name)?
+            .ok_or_else(|| Error::not_found("The image does not exist."))?;
+
+        return Ok(());
+    }
+
+    let facility_data = facility_data.ok_or_else(missing_location)?;
+
+    images::label_image(collection, facility_data, image_url, label)?;
+
+    Ok(())
+}
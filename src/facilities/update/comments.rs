@@ -11,7 +11,8 @@ use serde::Deserialize;
 use uuid::Uuid;
 
 use crate::{
-    database::FacilityCollection,
+    database::{ChangeOperation, FacilityCollection},
+    error::Error,
     facilities::{IDPair, MinimalFacilityData, OperationResult},
 };
 
@@ -34,7 +35,7 @@ pub(in crate::facilities) struct AddCommentData {
 pub(in crate::facilities) fn add_comment(
     data: Json<AddCommentData>,
     collection: FacilityCollection,
-) -> JsonValue {
+) -> Result<JsonValue, Error> {
     let AddCommentData {
         id: IDPair {
             sourceId,
@@ -47,7 +48,7 @@ pub(in crate::facilities) fn add_comment(
 
     let id = Uuid::new_v4();
 
-    let insert_result = collection.find_one_and_update(
+    collection.find_one_and_update(
         doc! {
             "properties.sourceId": sourceId.clone(),
             "properties.originalId": originalId.clone()
@@ -59,12 +60,10 @@ pub(in crate::facilities) fn add_comment(
             lat,
             lon,
         }),
-    );
+        ChangeOperation::addComment,
+    )?;
 
-    match insert_result {
-        Ok(_) => json!({ "result": OperationResult::success }),
-        Err(_) => json!({ "result": OperationResult::failure }),
-    }
+    Ok(json!({ "result": OperationResult::success }))
 }
 
 /// The data to flag a comment.
@@ -83,7 +82,7 @@ pub(in crate::facilities) struct FlagCommentData {
 pub(in crate::facilities) fn flag_comment(
     data: Json<FlagCommentData>,
     collection: FacilityCollection,
-) -> JsonValue {
+) -> Result<JsonValue, Error> {
     let FlagCommentData {
         id: IDPair {
             sourceId,
@@ -92,14 +91,14 @@ pub(in crate::facilities) fn flag_comment(
         commentId,
     } = data.into_inner();
 
-    let insert_result = collection.find_one_and_update(
-        doc! { "properties.sourceId": sourceId, "properties.originalId": originalId, "properties.comments.id": commentId.to_string() },
-        doc! { "$set": { "properties.comments.$.flagged": true } },
-        None,
-    );
+    collection
+        .find_one_and_update(
+            doc! { "properties.sourceId": sourceId, "properties.originalId": originalId, "properties.comments.id": commentId.to_string() },
+            doc! { "$set": { "properties.comments.$.flagged": true } },
+            None,
+            ChangeOperation::flagComment,
+        )?
+        .ok_or_else(|| Error::not_found("The comment does not exist."))?;
 
-    match insert_result {
-        Ok(_) => json!({ "result": OperationResult::success }),
-        Err(_) => json!({ "result": OperationResult::failure }),
-    }
+    Ok(json!({ "result": OperationResult::success }))
 }
@@ -0,0 +1,140 @@
+//! Handles a request to dispute the value of an attribute.
+
+use chrono::Utc;
+use rocket::post;
+use rocket_contrib::{
+    databases::mongodb::{bson, doc, Bson, Document},
+    json,
+    json::{Json, JsonValue},
+};
+use serde::Deserialize;
+
+use crate::{
+    database::{ChangeOperation, FacilityCollection},
+    error::Error,
+    facilities::{
+        attributes::ATTRIBUTES,
+        verification::{correction, dispute, push_vote, votes_path},
+        IDPair, OperationResult,
+    },
+};
+
+/// The data required to dispute an attribute.
+#[derive(Deserialize)]
+pub(in crate::facilities) struct DisputeAttributeData {
+    /// The ID of the facility to dispute the attribute for.
+    id: IDPair,
+    /// The attribute to dispute.
+    attribute: String,
+    /// The value the user proposes instead, if any.
+    value: Option<serde_json::Value>,
+}
+
+/// The number of times a dispute is attempted if the votes for the attribute change concurrently.
+const MAX_DISPUTE_ATTEMPTS: usize = 5;
+
+/// Disputes the current value of an attribute of a facility.
+///
+/// If the value has been disputed often enough, it is replaced by the value most disputes proposed.
+/// Without such a value, the votes that supported the disputed value are discarded instead.
+#[post("/dispute-attribute", format = "application/json", data = "<data>")]
+pub(in crate::facilities) fn dispute_attribute(
+    data: Json<DisputeAttributeData>,
+    collection: FacilityCollection,
+) -> Result<JsonValue, Error> {
+    let DisputeAttributeData {
+        id,
+        attribute,
+        value: proposed_value,
+    } = data.into_inner();
+
+    let attribute = ATTRIBUTES
+        .iter()
+        .find(|description| description.get_name() == attribute)
+        .ok_or_else(|| Error::invalid_request("Unknown attribute."))?;
+
+    if let Some(proposed_value) = &proposed_value {
+        if !attribute.get_domain().is_valid_value(proposed_value) {
+            return Err(Error::invalid_request(
+                "The proposed value is not valid for the attribute.",
+            )
+            .with_detail("allowedValues", attribute.get_domain().describe()));
+        }
+    }
+
+    for _ in 0..MAX_DISPUTE_ATTEMPTS {
+        let document = collection
+            .document_by_id(id.clone())?
+            .ok_or_else(|| Error::not_found("The facility does not exist."))?;
+        let mut facility: serde_json::Value = Bson::Document(document.clone()).into();
+
+        // Only values that are stored on the server can be disputed.
+        let current_value = attribute
+            .get_value(&facility)
+            .ok_or_else(|| Error::not_found("The attribute has no value."))?;
+
+        if proposed_value.as_ref() == Some(&current_value) {
+            return Err(Error::invalid_request(
+                "The proposed value is the current value. Use `/facilities/verify-attributes` to verify it instead.",
+            ));
+        }
+
+        let vote = dispute(&current_value, proposed_value.as_ref(), Utc::now());
+
+        // Determine whether this dispute leads to a correction by adding it to the votes that were read.
+        let votes = &mut facility["properties"]["verifications"][attribute.get_name()];
+        if !votes.is_array() {
+            *votes = serde_json::json!([]);
+        }
+        if let Some(votes) = votes.as_array_mut() {
+            votes.push(Bson::Document(vote.clone()).into());
+        }
+
+        let mut filter = doc! {
+            "properties.sourceId": id.sourceId.clone(),
+            "properties.originalId": id.originalId.clone()
+        };
+        let mut update = Document::new();
+        let value = match correction(attribute, &facility) {
+            Some(correction) => {
+                // The correction replaces the votes, so it must only be made if no votes were added meanwhile.
+                let read_votes = document
+                    .get_document("properties")
+                    .and_then(|properties| properties.get_document("verifications"))
+                    .ok()
+                    .and_then(|verifications| verifications.get(attribute.get_name()))
+                    .cloned()
+                    .unwrap_or(Bson::Null);
+                filter.insert(votes_path(attribute), read_votes);
+
+                let mut set_document = Document::new();
+                set_document.insert(votes_path(attribute), correction.votes);
+                if let Some(value) = &correction.value {
+                    set_document.insert(attribute.get_database_path(), Bson::from(value.clone()));
+                }
+                update.insert("$set", set_document);
+
+                correction.value.unwrap_or(current_value)
+            }
+            None => {
+                let mut push_document = Document::new();
+                push_document.insert(votes_path(attribute), push_vote(vote));
+                update.insert("$push", push_document);
+
+                current_value
+            }
+        };
+
+        let updated = collection
+            .find_one_and_update(filter, update, None, ChangeOperation::disputeAttribute)?
+            .is_some();
+
+        if updated {
+            return Ok(json!({ "result": OperationResult::success, "value": value }));
+        }
+    }
+
+    Err(Error::internal(
+        "The attribute was disputed by too many users at the same time.",
+    ))
+}
@@ -0,0 +1,150 @@
+//! Handles requests to undo changes to facilities.
+
+use rocket::post;
+use rocket_contrib::{
+    databases::mongodb::{oid::ObjectId, Bson},
+    json,
+    json::{Json, JsonValue},
+};
+use serde::Deserialize;
+
+use super::{IDPair, OperationResult};
+use crate::{
+    admin::Admin,
+    database::{
+        overwritten_path, removed_required_path, values_before, ChangeOperation, FacilityCollection,
+    },
+    error::Error,
+    facilities::query::{invalid_timestamp, parse_timestamp},
+};
+
+/// The data to revert a change.
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+pub(in crate::facilities) struct RevertChangeData {
+    /// The ID of the change to revert, as returned by the history.
+    changeId: String,
+}
+
+/// Reverts a single change of a facility.
+///
+/// Every path the change touched is set back to its value before the change. Changes of paths that were changed
+/// again later can't be reverted, since that would also undo the later changes.
+#[post("/revert-change", format = "application/json", data = "<data>")]
+pub(in crate::facilities) fn revert_change(
+    _admin: Admin,
+    data: Json<RevertChangeData>,
+    collection: FacilityCollection,
+) -> Result<JsonValue, Error> {
+    let change_id = ObjectId::with_string(&data.changeId)
+        .map_err(|_| Error::invalid_request("The change ID is invalid."))?;
+
+    let record = collection
+        .find_change(change_id)?
+        .ok_or_else(|| Error::not_found("The change does not exist."))?;
+
+    if record.get_str("operation").ok() == Some("createFacility") {
+        return Err(Error::invalid_request(
+            "Creating a facility can't be reverted.",
+        ));
+    }
+
+    let id = record
+        .get_document("id")
+        .ok()
+        .and_then(|id| {
+            Some(IDPair {
+                sourceId: String::from(id.get_str("sourceId").ok()?),
+                originalId: String::from(id.get_str("originalId").ok()?),
+            })
+        })
+        .ok_or_else(|| Error::internal("The change does not contain the ID of its facility."))?;
+
+    let timestamp = record
+        .get_utc_datetime("timestamp")
+        .map_err(|_| Error::internal("The change does not contain its timestamp."))?;
+    let later_records = collection.changes_since(id.clone(), *timestamp)?;
+
+    if let Some(path) = overwritten_path(&record, &later_records) {
+        return Err(Error::invalid_request(format!(
+            "The path `{}` was changed again later, so the change can't be reverted.",
+            path
+        )));
+    }
+
+    let values = values_before(&record);
+    check_required_paths(&values)?;
+
+    collection
+        .restore(id, values, ChangeOperation::revertChange)?
+        .ok_or_else(|| Error::not_found("The facility does not exist."))?;
+
+    Ok(json!({ "result": OperationResult::success }))
+}
+
+/// The data to restore a facility.
+#[derive(Deserialize)]
+pub(in crate::facilities) struct RestoreFacilityData {
+    /// The ID of the facility to restore.
+    id: IDPair,
+    /// The time of the state to restore the facility to.
+    timestamp: String,
+}
+
+/// Restores a facility to its state at the given time.
+///
+/// All changes made after that time are undone.
+#[post("/restore-facility", format = "application/json", data = "<data>")]
+pub(in crate::facilities) fn restore_facility(
+    _admin: Admin,
+    data: Json<RestoreFacilityData>,
+    collection: FacilityCollection,
+) -> Result<JsonValue, Error> {
+    let RestoreFacilityData { id, timestamp } = data.into_inner();
+
+    let timestamp = parse_timestamp(&timestamp).ok_or_else(invalid_timestamp)?;
+
+    let records = collection.changes_since(id.clone(), timestamp)?;
+
+    if records
+        .iter()
+        .any(|record| record.get_str("operation").ok() == Some("createFacility"))
+    {
+        return Err(Error::invalid_request(
+            "The facility did not exist at that time.",
+        ));
+    }
+
+    // The value of a path at the given time is its value before the first change after that time.
+    let mut values = Vec::new();
+    for record in &records {
+        for (path, value) in values_before(record) {
+            if !values.iter().any(|(existing, _)| existing == &path) {
+                values.push((path, value));
+            }
+        }
+    }
+
+    if values.is_empty() {
+        return Ok(json!({ "result": OperationResult::success }));
+    }
+
+    check_required_paths(&values)?;
+
+    collection
+        .restore(id, values, ChangeOperation::restoreFacility)?
+        .ok_or_else(|| Error::not_found("The facility does not exist."))?;
+
+    Ok(json!({ "result": OperationResult::success }))
+}
+
+/// Ensures that undoing changes does not remove a path that every facility has.
+fn check_required_paths(values: &[(String, Option<Bson>)]) -> Result<(), Error> {
+    match removed_required_path(values) {
+        Some(path) => Err(Error::invalid_request(format!(
+            "Undoing the changes would remove the path `{}` of the facility.",
+            path
+        ))),
+        None => Ok(()),
+    }
+}
@@ -1,8 +1,9 @@
 //! Handles a set facility request.
 
+use chrono::Utc;
 use rocket::post;
 use rocket_contrib::{
-    databases::mongodb::{bson, doc},
+    databases::mongodb::{bson, doc, Bson},
     json,
     json::{Json, JsonValue},
 };
@@ -10,8 +11,13 @@ use serde::Deserialize;
 
 use super::insert_json_flattened;
 use crate::{
-    database::FacilityCollection,
-    facilities::{IDPair, MinimalFacilityData, OperationResult},
+    database::{ChangeOperation, FacilityCollection},
+    error::Error,
+    facilities::{
+        attributes::ATTRIBUTES,
+        verification::{attribute_values, push_vote, vote, votes_path},
+        IDPair, MinimalFacilityData, OperationResult,
+    },
 };
 
 /// Represents the data that can be set in the `set-facility`-API.
@@ -32,6 +38,85 @@ pub(in crate::facilities) struct SetFacilityData {
     lon: f64,
     /// Indicates whether a new facility is created.
     createNewFacility: bool,
+    /// Indicates whether unknown accessibility fields are rejected instead of being dropped.
+    #[serde(default)]
+    strict: bool,
+}
+
+/// The result of validating the accessibility information.
+struct ValidatedAccessibility {
+    /// The accessibility information without the unknown fields.
+    accessibility: serde_json::Value,
+    /// The paths of the unknown fields relative to `accessibility`.
+    unknown_attributes: Vec<String>,
+    /// The fields with values outside of the domain of their attribute.
+    invalid_values: Vec<serde_json::Value>,
+}
+
+/// Validates the accessibility information against the known attributes.
+///
+/// Every field must either be an attribute or an object containing attributes.
+/// Unknown fields are removed, so that only known attributes are stored.
+///
+/// Returns `None` if the accessibility information is not an object.
+fn validate_accessibility(mut accessibility: serde_json::Value) -> Option<ValidatedAccessibility> {
+    let mut unknown_attributes = Vec::new();
+    let mut invalid_values = Vec::new();
+
+    /// Validates the fields of the object at the given path and removes the unknown ones.
+    fn validate_object(
+        object: &mut serde_json::Map<String, serde_json::Value>,
+        path: &str,
+        unknown_attributes: &mut Vec<String>,
+        invalid_values: &mut Vec<serde_json::Value>,
+    ) {
+        let keys: Vec<String> = object.keys().cloned().collect();
+
+        for key in keys {
+            let field_path = format!("{}.{}", path, key);
+            let attribute = ATTRIBUTES
+                .iter()
+                .find(|attribute| attribute.get_database_path() == field_path);
+
+            match (attribute, object.get_mut(&key)) {
+                (Some(attribute), Some(value)) => {
+                    if !attribute.get_domain().is_valid_value(value) {
+                        invalid_values.push(serde_json::json!({
+                            "attribute": attribute.get_name(),
+                            "value": value,
+                            "allowedValues": attribute.get_domain().describe(),
+                        }));
+                    }
+                }
+                (None, Some(serde_json::Value::Object(nested))) => {
+                    validate_object(nested, &field_path, unknown_attributes, invalid_values);
+
+                    if nested.is_empty() {
+                        object.remove(&key);
+                    }
+                }
+                _ => {
+                    unknown_attributes.push(String::from(
+                        field_path.trim_start_matches("properties.accessibility."),
+                    ));
+                    object.remove(&key);
+                }
+            }
+        }
+    }
+
+    validate_object(
+        accessibility.as_object_mut()?,
+        "properties.accessibility",
+        &mut unknown_attributes,
+        &mut invalid_values,
+    );
+
+    Some(ValidatedAccessibility {
+        accessibility,
+        unknown_attributes,
+        invalid_values,
+    })
 }
 
 /// Adds or modifies an facility in the facilities collection.
@@ -40,7 +125,7 @@ pub(in crate::facilities) struct SetFacilityData {
 pub(in crate::facilities) fn set_facility(
     data: Json<SetFacilityData>,
     collection: FacilityCollection,
-) -> JsonValue {
+) -> Result<JsonValue, Error> {
     let mut set_document = doc! {};
 
     let SetFacilityData {
@@ -51,8 +136,53 @@ pub(in crate::facilities) fn set_facility(
         lat,
         lon,
         createNewFacility,
+        strict,
     } = data.into_inner();
 
+    let mut unknown_attributes = Vec::new();
+    let accessibility = match accessibility.map(validate_accessibility) {
+        Some(None) => {
+            return Err(Error::invalid_request(
+                "The accessibility information must be an object.",
+            ))
+        }
+        Some(Some(validated)) => {
+            if !validated.invalid_values.is_empty()
+                || (strict && !validated.unknown_attributes.is_empty())
+            {
+                return Err(Error::invalid_request(
+                    "The accessibility information contains invalid values or unknown attributes.",
+                )
+                .with_detail("unknownAttributes", validated.unknown_attributes)
+                .with_detail("invalidValues", validated.invalid_values));
+            }
+
+            unknown_attributes = validated.unknown_attributes;
+            Some(validated.accessibility)
+        }
+        None => None,
+    };
+
+    // Unknown attributes that were dropped are reported, but successful responses stay unchanged otherwise.
+    let success = || {
+        if unknown_attributes.is_empty() {
+            json!({ "result": OperationResult::success })
+        } else {
+            json!({ "result": OperationResult::success, "unknownAttributes": unknown_attributes })
+        }
+    };
+
+    // Setting the value of an attribute counts as a vote for it, which contradicts the votes for other values.
+    let now = Utc::now();
+    let votes: Vec<_> = accessibility
+        .as_ref()
+        .map(|accessibility| {
+            attribute_values(
+                &serde_json::json!({ "properties": { "accessibility": accessibility } }),
+            )
+        })
+        .unwrap_or_default();
+
     /// Insert an optional value into a document.
     macro_rules! insert_into_doc {
         ($doc:expr, $prefix:expr, $name:ident, flatten = true) => {
@@ -89,23 +219,32 @@ pub(in crate::facilities) fn set_facility(
             insert_into_doc!(set_document, "properties.", address, flatten = true);
             insert_into_doc!(set_document, "properties.", accessibility, flatten = true);
 
-            let insert_result = collection.find_one_and_update(
+            let mut update = doc! { "$set": set_document };
+            if !votes.is_empty() {
+                let mut push_document = doc! {};
+                for (attribute, value) in votes {
+                    push_document.insert(votes_path(attribute), push_vote(vote(&value, now)));
+                }
+                update.insert("$push", push_document);
+            }
+
+            collection.find_one_and_update(
                 doc! { "properties.sourceId": sourceId.clone(), "properties.originalId": originalId.clone() },
-                doc! { "$set": set_document },
+                update,
                 Some(MinimalFacilityData {
                     lat,
                     lon,
                     sourceId,
                     originalId,
                 }),
-            );
+                ChangeOperation::setFacility,
+            )?;
 
-            match insert_result {
-                Ok(_) => json!({ "result": OperationResult::success }),
-                Err(_) => json!({ "result": OperationResult::failure }),
-            }
+            Ok(success())
         } else {
-            json!({ "result": OperationResult::failure, "reason": "The `sourceId` and `originalId` inside `id` are required to update existing entries." })
+            Err(Error::invalid_request(
+                "The `sourceId` and `originalId` inside `id` are required to update existing entries.",
+            ))
         }
     } else {
         let mut props = doc! {};
@@ -114,6 +253,17 @@ pub(in crate::facilities) fn set_facility(
         insert_into_doc!(props, "", address, flatten = false);
         insert_into_doc!(props, "", accessibility, flatten = false);
 
+        if !votes.is_empty() {
+            let mut verifications = doc! {};
+            for (attribute, value) in votes {
+                verifications.insert(
+                    attribute.get_name(),
+                    vec![Bson::Document(vote(&value, now))],
+                );
+            }
+            props.insert("verifications", verifications);
+        }
+
         let document = doc! {
             "geometry": {
                 "coordinates": [lon, lat],
@@ -122,11 +272,8 @@ pub(in crate::facilities) fn set_facility(
             "properties": props
         };
 
-        let insert_result = collection.insert(document);
+        collection.insert(document)?;
 
-        match insert_result {
-            Ok(_) => json!({ "result": OperationResult::success }),
-            Err(_) => json!({ "result": OperationResult::failure }),
-        }
+        Ok(success())
     }
 }
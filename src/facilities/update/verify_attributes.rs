@@ -1,16 +1,22 @@
 //! Handles a request to verify an attribute.
 
+use chrono::Utc;
 use rocket::post;
 use rocket_contrib::{
-    databases::mongodb::{bson, doc},
+    databases::mongodb::{bson, doc, Document},
     json,
     json::{Json, JsonValue},
 };
 use serde::Deserialize;
 
 use crate::{
-    database::FacilityCollection,
-    facilities::{attributes::ATTRIBUTES, IDPair, MinimalFacilityData, OperationResult},
+    database::{ChangeOperation, FacilityCollection},
+    error::Error,
+    facilities::{
+        attributes::ATTRIBUTES,
+        verification::{attribute_values, push_vote, vote, votes_path},
+        IDPair, MinimalFacilityData, OperationResult,
+    },
 };
 
 /// The data required to verify an attribute.
@@ -26,13 +32,15 @@ pub(in crate::facilities) struct VerifyAttributeData {
     lon: f64,
 }
 
-/// Verifies an attribute of a facility.
+/// Verifies attributes of a facility.
+///
+/// This records a vote for the current value of every given attribute. Attributes without a value are ignored.
 #[post("/verify-attributes", format = "application/json", data = "<data>")]
 #[allow(non_snake_case)]
 pub(in crate::facilities) fn verify_attributes(
     data: Json<VerifyAttributeData>,
     collection: FacilityCollection,
-) -> JsonValue {
+) -> Result<JsonValue, Error> {
     let VerifyAttributeData {
         id: IDPair {
             sourceId,
@@ -43,16 +51,6 @@ pub(in crate::facilities) fn verify_attributes(
         lon,
     } = data.into_inner();
 
-    // Filter attributes based on whether they're known to the server.
-    let known_attributes: Vec<_> = attributes
-        .iter()
-        .filter(|attribute| {
-            ATTRIBUTES
-                .iter()
-                .any(|attribute_description| attribute == &attribute_description.get_name())
-        })
-        .map(|attribute| bson!(attribute))
-        .collect();
     let unknown_attributes: Vec<_> = attributes
         .iter()
         .filter(|attribute| {
@@ -62,26 +60,45 @@ pub(in crate::facilities) fn verify_attributes(
         })
         .collect();
 
-    let insert_result = collection.find_one_and_update(
+    let id = IDPair {
+        sourceId,
+        originalId,
+    };
+    let facility = collection
+        .by_id(id.clone())?
+        .unwrap_or_else(|| serde_json::json!({}));
+
+    // Every verified attribute with a value gets a vote for its current value.
+    let now = Utc::now();
+    let mut votes = Document::new();
+    for (attribute, value) in attribute_values(&facility) {
+        if attributes
+            .iter()
+            .any(|verified| verified == attribute.get_name())
+        {
+            votes.insert(votes_path(attribute), push_vote(vote(&value, now)));
+        }
+    }
+
+    let mut update = Document::new();
+    if !votes.is_empty() {
+        update.insert("$push", votes);
+    }
+
+    collection.find_one_and_update(
         doc! {
-            "properties.sourceId": sourceId.clone(),
-            "properties.originalId": originalId.clone()
+            "properties.sourceId": id.sourceId.clone(),
+            "properties.originalId": id.originalId.clone()
         },
-        doc! { "$addToSet": { "properties.verifiedAttributes": { "$each": known_attributes } } },
+        update,
         Some(MinimalFacilityData {
-            sourceId,
-            originalId,
+            sourceId: id.sourceId,
+            originalId: id.originalId,
             lat,
             lon,
         }),
-    );
+        ChangeOperation::verifyAttributes,
+    )?;
 
-    match insert_result {
-        Ok(_) => {
-            json!({ "result": OperationResult::success, "unknownAttributes": unknown_attributes })
-        }
-        Err(_) => {
-            json!({ "result": OperationResult::failure, "unknownAttributes": unknown_attributes })
-        }
-    }
+    Ok(json!({ "result": OperationResult::success, "unknownAttributes": unknown_attributes }))
 }
@@ -1,7 +1,10 @@
 //! Handles a will-visit request.
 
+use chrono::Utc;
+use log::error;
 use rocket::post;
 use rocket_contrib::{
+    databases::mongodb::{bson, doc, Bson, Document},
     json,
     json::{Json, JsonValue},
 };
@@ -10,6 +13,7 @@ use serde::Deserialize;
 use super::{IDPair, OperationResult};
 use crate::{
     database::FacilityCollection,
+    error::Error,
     facilities::{query::perform_radius_search, questions::generate_facility_questions},
 };
 
@@ -26,11 +30,43 @@ pub(in crate::facilities) struct RadiusSearch {
 
 /// Represents the data sent by a will visit request.
 #[derive(Deserialize)]
+#[allow(non_snake_case)]
 pub(in crate::facilities) struct WillVisitData {
     /// The search that was performed.
     search: RadiusSearch,
     /// The ID of the facility to visit.
     id: IDPair,
+    /// The maximum number of questions to return.
+    maxQuestions: Option<usize>,
+}
+
+/// Records the visit along with the search that led to it.
+///
+/// The rank is the position of the facility in the search results starting at `1`. Recording is best effort,
+/// so failures don't affect the response.
+fn record_visit(
+    id: &IDPair,
+    search: &RadiusSearch,
+    index_in_search: Option<usize>,
+    result_count: usize,
+    geometry: Option<&serde_json::Value>,
+    collection: &FacilityCollection,
+) {
+    let mut visit = doc! {
+        "id": { "sourceId": id.sourceId.clone(), "originalId": id.originalId.clone() },
+        "search": { "lat": search.lat, "lon": search.lon, "radius": search.radius },
+        "rank": index_in_search.map_or(Bson::Null, |index| Bson::I64(index as i64 + 1)),
+        "resultCount": result_count as i64,
+        "timestamp": Bson::UtcDatetime(Utc::now())
+    };
+
+    if let Some(geometry) = geometry {
+        visit.insert("geometry", Bson::from(geometry.clone()));
+    }
+
+    if let Err(error) = collection.record_visit(visit) {
+        error!("The visit could not be recorded: {}", error);
+    }
 }
 
 /// Indicates that the user will visit the specified location after the given search.
@@ -38,13 +74,21 @@ pub(in crate::facilities) struct WillVisitData {
 pub(in crate::facilities) fn will_visit(
     data: Json<WillVisitData>,
     collection: FacilityCollection,
-) -> JsonValue {
-    let WillVisitData { search, id } = data.into_inner();
+) -> Result<JsonValue, Error> {
+    let WillVisitData {
+        search,
+        id,
+        maxQuestions: max_questions,
+    } = data.into_inner();
 
-    let radius_search_results: Vec<serde_json::Value> =
-        perform_radius_search(search.lon, search.lat, search.radius, &collection)
-            .map(|iter| iter.collect())
-            .unwrap_or_else(|| Vec::new());
+    let radius_search_results: Vec<serde_json::Value> = perform_radius_search(
+        search.lon,
+        search.lat,
+        search.radius,
+        Document::new(),
+        &collection,
+    )?
+    .collect();
 
     let index_in_search = radius_search_results
         .iter()
@@ -55,7 +99,7 @@ pub(in crate::facilities) fn will_visit(
         })
         .map(|(index, _)| index);
 
-    let facility = if let Ok(Some(facility)) = collection.by_id(id) {
+    let facility = if let Ok(Some(facility)) = collection.by_id(id.clone()) {
         Some(facility)
     } else {
         None
@@ -64,15 +108,47 @@ pub(in crate::facilities) fn will_visit(
     match (facility, index_in_search) {
         (Some(_), None) => {
             // The facility was in the database, but not in the search. This is most likely a bug in the frontend.
-            json!({ "result": OperationResult::failure, "reason": "Facility is not in given search."})
+            Err(Error::invalid_request("Facility is not in given search."))
         }
-        (Some(facility), Some(_index_in_search)) => {
-            json!({ "result": OperationResult::success, "questions": generate_facility_questions(&facility)})
+        (Some(facility), Some(index_in_search)) => {
+            record_visit(
+                &id,
+                &search,
+                Some(index_in_search),
+                radius_search_results.len(),
+                Some(&radius_search_results[index_in_search]["geometry"]),
+                &collection,
+            );
+
+            let questions = generate_facility_questions(&id, &facility, max_questions);
+
+            // Questions users were already asked are less useful to ask again, so count them.
+            // The questions are still useful if they can't be counted, so the request does not fail then.
+            let keys: Vec<String> = questions
+                .iter()
+                .map(|question| question.question.subject().key())
+                .collect();
+            if let Err(error) = collection.count_questions(id, &keys) {
+                error!("The asked questions could not be counted: {}", error);
+            }
+
+            Ok(json!({ "result": OperationResult::success, "questions": questions }))
         }
         _ => {
+            record_visit(
+                &id,
+                &search,
+                None,
+                radius_search_results.len(),
+                None,
+                &collection,
+            );
+
             // We don't know anything about the facility, so we assume nothing about it.
             let empty_facility = json!({});
-            json!({ "result": OperationResult::success, "questions": generate_facility_questions(&empty_facility) })
+            Ok(
+                json!({ "result": OperationResult::success, "questions": generate_facility_questions(&id, &empty_facility, max_questions) }),
+            )
         }
     }
 }
@@ -0,0 +1,307 @@
+//! Determines how confident we are in the values of the attributes of a facility.
+//!
+//! Every answer about an attribute is stored as a vote for a value in `properties.verifications.<attribute>`,
+//! along with the time it was given. Votes for the current value support it, votes for any other value contradict it.
+//! Users can also dispute a value without proposing another one, which only contradicts the disputed value.
+//! The weight of a vote halves every `TONARI_VERIFICATION_HALF_LIFE_DAYS` days, so that old answers count less than
+//! recent ones.
+
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use rocket_contrib::databases::mongodb::{bson, doc, Bson, Document};
+
+use super::attributes::{AttributeDescription, ATTRIBUTES};
+use crate::configuration::{
+    DISPUTE_THRESHOLD, MAX_VOTES_PER_ATTRIBUTE, VERIFICATION_CONFIDENCE_THRESHOLD,
+    VERIFICATION_HALF_LIFE_DAYS,
+};
+
+/// The number of milliseconds in a day.
+const MILLISECONDS_PER_DAY: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// Returns the path to the votes for the attribute in the database in dotted notation.
+pub fn votes_path(attribute: &AttributeDescription) -> String {
+    format!("properties.verifications.{}", attribute.get_name())
+}
+
+/// Creates a vote for the value given at the specified time.
+pub fn vote(value: &serde_json::Value, timestamp: DateTime<Utc>) -> Document {
+    doc! {
+        "value": Bson::from(value.clone()),
+        "timestamp": Bson::UtcDatetime(timestamp)
+    }
+}
+
+/// Returns the `$push` argument that adds the vote to the votes for an attribute.
+///
+/// Only the most recent `TONARI_MAX_VOTES_PER_ATTRIBUTE` votes are kept.
+pub fn push_vote(vote: Document) -> Document {
+    doc! {
+        "$each": [vote],
+        "$slice": -(*MAX_VOTES_PER_ATTRIBUTE as i64)
+    }
+}
+
+/// Creates a vote that disputes a value at the specified time, optionally proposing the correct value.
+pub fn dispute(
+    disputed: &serde_json::Value,
+    proposed: Option<&serde_json::Value>,
+    timestamp: DateTime<Utc>,
+) -> Document {
+    let mut vote = doc! {
+        "disputes": Bson::from(disputed.clone()),
+        "timestamp": Bson::UtcDatetime(timestamp)
+    };
+
+    if let Some(proposed) = proposed {
+        vote.insert("value", Bson::from(proposed.clone()));
+    }
+
+    vote
+}
+
+/// Returns the value of every attribute contained in the facility.
+pub fn attribute_values(
+    facility: &serde_json::Value,
+) -> Vec<(&'static AttributeDescription, serde_json::Value)> {
+    ATTRIBUTES
+        .iter()
+        .filter_map(|attribute| Some((attribute, attribute.get_value(facility)?)))
+        .collect()
+}
+
+/// Determines whether the vote supports the value.
+fn supports(vote: &serde_json::Value, value: &serde_json::Value) -> bool {
+    &vote["value"] == value
+}
+
+/// Determines whether the vote contradicts the value.
+///
+/// Disputes without a proposed value only contradict the value they dispute.
+fn contradicts(vote: &serde_json::Value, value: &serde_json::Value) -> bool {
+    (!vote["value"].is_null() && &vote["value"] != value) || &vote["disputes"] == value
+}
+
+/// Reads the time of a vote.
+///
+/// Depending on how the facility was read, the time is either an extended JSON date or an RFC 3339 string.
+fn vote_timestamp(vote: &serde_json::Value) -> Option<DateTime<Utc>> {
+    let timestamp = &vote["timestamp"];
+
+    if let Some(timestamp) = timestamp.as_str() {
+        return DateTime::parse_from_rfc3339(timestamp)
+            .ok()
+            .map(|timestamp| timestamp.with_timezone(&Utc));
+    }
+
+    let milliseconds = &timestamp["$date"]["$numberLong"];
+    let milliseconds = milliseconds
+        .as_i64()
+        .or_else(|| milliseconds.as_str()?.parse().ok())?;
+
+    Some(Utc.timestamp_millis(milliseconds))
+}
+
+/// Returns the weight of a vote given at the specified time.
+fn vote_weight(timestamp: DateTime<Utc>, now: DateTime<Utc>) -> f64 {
+    let age_in_days = (now - timestamp).num_milliseconds().max(0) as f64 / MILLISECONDS_PER_DAY;
+
+    0.5f64.powf(age_in_days / *VERIFICATION_HALF_LIFE_DAYS)
+}
+
+/// Determines whether a vote still counts fully, that is whether it is less than one half-life old.
+///
+/// Disputes of image labels only count while they are recent, just like old votes for attributes barely count.
+pub fn is_recent_vote(vote: &serde_json::Value, now: DateTime<Utc>) -> bool {
+    vote_timestamp(vote).map_or(false, |timestamp| vote_weight(timestamp, now) > 0.5)
+}
+
+/// Returns how confident we are in the current value of the attribute, between `0` and `1`.
+///
+/// Attributes without a value have a confidence of `0`. Otherwise the confidence grows with the weight of the
+/// supporting votes and shrinks with the weight of the contradicting ones.
+pub fn confidence(
+    attribute: &AttributeDescription,
+    facility: &serde_json::Value,
+    now: DateTime<Utc>,
+) -> f64 {
+    let value = match attribute.get_value(facility) {
+        Some(value) => value,
+        None => return 0.0,
+    };
+
+    let mut support = 0.0;
+    let mut opposition = 0.0;
+
+    if let Some(votes) = facility["properties"]["verifications"][attribute.get_name()].as_array() {
+        for vote in votes {
+            let weight = match vote_timestamp(vote) {
+                Some(timestamp) => vote_weight(timestamp, now),
+                None => continue,
+            };
+
+            if supports(vote, &value) {
+                support += weight;
+            } else if contradicts(vote, &value) {
+                opposition += weight;
+            }
+        }
+    }
+
+    // The constant in the denominator keeps a single vote from making us certain.
+    ((support - opposition) / (support + opposition + 1.0)).max(0.0)
+}
+
+/// The correction of a value that was disputed often enough.
+pub struct Correction {
+    /// The value that replaces the disputed value.
+    ///
+    /// This is only present if the majority of the disputes proposed the same value.
+    pub value: Option<serde_json::Value>,
+    /// The votes that remain for the attribute.
+    ///
+    /// Only the votes since the disputed value was last supported remain, so that the disputed value loses
+    /// the confidence it had.
+    pub votes: Vec<Bson>,
+}
+
+/// Converts a vote read from the database back into BSON.
+fn vote_to_bson(vote: &serde_json::Value) -> Option<Bson> {
+    let mut vote_doc = doc! { "timestamp": Bson::UtcDatetime(vote_timestamp(vote)?) };
+
+    for key in ["value", "disputes"].iter() {
+        if !vote[key].is_null() {
+            vote_doc.insert(*key, Bson::from(vote[key].clone()));
+        }
+    }
+
+    Some(Bson::Document(vote_doc))
+}
+
+/// Returns the proposal that the majority of the disputes agree on, if any.
+///
+/// Disputes without a proposal count as `None`.
+pub fn majority_proposal<T: PartialEq + Clone>(proposals: &[Option<T>]) -> Option<T> {
+    proposals.iter().flatten().find_map(|proposal| {
+        let count = proposals
+            .iter()
+            .filter(|other| other.as_ref() == Some(proposal))
+            .count();
+
+        if count * 2 > proposals.len() {
+            Some(proposal.clone())
+        } else {
+            None
+        }
+    })
+}
+
+/// Determines whether the current value of the attribute has been disputed often enough to be corrected.
+///
+/// Only the disputes since the value was last supported count. Returns `None` if there were fewer than
+/// `TONARI_DISPUTE_THRESHOLD` of them.
+pub fn correction(
+    attribute: &AttributeDescription,
+    facility: &serde_json::Value,
+) -> Option<Correction> {
+    let value = attribute.get_value(facility)?;
+    let votes = facility["properties"]["verifications"][attribute.get_name()].as_array()?;
+
+    let recent_votes = match votes.iter().rposition(|vote| supports(vote, &value)) {
+        Some(last_support) => &votes[last_support + 1..],
+        None => &votes[..],
+    };
+
+    let proposals: Vec<Option<serde_json::Value>> = recent_votes
+        .iter()
+        .filter(|vote| contradicts(vote, &value))
+        .map(|vote| Some(vote["value"].clone()).filter(|proposal| !proposal.is_null()))
+        .collect();
+
+    if (proposals.len() as u64) < *DISPUTE_THRESHOLD {
+        return None;
+    }
+
+    let kept_votes = recent_votes.len() - recent_votes.len().min(*MAX_VOTES_PER_ATTRIBUTE as usize);
+
+    Some(Correction {
+        value: majority_proposal(&proposals),
+        votes: recent_votes[kept_votes..]
+            .iter()
+            .filter_map(vote_to_bson)
+            .collect(),
+    })
+}
+
+/// Determines whether users should be asked about the attribute again.
+pub fn needs_verification(
+    attribute: &AttributeDescription,
+    facility: &serde_json::Value,
+    now: DateTime<Utc>,
+) -> bool {
+    confidence(attribute, facility, now) < *VERIFICATION_CONFIDENCE_THRESHOLD
+}
+
+/// Converts the times of the votes into RFC 3339 strings.
+fn format_timestamps(votes: &mut [serde_json::Value]) {
+    for vote in votes {
+        if let Some(timestamp) = vote_timestamp(vote) {
+            vote["timestamp"] =
+                serde_json::json!(timestamp.to_rfc3339_opts(SecondsFormat::Millis, true));
+        }
+    }
+}
+
+/// Adds the confidence in every attribute with a value as `properties.confidence` to the facility.
+///
+/// The times of the votes and of the disputes of image labels are converted into RFC 3339 strings.
+pub fn add_confidence_scores(facility: &mut serde_json::Value) {
+    let now = Utc::now();
+
+    let scores: serde_json::Map<String, serde_json::Value> = attribute_values(facility)
+        .into_iter()
+        .map(|(attribute, _)| {
+            (
+                String::from(attribute.get_name()),
+                serde_json::json!(confidence(attribute, facility, now)),
+            )
+        })
+        .collect();
+
+    let properties = match facility
+        .get_mut("properties")
+        .and_then(|properties| properties.as_object_mut())
+    {
+        Some(properties) => properties,
+        None => return,
+    };
+
+    if let Some(verifications) = properties
+        .get_mut("verifications")
+        .and_then(|verifications| verifications.as_object_mut())
+    {
+        for votes in verifications
+            .values_mut()
+            .filter_map(|votes| votes.as_array_mut())
+        {
+            format_timestamps(votes);
+        }
+    }
+
+    if let Some(images) = properties
+        .get_mut("images")
+        .and_then(|images| images.as_array_mut())
+    {
+        for disputes in images
+            .iter_mut()
+            .filter_map(|image| image.get_mut("labelDisputes"))
+            .filter_map(|disputes| disputes.as_array_mut())
+        {
+            format_timestamps(disputes);
+        }
+    }
+
+    properties.insert(
+        String::from("confidence"),
+        serde_json::Value::Object(scores),
+    );
+}
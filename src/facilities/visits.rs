@@ -0,0 +1,189 @@
+//! Provides statistics about the visits users announced with will-visit requests.
+//!
+//! Every will-visit request is recorded along with the search that led to it and the rank of the facility in the
+//! results of that search. The statistics are only available to administrators.
+
+use rocket::{get, post};
+use rocket_contrib::{
+    databases::mongodb::{bson, doc, Bson, Document},
+    json,
+    json::{Json, JsonValue},
+};
+
+use super::{
+    area::Area,
+    query::{invalid_timestamp, parse_timestamp},
+    IDPair, OperationResult,
+};
+use crate::{admin::Admin, database::FacilityCollection, error::Error};
+
+/// The number of facilities returned by a most-visited request if the client does not specify it.
+const DEFAULT_MOST_VISITED_COUNT: u64 = 10;
+
+/// Creates the filter for the visits since the given time.
+fn since_filter(since: Option<String>) -> Result<Document, Error> {
+    match since {
+        Some(since) => {
+            let since = parse_timestamp(&since).ok_or_else(invalid_timestamp)?;
+
+            Ok(doc! { "timestamp": { "$gte": Bson::UtcDatetime(since) } })
+        }
+        None => Ok(Document::new()),
+    }
+}
+
+/// Returns the average rank of the visits that match the filter and have one.
+///
+/// Visits of facilities that were not in the search results have no rank.
+fn average_rank(filter: Document, collection: &FacilityCollection) -> Result<Option<f64>, Error> {
+    let results = collection.aggregate_visits(vec![
+        doc! { "$match": filter },
+        doc! { "$group": { "_id": Bson::Null, "averageRank": { "$avg": "$rank" } } },
+    ])?;
+
+    Ok(results
+        .first()
+        .and_then(|result| result.get_f64("averageRank").ok()))
+}
+
+/// Returns the statistics of all visits.
+///
+/// The average rank shows how well the search ranks the facilities users actually choose.
+#[get("/visit-statistics?<since>")]
+pub(super) fn visit_statistics(
+    _admin: Admin,
+    since: Option<String>,
+    collection: FacilityCollection,
+) -> Result<JsonValue, Error> {
+    let filter = since_filter(since)?;
+
+    let mut ranked_filter = filter.clone();
+    ranked_filter.insert("rank", doc! { "$ne": Bson::Null });
+
+    Ok(json!({
+        "result": OperationResult::success,
+        "visitCount": collection.count_visits(filter.clone())?,
+        "rankedVisitCount": collection.count_visits(ranked_filter)?,
+        "averageRank": average_rank(filter, &collection)?
+    }))
+}
+
+/// Returns the number of visits of the facility with the given ID.
+#[get("/visits/<sourceId>/<originalId>?<since>")]
+#[allow(non_snake_case)]
+pub(super) fn visits(
+    _admin: Admin,
+    sourceId: String,
+    originalId: String,
+    since: Option<String>,
+    collection: FacilityCollection,
+) -> Result<JsonValue, Error> {
+    let mut filter = since_filter(since)?;
+    filter.insert("id.sourceId", sourceId.clone());
+    filter.insert("id.originalId", originalId.clone());
+
+    Ok(json!({
+        "result": OperationResult::success,
+        "id": IDPair { sourceId, originalId },
+        "visitCount": collection.count_visits(filter.clone())?,
+        "averageRank": average_rank(filter, &collection)?
+    }))
+}
+
+/// Returns the facilities within the area sent in the request body that were visited most often.
+///
+/// Every facility contains its `visitCount` in its properties.
+#[post(
+    "/most-visited?<limit>&<since>",
+    format = "application/json",
+    data = "<area>"
+)]
+pub(super) fn most_visited(
+    _admin: Admin,
+    area: Json<Area>,
+    limit: Option<u64>,
+    since: Option<String>,
+    collection: FacilityCollection,
+) -> Result<JsonValue, Error> {
+    let limit = limit.unwrap_or(DEFAULT_MOST_VISITED_COUNT);
+    if limit == 0 {
+        return Err(Error::invalid_request(
+            "The limit must be greater than zero.",
+        ));
+    }
+
+    let mut filter = since_filter(since)?;
+    filter.insert("geometry", area.to_condition()?);
+
+    let results = collection.aggregate_visits(vec![
+        doc! { "$match": filter },
+        doc! { "$group": { "_id": "$id", "visitCount": { "$sum": 1 } } },
+        doc! { "$sort": { "visitCount": -1, "_id.sourceId": 1, "_id.originalId": 1 } },
+        doc! { "$limit": limit as i64 },
+    ])?;
+
+    let visit_counts: Vec<((String, String), u64)> = results
+        .iter()
+        .filter_map(|result| {
+            let id = result.get_document("_id").ok()?;
+            let visit_count = match result.get("visitCount")? {
+                Bson::I32(count) => *count as u64,
+                Bson::I64(count) => *count as u64,
+                Bson::FloatingPoint(count) => *count as u64,
+                _ => return None,
+            };
+
+            Some((
+                (
+                    String::from(id.get_str("sourceId").ok()?),
+                    String::from(id.get_str("originalId").ok()?),
+                ),
+                visit_count,
+            ))
+        })
+        .collect();
+
+    if visit_counts.is_empty() {
+        return Ok(
+            json!({ "result": OperationResult::success, "features": [], "featureCount": 0 }),
+        );
+    }
+
+    let ids: Vec<Bson> = visit_counts
+        .iter()
+        .map(|((source_id, original_id), _)| {
+            Bson::Document(doc! {
+                "properties.sourceId": source_id.clone(),
+                "properties.originalId": original_id.clone()
+            })
+        })
+        .collect();
+
+    let mut facilities: Vec<serde_json::Value> = collection
+        .perform_json_query(Some(doc! { "$or": ids }), None)?
+        .collect();
+
+    for facility in &mut facilities {
+        let visit_count = visit_counts
+            .iter()
+            .find(|((source_id, original_id), _)| {
+                facility["properties"]["sourceId"] == source_id.as_str()
+                    && facility["properties"]["originalId"] == original_id.as_str()
+            })
+            .map_or(0, |(_, count)| *count);
+
+        facility["properties"]["visitCount"] = visit_count.into();
+    }
+
+    facilities.sort_by(|a, b| {
+        b["properties"]["visitCount"]
+            .as_u64()
+            .cmp(&a["properties"]["visitCount"].as_u64())
+    });
+
+    Ok(json!({
+        "result": OperationResult::success,
+        "features": facilities,
+        "featureCount": facilities.len()
+    }))
+}
@@ -1,36 +1,48 @@
 //! This modules deals with up- and downloading images.
 
+use chrono::Utc;
+use image::{
+    codecs::jpeg::JpegEncoder,
+    imageops::{self, FilterType},
+    io::Reader as ImageReader,
+    ColorType, DynamicImage, ImageError, ImageFormat, Rgb, RgbImage,
+};
 use lazy_static::lazy_static;
 use multipart::server::{save::PartialReason, Multipart, MultipartField, SaveResult};
 use rocket::{
     data::DataStream,
     get,
-    http::{
-        hyper::mime::{Mime, SubLevel, TopLevel},
-        ContentType, Status,
-    },
+    http::{ContentType, RawStr},
     post,
-    response::NamedFile,
-    routes, Data, Route,
+    response::content::Content,
+    routes, Data, FromFormValue, Route,
 };
 use rocket_contrib::{
-    databases::mongodb::{bson, doc},
+    databases::mongodb::{self, bson, doc, Bson, Document},
     json,
     json::{Json, JsonValue},
 };
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
     collections::HashSet,
-    fs::remove_file,
-    path::{Path, PathBuf},
+    io::{self, Cursor},
 };
 use uuid::Uuid;
 
 use crate::{
-    database::FacilityCollection,
-    facilities::{IDPair, MinimalFacilityData, OperationResult},
+    database::{ChangeOperation, FacilityCollection},
+    error::{Error, ErrorCode},
+    facilities::{is_recent_vote, majority_proposal, IDPair, MinimalFacilityData, OperationResult},
 };
 
+#[cfg(feature = "heic")]
+mod heic;
+mod store;
+
+use self::store::image_store;
+pub use self::store::ImageStorageBackend;
+
 /// Represents the possible labels an image can have.
 #[derive(Hash, PartialEq, Eq, Serialize, Deserialize, Clone)]
 #[allow(non_camel_case_types)]
@@ -69,15 +81,77 @@ lazy_static! {
 /// The type that represents an image ID.
 pub type ImageID = Uuid;
 
+/// Represents the sizes in which every uploaded image is available.
+#[derive(Clone, Copy, PartialEq, FromFormValue)]
+#[allow(non_camel_case_types)]
+pub enum ImageSize {
+    /// A small preview of the image, for example for lists of facilities.
+    thumb,
+    /// A size suitable for showing the image on phones.
+    medium,
+    /// The image in the resolution it was uploaded in.
+    full,
+}
+
+/// The quality of the JPEG images the uploaded images are stored as, from 1 to 100.
+const JPEG_QUALITY: u8 = 90;
+
+/// The number of times a dispute is attempted if the disputes of the image label change concurrently.
+const MAX_DISPUTE_ATTEMPTS: usize = 5;
+
+/// The MIME types of images that can be uploaded, as determined from their content.
+///
+/// Uploaded images are converted to JPEG, regardless of the format they were uploaded in. HEIC images can only be
+/// uploaded if the backend was compiled with the `heic` feature.
+#[cfg(feature = "heic")]
+pub const ACCEPTED_IMAGE_TYPES: &[&str] = &[
+    "image/jpeg",
+    "image/png",
+    "image/webp",
+    "image/heic",
+    "image/heif",
+];
+#[cfg(not(feature = "heic"))]
+pub const ACCEPTED_IMAGE_TYPES: &[&str] = &["image/jpeg", "image/png", "image/webp"];
+
+/// All sizes in which images are available.
+const ALL_IMAGE_SIZES: [ImageSize; 3] = [ImageSize::thumb, ImageSize::medium, ImageSize::full];
+
+impl ImageSize {
+    /// Returns the name of the size, as used in URLs and file names.
+    fn name(self) -> &'static str {
+        match self {
+            ImageSize::thumb => "thumb",
+            ImageSize::medium => "medium",
+            ImageSize::full => "full",
+        }
+    }
+
+    /// Returns the maximum width and height of images of this size in pixels.
+    ///
+    /// Returns `None` for the uploaded image, which is not resized.
+    fn max_dimension(self) -> Option<u32> {
+        match self {
+            ImageSize::thumb => Some(*crate::configuration::IMAGE_THUMB_SIZE),
+            ImageSize::medium => Some(*crate::configuration::IMAGE_MEDIUM_SIZE),
+            ImageSize::full => None,
+        }
+    }
+}
+
 /// Describes the result of uploading an image.
 ///
 /// Note that this type only exists to make JSON serialization nicer. Otherwise a Rust `enum` would be used.
+#[allow(non_snake_case)]
 #[derive(Debug, Serialize)]
 struct ImageUploadResult {
     /// The type of upload result.
     result: ImageUploadResultType,
     /// The ID of the uploaded image.
     id: Option<ImageID>,
+    /// The MIME types of images that can be uploaded, if the image has an unsupported format.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acceptedTypes: Option<&'static [&'static str]>,
 }
 
 impl ImageUploadResult {
@@ -86,12 +160,26 @@ impl ImageUploadResult {
         ImageUploadResult {
             result: ImageUploadResultType::success,
             id: Some(id),
+            acceptedTypes: None,
+        }
+    }
+
+    /// Creates an `ImageUploadResult` for an image that was already uploaded with the given ID.
+    fn duplicate(id: ImageID) -> ImageUploadResult {
+        ImageUploadResult {
+            result: ImageUploadResultType::duplicate,
+            id: Some(id),
+            acceptedTypes: None,
         }
     }
 
     /// Creates a failed `ImageUploadResult`.
     fn fail(reason: ImageUploadResultType) -> ImageUploadResult {
         ImageUploadResult {
+            acceptedTypes: match reason {
+                ImageUploadResultType::unsupportedFormat => Some(ACCEPTED_IMAGE_TYPES),
+                _ => None,
+            },
             result: reason,
             id: None,
         }
@@ -104,18 +192,61 @@ impl ImageUploadResult {
 enum ImageUploadResultType {
     /// The image was uploaded successfully.
     success,
-    /// The image file size is larger than the allowed maximum.
+    /// The same image was already uploaded for the facility, so the ID of that image is returned instead.
+    duplicate,
+    /// The image file size or its number of pixels is larger than the allowed maximum.
     tooLarge,
-    /// The image isn't a jpeg file.
-    ///
-    /// Only jpeg files are allowed.
-    notJpeg,
+    /// The image isn't in one of the `ACCEPTED_IMAGE_TYPES`.
+    unsupportedFormat,
+    /// The image seems to be in one of the `ACCEPTED_IMAGE_TYPES`, but can't be read, for example because it is
+    /// truncated.
+    invalidImage,
     /// Some internal error happened while saving.
     ///
     /// This is most likely an IO error.
     internalError,
 }
 
+impl ImageUploadResultType {
+    /// Returns the error that corresponds to a failed upload.
+    ///
+    /// Returns `None` for successful uploads.
+    fn to_error(&self) -> Option<Error> {
+        match self {
+            ImageUploadResultType::success | ImageUploadResultType::duplicate => None,
+            ImageUploadResultType::tooLarge => Some(Error::new(
+                ErrorCode::payloadTooLarge,
+                "The image is larger than the allowed maximum.",
+            )),
+            ImageUploadResultType::unsupportedFormat => Some(
+                Error::new(
+                    ErrorCode::unsupportedMediaType,
+                    format!(
+                        "Only images of the types {} are allowed.",
+                        ACCEPTED_IMAGE_TYPES.join(", ")
+                    ),
+                )
+                .with_detail("acceptedTypes", ACCEPTED_IMAGE_TYPES),
+            ),
+            ImageUploadResultType::invalidImage => {
+                Some(Error::invalid_request("The image could not be read."))
+            }
+            ImageUploadResultType::internalError => {
+                Some(Error::internal("The image could not be saved."))
+            }
+        }
+    }
+
+    /// Returns the result of an upload whose image could not be read.
+    fn unreadable(error: &ImageError) -> ImageUploadResultType {
+        match error {
+            ImageError::Unsupported(_) => ImageUploadResultType::unsupportedFormat,
+            ImageError::Limits(_) => ImageUploadResultType::tooLarge,
+            _ => ImageUploadResultType::invalidImage,
+        }
+    }
+}
+
 /// The routes for handling images.
 pub fn image_routes() -> Vec<Route> {
     routes![
@@ -123,51 +254,232 @@ pub fn image_routes() -> Vec<Route> {
         image_download,
         set_image_label,
         flag_image,
-        verify_image_label
+        verify_image_label,
+        dispute_image_label
     ]
 }
 
-/// Converts an image id into a path.
-fn image_path_from_id(id: &ImageID) -> PathBuf {
-    let mut image_path = PathBuf::from(&*crate::configuration::IMAGE_PATH);
-    image_path.push(id.to_string());
-    image_path.set_extension("jpg");
-
-    image_path
+/// Converts an image id into the name the image is stored under in the given size.
+///
+/// The uploaded image is stored as `<id>.jpg`, the other sizes as `<id>-<size>.jpg` next to it.
+fn image_name_from_id(id: &ImageID, size: ImageSize) -> String {
+    match size {
+        ImageSize::full => format!("{}.jpg", id),
+        _ => format!("{}-{}.jpg", id, size.name()),
+    }
 }
 
 /// Generates a new image id.
-fn generate_image_id() -> ImageID {
-    let id_in_use = |id| image_path_from_id(&id).exists();
+fn generate_image_id() -> io::Result<ImageID> {
+    let id_in_use = |id| image_store().exists(&image_name_from_id(&id, ImageSize::full));
 
     let mut id = Uuid::new_v4();
 
-    while id_in_use(id) {
+    while id_in_use(id)? {
         id = Uuid::new_v4();
     }
 
-    id
+    Ok(id)
 }
 
-/// Generates an image url from the given image ID.
-pub fn url_from_id(id: &ImageID) -> String {
-    format!(
+/// Generates the url of the image with the given ID in the given size.
+///
+/// Sizes other than the uploaded image are requested with the `size` query parameter.
+pub fn url_from_id(id: &ImageID, size: ImageSize) -> String {
+    let url = format!(
         "{}{}{}",
         &*crate::configuration::IMAGE_URL_PREFIX,
         id,
         &*crate::configuration::IMAGE_URL_SUFFIX
-    )
+    );
+
+    match size {
+        ImageSize::full => url,
+        _ => format!("{}?size={}", url, size.name()),
+    }
+}
+
+/// Generates the urls of the image with the given ID in all sizes.
+fn variant_urls(id: &ImageID) -> Document {
+    let mut variants = Document::new();
+    for size in ALL_IMAGE_SIZES.iter() {
+        variants.insert(size.name(), url_from_id(id, *size));
+    }
+
+    variants
 }
 
 /// Handles image downloads with the given id.
-#[get("/<id>")]
-fn image_download(id: rocket_contrib::uuid::Uuid) -> Option<NamedFile> {
+///
+/// The `size` is `full` by default.
+#[get("/<id>?<size>")]
+fn image_download(
+    id: rocket_contrib::uuid::Uuid,
+    size: Option<Result<ImageSize, &RawStr>>,
+) -> Result<Content<Vec<u8>>, Error> {
     let id = id.into_inner();
+    let size = size
+        .unwrap_or(Ok(ImageSize::full))
+        .map_err(|_| Error::invalid_request("The size must be `thumb`, `medium` or `full`."))?;
+
+    // Images uploaded before the sizes were generated only exist in full size.
+    let image = match image_store().get(&image_name_from_id(&id, size))? {
+        Some(image) => image,
+        None => image_store()
+            .get(&image_name_from_id(&id, ImageSize::full))?
+            .ok_or_else(|| Error::not_found("The image does not exist."))?,
+    };
+
+    Ok(Content(ContentType::JPEG, image))
+}
 
-    NamedFile::open(image_path_from_id(&id)).ok()
+/// Reads the EXIF orientation of the uploaded image.
+///
+/// Returns `1`, which means that the image is stored upright, if the image has no orientation.
+fn read_orientation(data: &[u8]) -> u32 {
+    exif::Reader::new(&mut Cursor::new(data))
+        .ok()
+        .and_then(|reader| {
+            reader
+                .get_field(exif::Tag::Orientation, false)?
+                .value
+                .get_uint(0)
+        })
+        .unwrap_or(1)
+}
+
+/// Rotates and flips the image according to its EXIF orientation, so that it is upright.
+fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// Returns the format of the uploaded image, which has the given MIME type.
+///
+/// HEIC images are not handled by the `image` crate, so they have no format.
+fn image_format(mime_type: &str) -> Option<ImageFormat> {
+    match mime_type {
+        "image/png" => Some(ImageFormat::Png),
+        "image/webp" => Some(ImageFormat::WebP),
+        "image/heic" | "image/heif" => None,
+        _ => Some(ImageFormat::Jpeg),
+    }
+}
+
+/// Reads the width and height of the uploaded image, which has the given MIME type, without decoding it.
+fn image_dimensions(data: &[u8], mime_type: &str) -> image::ImageResult<(u32, u32)> {
+    match image_format(mime_type) {
+        Some(format) => ImageReader::with_format(Cursor::new(data), format).into_dimensions(),
+        #[cfg(feature = "heic")]
+        None => heic::dimensions(data),
+        #[cfg(not(feature = "heic"))]
+        None => Err(heic_unsupported()),
+    }
+}
+
+/// Decodes the uploaded image, which has the given MIME type.
+fn decode_image(data: &[u8], mime_type: &str) -> image::ImageResult<DynamicImage> {
+    match image_format(mime_type) {
+        Some(format) => image::load_from_memory_with_format(data, format),
+        #[cfg(feature = "heic")]
+        None => heic::decode(data),
+        #[cfg(not(feature = "heic"))]
+        None => Err(heic_unsupported()),
+    }
+}
+
+/// Returns the error for HEIC images, which can't be read without the `heic` feature.
+#[cfg(not(feature = "heic"))]
+fn heic_unsupported() -> ImageError {
+    ImageError::Unsupported(image::error::UnsupportedError::from(
+        image::error::ImageFormatHint::Name(String::from("HEIC")),
+    ))
+}
+
+/// Converts the image to RGB, placing transparent parts of it on a white background.
+///
+/// JPEG files can't be transparent, so transparent parts of PNG and WebP images would otherwise turn black.
+fn flatten(image: &DynamicImage) -> RgbImage {
+    let rgba = image.to_rgba8();
+
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let pixel = rgba.get_pixel(x, y);
+        let alpha = u32::from(pixel[3]);
+        let blend = |channel: u8| ((u32::from(channel) * alpha + 255 * (255 - alpha)) / 255) as u8;
+
+        Rgb([blend(pixel[0]), blend(pixel[1]), blend(pixel[2])])
+    })
+}
+
+/// Returns the dimensions of an image scaled down to fit into the maximum dimension, keeping its aspect ratio.
+fn scaled_dimensions(width: u32, height: u32, max_dimension: u32) -> (u32, u32) {
+    let scale = f64::from(max_dimension) / f64::from(width.max(height));
+    let scaled = |dimension: u32| ((f64::from(dimension) * scale).round() as u32).max(1);
+
+    (scaled(width), scaled(height))
+}
+
+/// Encodes the image as a JPEG file without any metadata.
+fn encode_jpeg(image: &RgbImage) -> image::ImageResult<Vec<u8>> {
+    let mut data = Vec::new();
+
+    JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY).encode(
+        image.as_raw(),
+        image.width(),
+        image.height(),
+        ColorType::Rgb8,
+    )?;
+
+    Ok(data)
+}
+
+/// Stores the uploaded image with the given ID in all sizes.
+///
+/// `decoded` is the image decoded from the uploaded file `data`.
+///
+/// The uploaded file is re-encoded as JPEG, which removes all of its metadata, such as the location a photo was
+/// taken at or the serial number of the camera. The orientation in the metadata is applied before, so that the
+/// image is still displayed upright. Images are only scaled down, so small images keep their size.
+fn store_image_sizes(id: &ImageID, decoded: DynamicImage, data: &[u8]) -> image::ImageResult<()> {
+    let original = flatten(&apply_orientation(decoded, read_orientation(data)));
+    let (width, height) = original.dimensions();
+
+    for size in ALL_IMAGE_SIZES.iter() {
+        let resized;
+        let image = match size.max_dimension() {
+            Some(max_dimension) if width.max(height) > max_dimension => {
+                let (new_width, new_height) = scaled_dimensions(width, height, max_dimension);
+                resized = imageops::resize(&original, new_width, new_height, FilterType::Lanczos3);
+                &resized
+            }
+            _ => &original,
+        };
+
+        image_store().put(&image_name_from_id(id, *size), &encode_jpeg(image)?)?;
+    }
+
+    Ok(())
+}
+
+/// Deletes the image with the given ID in all sizes, ignoring sizes that don't exist.
+fn remove_image_files(id: &ImageID) {
+    for size in ALL_IMAGE_SIZES.iter() {
+        image_store().delete(&image_name_from_id(id, *size)).ok();
+    }
 }
 
 /// Handles uploading images.
+///
+/// Every image is handled on its own, so the result contains the result of every image in the same order.
+/// If all images failed, the request fails with the error of the first image.
 #[post(
     "/upload/<sourceId>/<originalId>?<lat>&<lon>",
     format = "multipart/form-data",
@@ -182,15 +494,18 @@ fn image_upload(
     data: Data,
     content_type: &ContentType,
     collection: FacilityCollection,
-) -> Result<JsonValue, Status> {
+) -> Result<JsonValue, Error> {
     if !content_type.is_form_data() {
-        return Err(Status::BadRequest);
+        return Err(Error::new(
+            ErrorCode::unsupportedMediaType,
+            "Images must be uploaded as `multipart/form-data`.",
+        ));
     }
 
     let (_, boundary) = content_type
         .params()
         .find(|&(key, _)| key == "boundary")
-        .ok_or(Status::BadRequest)?;
+        .ok_or_else(|| Error::invalid_request("The multipart boundary is missing."))?;
 
     let mut save_results = Vec::new();
 
@@ -207,9 +522,25 @@ fn image_upload(
                 ));
             }
         })
-        .map_err(|_| Status::InternalServerError)?;
+        .map_err(|_| Error::internal("The uploaded data could not be read."))?;
+
+    if save_results.is_empty() {
+        return Err(Error::invalid_request(
+            "The request does not contain an `image` field.",
+        ));
+    }
+
+    // The request only fails if no image could be saved, so that successful uploads are not hidden.
+    if save_results
+        .iter()
+        .all(|save_result| save_result.id.is_none())
+    {
+        if let Some(error) = save_results[0].result.to_error() {
+            return Err(error.with_detail("results", save_results));
+        }
+    }
 
-    Ok(json!({ "results": save_results }))
+    Ok(json!({ "result": OperationResult::success, "results": save_results }))
 }
 
 /// Represents the post request that is used to set an image label.
@@ -231,7 +562,10 @@ struct SetImageLabelData {
 /// Handles the request for setting an image label.
 #[post("/set-label", format = "application/json", data = "<data>")]
 #[allow(non_snake_case)]
-fn set_image_label(data: Json<SetImageLabelData>, collection: FacilityCollection) -> JsonValue {
+fn set_image_label(
+    data: Json<SetImageLabelData>,
+    collection: FacilityCollection,
+) -> Result<JsonValue, Error> {
     let SetImageLabelData {
         imageURL,
         imageLabel,
@@ -244,37 +578,50 @@ fn set_image_label(data: Json<SetImageLabelData>, collection: FacilityCollection
     } = data.into_inner();
 
     if !ALL_IMAGE_LABELS.contains(&imageLabel) {
-        return json!({ "result": OperationResult::failure, "reason": "Unknown image label." });
+        return Err(Error::invalid_request("Unknown image label."));
     }
 
-    let insert_result = collection.find_one_and_update(
-        doc! { "properties.sourceId": sourceId.clone(), "properties.originalId": originalId.clone(), "properties.images.url": imageURL.clone() },
-        doc! { "$set": { "properties.images.$.label": json!(imageLabel).as_str().unwrap() } },
+    let facility = MinimalFacilityData {
+        sourceId,
+        originalId,
+        lat,
+        lon,
+    };
+
+    label_image(&collection, facility, imageURL, &imageLabel)?;
+
+    Ok(json!({ "result": OperationResult::success }))
+}
+
+/// Sets the label of the image with the given URL.
+///
+/// Images that are not in the database yet must be remote images, so they are added to the facility.
+pub fn label_image(
+    collection: &FacilityCollection,
+    facility: MinimalFacilityData,
+    image_url: String,
+    label: &ImageLabel,
+) -> mongodb::Result<()> {
+    let label = json!(label).as_str().unwrap().to_string();
+
+    let update_result = collection.find_one_and_update(
+        doc! { "properties.sourceId": facility.sourceId.clone(), "properties.originalId": facility.originalId.clone(), "properties.images.url": image_url.clone() },
+        doc! { "$set": { "properties.images.$.label": label.clone() } },
         None,
-    );
+        ChangeOperation::setImageLabel,
+    )?;
 
-    match insert_result {
-        Ok(None) => {
-            // The image did not exist, so it must be a remote image. Insert it into the database.
-            let insert_result = collection.find_one_and_update(
-                doc! { "properties.sourceId": sourceId.clone(), "properties.originalId": originalId.clone() },
-                doc! { "$push": { "properties.images": { "url": imageURL, "label": json!(imageLabel).as_str().unwrap() } } },
-                Some(MinimalFacilityData {
-                    sourceId: sourceId,
-                    originalId: originalId,
-                    lat: lat,
-                    lon: lon
-                }),
-            );
-
-            match insert_result {
-                Ok(_) => json!({ "result": OperationResult::success }),
-                Err(_) => json!({ "result": OperationResult::failure }),
-            }
-        }
-        Ok(Some(_)) => json!({ "result": OperationResult::success }),
-        Err(_) => json!({ "result": OperationResult::failure }),
+    if update_result.is_none() {
+        // The image did not exist, so it must be a remote image. Insert it into the database.
+        collection.find_one_and_update(
+            doc! { "properties.sourceId": facility.sourceId.clone(), "properties.originalId": facility.originalId.clone() },
+            doc! { "$push": { "properties.images": { "url": image_url, "label": label } } },
+            Some(facility),
+            ChangeOperation::setImageLabel,
+        )?;
     }
+
+    Ok(())
 }
 
 /// Represents the post request that is used to verify an image label.
@@ -294,7 +641,10 @@ struct VerifyImageLabel {
 /// Handles the request for setting an image label.
 #[post("/verify-label", format = "application/json", data = "<data>")]
 #[allow(non_snake_case)]
-fn verify_image_label(data: Json<VerifyImageLabel>, collection: FacilityCollection) -> JsonValue {
+fn verify_image_label(
+    data: Json<VerifyImageLabel>,
+    collection: FacilityCollection,
+) -> Result<JsonValue, Error> {
     let VerifyImageLabel {
         id: IDPair {
             sourceId,
@@ -304,22 +654,182 @@ fn verify_image_label(data: Json<VerifyImageLabel>, collection: FacilityCollecti
         imageLabel,
     } = data.into_inner();
 
-    let insert_result = collection.find_one_and_update(
+    let id = IDPair {
+        sourceId,
+        originalId,
+    };
+
+    verify_label(&collection, id, imageURL, &imageLabel)?
+        .ok_or_else(|| Error::not_found("The image does not exist or has a different label."))?;
+
+    Ok(json!({ "result": OperationResult::success }))
+}
+
+/// Verifies the label of the image with the given URL, if it still has that label.
+///
+/// The earlier disputes of the label are discarded, just like a vote for the value of an attribute discards the
+/// earlier disputes of the value. Returns `Ok(None)` if there is no such image.
+pub fn verify_label(
+    collection: &FacilityCollection,
+    id: IDPair,
+    image_url: String,
+    label: &str,
+) -> mongodb::Result<Option<Document>> {
+    collection.find_one_and_update(
         doc! {
-            "properties.sourceId": sourceId,
-            "properties.originalId": originalId,
-            "properties.images.url": imageURL.to_string(),
-            "properties.images.label": imageLabel.to_string()
+            "properties.sourceId": id.sourceId,
+            "properties.originalId": id.originalId,
+            "properties.images.url": image_url,
+            "properties.images.label": label
+        },
+        doc! {
+            "$set": {
+                "properties.images.$.labelVerified": true,
+                "properties.images.$.labelDisputes": []
+            }
         },
-        doc! { "$set": { "properties.images.$.labelVerified": true } },
         None,
-    );
+        ChangeOperation::verifyImageLabel,
+    )
+}
+
+/// Represents the post request that is used to dispute an image label.
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+struct DisputeImageLabel {
+    /// The ID of the facility the image belongs to.
+    id: IDPair,
+    /// The URL of the image.
+    imageURL: String,
+    /// The label of the image that is disputed.
+    ///
+    /// This makes sure that only the label the user saw can be disputed.
+    imageLabel: ImageLabel,
+    /// The label the user proposes instead, if any.
+    proposedLabel: Option<ImageLabel>,
+}
+
+/// Handles the request for disputing an image label.
+///
+/// Once the label has been disputed often enough since it was last verified, it is no longer verified. If the
+/// majority of the disputes proposed the same label, that label replaces the disputed one. Disputes only count for
+/// `TONARI_VERIFICATION_HALF_LIFE_DAYS` days.
+#[post("/dispute-label", format = "application/json", data = "<data>")]
+#[allow(non_snake_case)]
+fn dispute_image_label(
+    data: Json<DisputeImageLabel>,
+    collection: FacilityCollection,
+) -> Result<JsonValue, Error> {
+    let DisputeImageLabel {
+        id,
+        imageURL,
+        imageLabel,
+        proposedLabel,
+    } = data.into_inner();
+
+    if let Some(proposedLabel) = &proposedLabel {
+        if !ALL_IMAGE_LABELS.contains(proposedLabel) {
+            return Err(Error::invalid_request("Unknown image label."));
+        }
+
+        if proposedLabel == &imageLabel {
+            return Err(Error::invalid_request(
+                "The proposed label is the disputed label.",
+            ));
+        }
+    }
+
+    let label = json!(imageLabel).as_str().unwrap().to_string();
+
+    for _ in 0..MAX_DISPUTE_ATTEMPTS {
+        let document = collection
+            .document_by_id(id.clone())?
+            .ok_or_else(|| Error::not_found("The facility does not exist."))?;
+
+        let image = document
+            .get_document("properties")
+            .and_then(|properties| properties.get_array("images"))
+            .ok()
+            .and_then(|images| {
+                images.iter().filter_map(Bson::as_document).find(|image| {
+                    image.get_str("url").ok() == Some(imageURL.as_str())
+                        && image.get_str("label").ok() == Some(label.as_str())
+                })
+            })
+            .ok_or_else(|| {
+                Error::not_found("The image does not exist or has a different label.")
+            })?;
+        let read_disputes = image.get("labelDisputes").cloned().unwrap_or(Bson::Null);
+
+        let now = Utc::now();
+
+        // The recent earlier disputes of the label along with this one.
+        let mut proposals: Vec<Option<ImageLabel>> =
+            match serde_json::Value::from(read_disputes.clone()) {
+                serde_json::Value::Array(disputes) => disputes
+                    .iter()
+                    .filter(|dispute| is_recent_vote(dispute, now))
+                    .map(|dispute| serde_json::from_value(dispute["label"].clone()).ok())
+                    .collect(),
+                _ => Vec::new(),
+            };
+        proposals.push(proposedLabel.clone());
 
-    match insert_result {
-        Ok(Some(_)) => json!({ "result": OperationResult::success }),
-        Ok(None) => json!({ "result": OperationResult::entryNotFound }),
-        Err(_) => json!({ "result": OperationResult::failure }),
+        let (update, new_label) =
+            if proposals.len() as u64 >= *crate::configuration::DISPUTE_THRESHOLD {
+                let new_label = majority_proposal(&proposals).unwrap_or_else(|| imageLabel.clone());
+
+                (
+                    doc! {
+                        "$set": {
+                            "properties.images.$.label": json!(new_label).as_str().unwrap(),
+                            "properties.images.$.labelVerified": false,
+                            "properties.images.$.labelDisputes": []
+                        }
+                    },
+                    new_label,
+                )
+            } else {
+                let mut dispute = doc! { "timestamp": Bson::UtcDatetime(now) };
+                if let Some(proposedLabel) = &proposedLabel {
+                    dispute.insert("label", json!(proposedLabel).as_str().unwrap());
+                }
+
+                (
+                    doc! { "$push": { "properties.images.$.labelDisputes": dispute } },
+                    imageLabel.clone(),
+                )
+            };
+
+        // The image is only updated if its disputes were not changed since they were read, and `$elemMatch`
+        // makes sure that the positional operator refers to the same image.
+        let updated = collection
+            .find_one_and_update(
+                doc! {
+                    "properties.sourceId": id.sourceId.clone(),
+                    "properties.originalId": id.originalId.clone(),
+                    "properties.images": {
+                        "$elemMatch": {
+                            "url": imageURL.clone(),
+                            "label": label.clone(),
+                            "labelDisputes": read_disputes
+                        }
+                    }
+                },
+                update,
+                None,
+                ChangeOperation::disputeImageLabel,
+            )?
+            .is_some();
+
+        if updated {
+            return Ok(json!({ "result": OperationResult::success, "imageLabel": new_label }));
+        }
     }
+
+    Err(Error::internal(
+        "The label was disputed by too many users at the same time.",
+    ))
 }
 
 /// Represents the data required to flag an image as inappropriate.
@@ -335,7 +845,10 @@ struct FlagImageData {
 /// Handles the request for flagging an image as inappropriate.
 #[post("/flag-image", format = "application/json", data = "<data>")]
 #[allow(non_snake_case)]
-fn flag_image(data: Json<FlagImageData>, collection: FacilityCollection) -> JsonValue {
+fn flag_image(
+    data: Json<FlagImageData>,
+    collection: FacilityCollection,
+) -> Result<JsonValue, Error> {
     let FlagImageData {
         imageURL,
         id: IDPair {
@@ -344,17 +857,16 @@ fn flag_image(data: Json<FlagImageData>, collection: FacilityCollection) -> Json
         },
     } = data.into_inner();
 
-    let insert_result = collection.find_one_and_update(
-        doc! { "properties.sourceId": sourceId, "properties.originalId": originalId, "properties.images.url": imageURL.to_string() },
-        doc! { "$set": { "properties.images.$.flagged": true } },
-        None,
-    );
+    collection
+        .find_one_and_update(
+            doc! { "properties.sourceId": sourceId, "properties.originalId": originalId, "properties.images.url": imageURL },
+            doc! { "$set": { "properties.images.$.flagged": true } },
+            None,
+            ChangeOperation::flagImage,
+        )?
+        .ok_or_else(|| Error::not_found("The image does not exist."))?;
 
-    match insert_result {
-        Ok(Some(_)) => json!({ "result": OperationResult::success }),
-        Ok(None) => json!({ "result": OperationResult::entryNotFound }),
-        Err(_) => json!({ "result": OperationResult::failure }),
-    }
+    Ok(json!({ "result": OperationResult::success }))
 }
 
 /// Handle an image upload to the server.
@@ -366,70 +878,170 @@ fn handle_image_upload(
     lat: f64,
     lon: f64,
 ) -> ImageUploadResult {
-    match image_entry.headers.content_type {
-        Some(Mime(TopLevel::Image, SubLevel::Jpeg, _)) => (),
-        _ => {
-            return ImageUploadResult::fail(ImageUploadResultType::notJpeg);
-        }
-    }
-
-    let id = generate_image_id();
-    let path = image_path_from_id(&id);
-
+    let mut data = Vec::new();
     let save_result = image_entry
         .data
         .save()
         // Limit to files of size `IMAGE_UPLOAD_SIZE_LIMIT`.
         .size_limit(*crate::configuration::IMAGE_UPLOAD_SIZE_LIMIT)
-        // Always save to disk.
-        .memory_threshold(0)
-        // Save to the given path.
-        .with_path(&path);
+        // Keep the upload in memory, it is only stored after it has been re-encoded.
+        .write_to(&mut data);
 
     match save_result {
         SaveResult::Full(_) => {
-            if !path_is_jpeg(&path) {
-                // Delete the non-jpeg file, ignore if deleting fails.
-                remove_file(&path).ok();
+            let mime_type = tree_magic::from_u8(&data);
 
-                return ImageUploadResult::fail(ImageUploadResultType::notJpeg);
+            if !ACCEPTED_IMAGE_TYPES.contains(&mime_type) {
+                return ImageUploadResult::fail(ImageUploadResultType::unsupportedFormat);
             }
 
-            let insert_result = collection.find_one_and_update(
-                doc! { "properties.sourceId": source_id.clone(), "properties.originalId": original_id.clone() },
-                doc! { "$push": { "properties.images": { "id": id.to_string(), "url": url_from_id(&id) } } },
-                Some(MinimalFacilityData {
-                    lat,
-                    lon,
-                    sourceId: source_id,
-                    originalId: original_id,
-                }),
-            );
+            // Decoding needs memory for every pixel, so only the header is read before deciding to decode.
+            match image_dimensions(&data, mime_type) {
+                Ok((width, height))
+                    if u64::from(width) * u64::from(height)
+                        > *crate::configuration::IMAGE_MAX_PIXELS =>
+                {
+                    return ImageUploadResult::fail(ImageUploadResultType::tooLarge)
+                }
+                Ok(_) => (),
+                Err(error) => {
+                    return ImageUploadResult::fail(ImageUploadResultType::unreadable(&error))
+                }
+            }
+
+            let hash = format!("{:x}", Sha256::digest(&data));
+
+            // Retried uploads are recognized before the image is decoded and stored, concurrent ones only when
+            // the image is added to the facility.
+            match find_duplicate_image(collection, &source_id, &original_id, &hash) {
+                Ok(Some(id)) => return ImageUploadResult::duplicate(id),
+                Ok(None) => (),
+                Err(_) => return ImageUploadResult::fail(ImageUploadResultType::internalError),
+            }
+
+            let decoded = match decode_image(&data, mime_type) {
+                Ok(decoded) => decoded,
+                Err(error) => {
+                    return ImageUploadResult::fail(ImageUploadResultType::unreadable(&error))
+                }
+            };
+
+            let id = match generate_image_id() {
+                Ok(id) => id,
+                Err(_) => return ImageUploadResult::fail(ImageUploadResultType::internalError),
+            };
+
+            if store_image_sizes(&id, decoded, &data).is_err() {
+                // Delete all sizes that were saved, ignore if deleting fails.
+                remove_image_files(&id);
 
-            match insert_result {
-                Ok(_) => ImageUploadResult::success(id),
+                return ImageUploadResult::fail(ImageUploadResultType::internalError);
+            }
+
+            let facility = MinimalFacilityData {
+                lat,
+                lon,
+                sourceId: source_id,
+                originalId: original_id,
+            };
+
+            match add_image(collection, facility, &id, &hash) {
+                Ok(None) => ImageUploadResult::success(id),
+                Ok(Some(existing_id)) => {
+                    // The same file was uploaded concurrently, so this copy is not needed.
+                    remove_image_files(&id);
+
+                    ImageUploadResult::duplicate(existing_id)
+                }
                 Err(_) => {
-                    // Delete the orphan file, ignore if deleting fails.
-                    remove_file(&path).ok();
+                    // Delete the orphan files, ignore if deleting fails.
+                    remove_image_files(&id);
 
                     ImageUploadResult::fail(ImageUploadResultType::internalError)
                 }
             }
         }
-        SaveResult::Partial(_, reason) => {
-            // Delete the partial file, ignore if deleting fails.
-            remove_file(&path).ok();
-
-            ImageUploadResult::fail(match reason {
-                PartialReason::SizeLimit => ImageUploadResultType::tooLarge,
-                _ => ImageUploadResultType::internalError,
-            })
-        }
+        SaveResult::Partial(_, reason) => ImageUploadResult::fail(match reason {
+            PartialReason::SizeLimit => ImageUploadResultType::tooLarge,
+            _ => ImageUploadResultType::internalError,
+        }),
         _ => ImageUploadResult::fail(ImageUploadResultType::internalError),
     }
 }
 
-/// Checks if the file at the given path is a jpeg file.
-fn path_is_jpeg(path: &Path) -> bool {
-    path.exists() && tree_magic::from_filepath(&path) == "image/jpeg"
+/// Adds the stored image with the given ID and SHA-256 hash to the facility, creating the facility if necessary.
+///
+/// The image is only added if the facility has no image with the same hash, which is checked by the update itself,
+/// so that concurrent uploads of the same file add it only once. Returns the ID of the existing image in that case.
+fn add_image(
+    collection: &FacilityCollection,
+    facility: MinimalFacilityData,
+    id: &ImageID,
+    hash: &str,
+) -> mongodb::Result<Option<ImageID>> {
+    let image = doc! {
+        "id": id.to_string(),
+        "url": url_from_id(id, ImageSize::full),
+        "variants": variant_urls(id),
+        "sha256": hash
+    };
+    let facility_filter = doc! {
+        "properties.sourceId": facility.sourceId.clone(),
+        "properties.originalId": facility.originalId.clone()
+    };
+
+    let mut filter = facility_filter.clone();
+    filter.insert("properties.images.sha256", doc! { "$ne": hash });
+
+    let added = collection.find_one_and_update(
+        filter,
+        doc! { "$push": { "properties.images": image.clone() } },
+        None,
+        ChangeOperation::addImage,
+    )?;
+
+    if added.is_some() {
+        return Ok(None);
+    }
+
+    // Either the facility already has the image or it doesn't exist yet.
+    if let Some(existing_id) =
+        find_duplicate_image(collection, &facility.sourceId, &facility.originalId, hash)?
+    {
+        return Ok(Some(existing_id));
+    }
+
+    collection.find_one_and_update(
+        facility_filter,
+        doc! { "$push": { "properties.images": image } },
+        Some(facility),
+        ChangeOperation::addImage,
+    )?;
+
+    Ok(None)
+}
+
+/// Returns the ID of the image of the facility with the given SHA-256 hash, if it was uploaded before.
+///
+/// The hash is taken of the uploaded file, so only identical files are recognized, which happens for example
+/// if an upload is retried.
+fn find_duplicate_image(
+    collection: &FacilityCollection,
+    source_id: &str,
+    original_id: &str,
+    hash: &str,
+) -> mongodb::Result<Option<ImageID>> {
+    let facility = collection.by_id(IDPair {
+        sourceId: String::from(source_id),
+        originalId: String::from(original_id),
+    })?;
+
+    Ok(facility
+        .as_ref()
+        .and_then(|facility| facility["properties"]["images"].as_array())
+        .into_iter()
+        .flatten()
+        .find(|image| image["sha256"] == hash)
+        .and_then(|image| image["id"].as_str())
+        .and_then(|id| id.parse().ok()))
 }
@@ -0,0 +1,59 @@
+//! Decodes HEIC images, which are taken by default on newer iPhones.
+//!
+//! This requires `libheif` to be installed, so it's only available with the `heic` feature.
+
+use image::{
+    error::{DecodingError, ImageFormatHint},
+    DynamicImage, ImageError, ImageResult, RgbImage,
+};
+use libheif_rs::{ColorSpace, HeifContext, HeifError, RgbChroma};
+
+/// Creates an error of the `image` crate for a HEIC image that could not be decoded.
+fn decoding_error(message: String) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Name(String::from("HEIC")),
+        message,
+    ))
+}
+
+/// Converts an error of `libheif` into an error of the `image` crate.
+fn to_image_error(error: HeifError) -> ImageError {
+    decoding_error(error.to_string())
+}
+
+/// Reads the width and height of the primary image of the HEIC file without decoding it.
+pub fn dimensions(data: &[u8]) -> ImageResult<(u32, u32)> {
+    let context = HeifContext::read_from_bytes(data).map_err(to_image_error)?;
+    let handle = context.primary_image_handle().map_err(to_image_error)?;
+
+    Ok((handle.width(), handle.height()))
+}
+
+/// Decodes the primary image of the HEIC file.
+///
+/// `libheif` applies the rotation and mirroring of the image while decoding, so the image is already upright.
+pub fn decode(data: &[u8]) -> ImageResult<DynamicImage> {
+    let context = HeifContext::read_from_bytes(data).map_err(to_image_error)?;
+    let handle = context.primary_image_handle().map_err(to_image_error)?;
+    let image = handle
+        .decode(ColorSpace::Rgb(RgbChroma::Rgb), false)
+        .map_err(to_image_error)?;
+
+    let plane = image
+        .planes()
+        .interleaved
+        .ok_or_else(|| decoding_error(String::from("The image has no RGB plane.")))?;
+    let row_length = plane.width as usize * 3;
+
+    // Rows may be padded, so only the pixels of every row are copied.
+    let pixels = plane
+        .data
+        .chunks(plane.stride)
+        .take(plane.height as usize)
+        .flat_map(|row| row[..row_length].iter().cloned())
+        .collect();
+
+    RgbImage::from_raw(plane.width, plane.height, pixels)
+        .map(DynamicImage::ImageRgb8)
+        .ok_or_else(|| decoding_error(String::from("The image is truncated.")))
+}
@@ -0,0 +1,88 @@
+//! Handles storing the uploaded images.
+//!
+//! Images are either stored in a directory of the local filesystem or in a bucket of an S3-compatible object
+//! storage. The latter allows running several instances of the backend that share their images.
+
+mod filesystem;
+mod s3;
+
+use lazy_static::lazy_static;
+use std::{fmt, io, str::FromStr};
+
+use self::{filesystem::FilesystemImageStore, s3::S3ImageStore};
+use crate::configuration::{
+    IMAGE_PATH, IMAGE_STORAGE_BACKEND, S3_ACCESS_KEY, S3_BUCKET, S3_ENDPOINT, S3_REGION,
+    S3_SECRET_KEY,
+};
+
+/// The storage backends that images can be stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageStorageBackend {
+    /// Stores images in the directory `IMAGE_PATH`.
+    Filesystem,
+    /// Stores images in the bucket `S3_BUCKET` of an S3-compatible object storage.
+    S3,
+}
+
+impl FromStr for ImageStorageBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ImageStorageBackend, String> {
+        match s {
+            "filesystem" => Ok(ImageStorageBackend::Filesystem),
+            "s3" => Ok(ImageStorageBackend::S3),
+            _ => Err(format!(
+                "unknown image storage backend `{}` (expected `filesystem` or `s3`)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ImageStorageBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageStorageBackend::Filesystem => write!(f, "filesystem"),
+            ImageStorageBackend::S3 => write!(f, "s3"),
+        }
+    }
+}
+
+/// Describes the operations needed to store and retrieve images.
+///
+/// Images are identified by their file name, such as `<id>.jpg`, so that every implementation stores them
+/// under the same names.
+pub trait ImageStore: Send + Sync {
+    /// Stores the image with the given name, replacing an image with the same name.
+    fn put(&self, name: &str, data: &[u8]) -> io::Result<()>;
+
+    /// Returns the image with the given name or `None` if it doesn't exist.
+    fn get(&self, name: &str) -> io::Result<Option<Vec<u8>>>;
+
+    /// Deletes the image with the given name.
+    ///
+    /// Deleting an image that doesn't exist is not an error.
+    fn delete(&self, name: &str) -> io::Result<()>;
+
+    /// Checks whether an image with the given name exists.
+    fn exists(&self, name: &str) -> io::Result<bool>;
+}
+
+lazy_static! {
+    /// The configured image store, which is shared by all requests.
+    static ref IMAGE_STORE: Box<dyn ImageStore> = match *IMAGE_STORAGE_BACKEND {
+        ImageStorageBackend::Filesystem => Box::new(FilesystemImageStore::new(&IMAGE_PATH)),
+        ImageStorageBackend::S3 => Box::new(S3ImageStore::new(
+            &S3_ENDPOINT,
+            &S3_REGION,
+            &S3_BUCKET,
+            &S3_ACCESS_KEY,
+            &S3_SECRET_KEY,
+        )),
+    };
+}
+
+/// Returns the configured image store.
+pub fn image_store() -> &'static dyn ImageStore {
+    &**IMAGE_STORE
+}
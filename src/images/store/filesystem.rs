@@ -0,0 +1,54 @@
+//! Implements an image store that keeps the images in a directory of the local filesystem.
+
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::PathBuf,
+};
+
+use super::ImageStore;
+
+/// An image store that keeps every image as a file in a directory.
+pub struct FilesystemImageStore {
+    /// The directory the images are stored in.
+    directory: PathBuf,
+}
+
+impl FilesystemImageStore {
+    /// Creates a store for the images in the given directory.
+    pub fn new(directory: &str) -> FilesystemImageStore {
+        FilesystemImageStore {
+            directory: PathBuf::from(directory),
+        }
+    }
+
+    /// Returns the path of the image with the given name.
+    fn path(&self, name: &str) -> PathBuf {
+        self.directory.join(name)
+    }
+}
+
+impl ImageStore for FilesystemImageStore {
+    fn put(&self, name: &str, data: &[u8]) -> io::Result<()> {
+        fs::write(self.path(name), data)
+    }
+
+    fn get(&self, name: &str) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.path(name)) {
+            Ok(data) => Ok(Some(data)),
+            Err(ref error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(name)) {
+            Err(ref error) if error.kind() == ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    fn exists(&self, name: &str) -> io::Result<bool> {
+        Ok(self.path(name).exists())
+    }
+}
@@ -0,0 +1,145 @@
+//! Implements an image store that keeps the images in a bucket of an S3-compatible object storage.
+//!
+//! Besides Amazon S3 this works with other implementations of the S3 API, such as MinIO, by setting the endpoint.
+//!
+//! The S3 client is asynchronous, while the routes of the backend are not, so every request is run to completion
+//! on a runtime owned by the store.
+
+use rusoto_core::{credential::StaticProvider, HttpClient, Region, RusotoError};
+use rusoto_s3::{
+    DeleteObjectRequest, GetObjectError, GetObjectRequest, HeadObjectError, HeadObjectRequest,
+    PutObjectRequest, S3Client, S3,
+};
+use std::io::{self, ErrorKind};
+use tokio::{io::AsyncReadExt, runtime::Runtime};
+
+use super::ImageStore;
+
+/// The content type of the stored images, which are always JPEG files.
+const IMAGE_CONTENT_TYPE: &str = "image/jpeg";
+
+/// An image store that keeps every image as an object in a bucket.
+pub struct S3ImageStore {
+    /// The client for the S3 API.
+    client: S3Client,
+    /// The name of the bucket the images are stored in.
+    bucket: String,
+    /// The runtime that runs the requests to the S3 API.
+    runtime: Runtime,
+}
+
+impl S3ImageStore {
+    /// Creates a store for the images in the given bucket.
+    ///
+    /// If the endpoint is empty, the bucket is located in the given region of Amazon S3. If the keys are empty,
+    /// the credentials are read from the usual `AWS_*` environment variables or the instance profile.
+    pub fn new(
+        endpoint: &str,
+        region: &str,
+        bucket: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> S3ImageStore {
+        let region = if endpoint.is_empty() {
+            region
+                .parse()
+                .unwrap_or_else(|_| panic!("Unknown S3 region `{}`.", region))
+        } else {
+            Region::Custom {
+                name: String::from(region),
+                endpoint: String::from(endpoint),
+            }
+        };
+
+        let client = if access_key.is_empty() {
+            S3Client::new(region)
+        } else {
+            S3Client::new_with(
+                HttpClient::new().expect("The HTTP client for S3 could not be created."),
+                StaticProvider::new_minimal(String::from(access_key), String::from(secret_key)),
+                region,
+            )
+        };
+
+        S3ImageStore {
+            client,
+            bucket: String::from(bucket),
+            runtime: Runtime::new().expect("The runtime for S3 requests could not be created."),
+        }
+    }
+}
+
+/// Converts an error of the S3 API into an IO error, so that all image stores fail in the same way.
+fn to_io_error<E: std::error::Error + 'static>(error: RusotoError<E>) -> io::Error {
+    io::Error::new(ErrorKind::Other, error.to_string())
+}
+
+impl ImageStore for S3ImageStore {
+    fn put(&self, name: &str, data: &[u8]) -> io::Result<()> {
+        self.runtime
+            .block_on(self.client.put_object(PutObjectRequest {
+                bucket: self.bucket.clone(),
+                key: String::from(name),
+                body: Some(data.to_vec().into()),
+                content_type: Some(String::from(IMAGE_CONTENT_TYPE)),
+                ..Default::default()
+            }))
+            .map(|_| ())
+            .map_err(to_io_error)
+    }
+
+    fn get(&self, name: &str) -> io::Result<Option<Vec<u8>>> {
+        self.runtime.block_on(async {
+            let result = self
+                .client
+                .get_object(GetObjectRequest {
+                    bucket: self.bucket.clone(),
+                    key: String::from(name),
+                    ..Default::default()
+                })
+                .await;
+
+            let output = match result {
+                Ok(output) => output,
+                Err(RusotoError::Service(GetObjectError::NoSuchKey(_))) => return Ok(None),
+                Err(error) => return Err(to_io_error(error)),
+            };
+
+            let mut data = Vec::new();
+            if let Some(body) = output.body {
+                body.into_async_read().read_to_end(&mut data).await?;
+            }
+
+            Ok(Some(data))
+        })
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        self.runtime
+            .block_on(self.client.delete_object(DeleteObjectRequest {
+                bucket: self.bucket.clone(),
+                key: String::from(name),
+                ..Default::default()
+            }))
+            .map(|_| ())
+            .map_err(to_io_error)
+    }
+
+    fn exists(&self, name: &str) -> io::Result<bool> {
+        let result = self
+            .runtime
+            .block_on(self.client.head_object(HeadObjectRequest {
+                bucket: self.bucket.clone(),
+                key: String::from(name),
+                ..Default::default()
+            }));
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(RusotoError::Service(HeadObjectError::NoSuchKey(_))) => Ok(false),
+            // Responses to HEAD requests have no body, so a missing object is only recognizable by its status.
+            Err(RusotoError::Unknown(ref response)) if response.status.as_u16() == 404 => Ok(false),
+            Err(error) => Err(to_io_error(error)),
+        }
+    }
+}
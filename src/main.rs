@@ -1,8 +1,10 @@
 #![feature(proc_macro_hygiene, decl_macro)]
 #![feature(drain_filter)]
 
+mod admin;
 mod configuration;
 mod database;
+mod error;
 mod facilities;
 mod images;
 #[cfg(feature = "testpages")]
@@ -11,8 +13,11 @@ mod testpages;
 use rocket::Route;
 
 use crate::{
-    configuration::check_required_configuration, database::DatabaseConnection,
-    facilities::facilites_routes, images::image_routes,
+    configuration::{check_required_configuration, STORAGE_BACKEND},
+    database::{DatabaseConnection, StorageBackend},
+    error::{error_catchers, request_id_fairing},
+    facilities::{facilites_routes, load_attribute_catalogue},
+    images::image_routes,
 };
 
 /// The routes for pages to test the features.
@@ -30,6 +35,7 @@ fn testpage_routes() -> Option<Vec<Route>> {
 /// The main entry point of the server.
 fn main() {
     check_required_configuration();
+    load_attribute_catalogue();
 
     unsafe {
         signal_hook::register(signal_hook::SIGINT, || {
@@ -39,9 +45,14 @@ fn main() {
     }
 
     let mut rocket = rocket::ignite()
-        .attach(DatabaseConnection::fairing())
         .mount("/facilities", facilites_routes())
-        .mount("/images", image_routes());
+        .mount("/images", image_routes())
+        .register(error_catchers())
+        .attach(request_id_fairing());
+
+    if *STORAGE_BACKEND == StorageBackend::MongoDB {
+        rocket = rocket.attach(DatabaseConnection::fairing());
+    }
 
     if let Some(routes) = testpage_routes() {
         rocket = rocket.mount("/testpages", routes)
@@ -6,6 +6,8 @@ use rocket::{
     routes, Route,
 };
 
+use crate::images::ACCEPTED_IMAGE_TYPES;
+
 /// The routes for all the different test pages.
 pub(crate) fn testpage_routes() -> Vec<Route> {
     routes![
@@ -51,8 +53,10 @@ fn testpage_index() -> Html<&'static str> {
 }
 
 /// The test page for uploading images.
+///
+/// Only the image types that the backend can decode are offered.
 #[get("/imageupload")]
-fn testpage_image_upload() -> Html<&'static str> {
+fn testpage_image_upload() -> Html<String> {
     Html(r#"
         <html>
             <head>
@@ -61,11 +65,11 @@ fn testpage_image_upload() -> Html<&'static str> {
             <body>
                 <h1>Image upload</h1>
                 <form method="post" action="../images/upload/TEST_SOURCE_ID/TEST_ORIGINAL_ID?lat=52.526159&lon=13.400332" enctype="multipart/form-data">
-                    <input type="file" name="image" accept="image/jpeg" multiple/>
+                    <input type="file" name="image" accept="ACCEPTED_IMAGE_TYPES" multiple/>
                     <input type="submit"/>
                 </form>
             </body>
-        </html>"#
+        </html>"#.replace("ACCEPTED_IMAGE_TYPES", &ACCEPTED_IMAGE_TYPES.join(","))
     )
 }
 
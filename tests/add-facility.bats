@@ -8,7 +8,7 @@ load framework
 #      if the connection to the database works.
 @test "Add facility" {
   # test that facility does not exist
-  expect get facilities/by-radius/11/10/1 '{"result":"success", "featureCount": 0, "features": []}'
+  expect get facilities/by-radius/11/10/1 '{"result":"success", "featureCount": 0, "features": [], "nextCursor": null}'
 
   # add facility
   create-facility "Foobar" 10 11
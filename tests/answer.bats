@@ -0,0 +1,76 @@
+#!/usr/bin/env bats
+
+load framework
+
+question-id() {
+  local attribute=$1
+
+  local request=$(cat <<JSON
+{
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "answer"
+    },
+    "search": { "lat": 10, "lon": 11, "radius": 100 }
+}
+JSON
+)
+  extract-field "$(request post facilities/will-visit "$request")" ".questions[] | select(.attribute == \"$attribute\") | .id"
+}
+
+# This test ensures that
+#   1. questions can be answered by their ID
+#   2. every answer gets its own result
+#   3. answering with the current value verifies it
+@test "Answer questions" {
+  local request=$(cat <<JSON
+{
+    "createNewFacility": false,
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "answer"
+    },
+    "lat": 10,
+    "lon": 11,
+    "name": "Foobar"
+}
+JSON
+)
+  expect post facilities/set-facility '{"result":"success"}' "$request"
+
+  local wheelchair_id=$(question-id wheelchairAccess)
+  local fee_id=$(question-id fee)
+
+  local request=$(cat <<JSON
+{
+    "answers": [
+        { "questionId": "$wheelchair_id", "value": "noSteps" },
+        { "questionId": "$fee_id", "value": "unknownValue" },
+        { "questionId": "invalid", "value": true }
+    ]
+}
+JSON
+)
+  local result=$(request post facilities/answer "$request")
+  field-equals "$result" .result "success"
+  field-equals "$result" .results[0].result "success"
+  field-equals "$result" .results[0].questionId "$wheelchair_id"
+  field-equals "$result" .results[1].result "failure"
+  field-equals "$result" .results[2].result "failure"
+
+  [ "$(question-id wheelchairAccess)" = "$wheelchair_id" ]
+
+  local request=$(cat <<JSON
+{
+    "answers": [
+        { "questionId": "$wheelchair_id", "value": "noSteps" }
+    ]
+}
+JSON
+)
+  field-equals "$(request post facilities/answer "$request")" .results[0].result "success"
+
+  local result=$(request get "facilities/by-id/$TONARI_SOURCE_ID/answer")
+  field-equals "$result" .features[0].properties.accessibility.accessibleWith.wheelchair "noSteps"
+  field-equals "$result" '.features[0].properties.verifications.wheelchairAccess | length' "2"
+}
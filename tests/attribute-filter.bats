@@ -0,0 +1,85 @@
+#!/usr/bin/env bats
+
+load framework
+
+set-accessibility() {
+  local originalId=$1
+  local accessibility=$2
+
+  local request=$(cat <<JSON
+{
+    "createNewFacility": false,
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "$originalId"
+    },
+    "lat": 10,
+    "lon": 11,
+    "accessibility": $accessibility
+}
+JSON
+)
+  expect post facilities/set-facility '{"result":"success"}' "$request"
+}
+
+# This test ensures that
+#   1. tile and radius searches only return facilities with the requested attribute values
+#   2. unknown attributes and invalid values are rejected
+@test "Attribute filter" {
+  set-accessibility "no-steps" '{ "accessibleWith": { "wheelchair": "noSteps" }, "fee": false }'
+  set-accessibility "one-step" '{ "accessibleWith": { "wheelchair": "oneStep" }, "fee": false }'
+  set-accessibility "with-fee" '{ "accessibleWith": { "wheelchair": "noSteps" }, "fee": true }'
+
+  local result=$(request get "facilities/by-radius/11/10/100?wheelchairAccess=noSteps&fee=false")
+  field-equals "$result" .result "success"
+  field-equals "$result" .featureCount "1"
+  field-equals "$result" .features[0].properties.originalId "no-steps"
+
+  local result=$(request get "facilities/by-tile/0/0/0?wheelchairAccess=noSteps")
+  field-equals "$result" .result "success"
+  field-equals "$result" .featureCount "2"
+
+  local result=$(request get "facilities/by-tile/0/0/0?wheelchairAccess=noSteps&wheelchairAccess=oneStep&fee=false")
+  field-equals "$result" .result "success"
+  field-equals "$result" .featureCount "2"
+
+  local result=$(request get "facilities/by-tile/0/0/0?wheelchairAccess=banana&colour=blue")
+  field-equals "$result" .result "failure"
+  field-equals "$result" .unknownAttributes[0] "colour"
+  field-equals "$result" .invalidValues[0].attribute "wheelchairAccess"
+  field-equals "$result" .invalidValues[0].value "banana"
+}
+
+# This test ensures that
+#   1. numeric attributes can be filtered by ranges
+#   2. values outside of the bounds of an attribute are rejected
+@test "Numeric attribute filter" {
+  set-accessibility "narrow" '{ "doorWidth": 70, "steps": 2 }'
+  set-accessibility "wide" '{ "doorWidth": 95.5, "steps": 0 }'
+  set-accessibility "very-wide" '{ "doorWidth": 150, "steps": 0 }'
+
+  local result=$(request get "facilities/by-radius/11/10/100?doorWidth%3E=90")
+  field-equals "$result" .result "success"
+  field-equals "$result" .featureCount "2"
+
+  local result=$(request get "facilities/by-radius/11/10/100?doorWidth%3E=90&doorWidth%3C=120&steps=0")
+  field-equals "$result" .featureCount "1"
+  field-equals "$result" .features[0].properties.originalId "wide"
+
+  local result=$(request get "facilities/by-tile/0/0/0?doorWidth%3E=banana&fee%3E=true")
+  field-equals "$result" .result "failure"
+  field-equals "$result" '.invalidValues | length' "2"
+
+  local request=$(cat <<JSON
+{
+    "createNewFacility": true,
+    "lat": 10,
+    "lon": 11,
+    "accessibility": { "doorWidth": 500, "steps": 1.5 }
+}
+JSON
+)
+  local result=$(request post facilities/set-facility "$request")
+  field-equals "$result" .result "failure"
+  field-equals "$result" '.invalidValues | length' "2"
+}
@@ -0,0 +1,41 @@
+#!/usr/bin/env bats
+
+load framework
+
+# This test ensures that
+#   1. the attribute catalogue is served
+#   2. the attributes contain their domain, database path and labels
+@test "Attribute catalogue" {
+  local result=$(request get facilities/attributes)
+  is-json "$result"
+  field-equals "$result" .result "success"
+  field-equals "$result" '.attributes | length' "17"
+  field-equals "$result" '.attributes[] | select(.name == "wheelchairAccess") | .domain.type' "enum"
+  field-equals "$result" '.attributes[] | select(.name == "fee") | .databasePath' "properties.accessibility.fee"
+  field-equals "$result" '.attributes[] | select(.name == "fee") | .labels.en' "Fee"
+}
+
+# Starts the backend with the given attribute catalogue and prints its output once it has stopped.
+run-with-catalogue() {
+  local catalogue=$1
+
+  local tmpdir=$(mktemp -d)
+  echo "$catalogue" > "$tmpdir/catalogue.json"
+  docker run --rm -v "$tmpdir:/catalogue" -eTONARI_ATTRIBUTE_CATALOGUE=/catalogue/catalogue.json -eTONARI_{SOURCE_ID,IMAGE_URL_PREFIX,IMAGE_PATH} tonari/backend 2>&1 || true
+  rm -r "$tmpdir"
+}
+
+# This test ensures that catalogues whose attributes would overwrite each other or data outside the
+# accessibility of facilities are rejected at startup.
+@test "Invalid attribute catalogue" {
+  local output=$(run-with-catalogue '[
+    { "name": "key", "domain": { "type": "boolean" }, "databasePath": "properties.accessibility.key" },
+    { "name": "keyType", "domain": { "type": "boolean" }, "databasePath": "properties.accessibility.key.type" }
+  ]')
+  [[ "$output" == *"overlaps the path"* ]]
+
+  local output=$(run-with-catalogue '[
+    { "name": "name", "domain": { "type": "boolean" }, "databasePath": "properties.name" }
+  ]')
+  [[ "$output" == *"must be a dotted path below"* ]]
+}
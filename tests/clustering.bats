@@ -0,0 +1,48 @@
+#!/usr/bin/env bats
+
+load framework
+
+setup() {
+  export TONARI_CLUSTER_MIN_FEATURES=3
+  containers-run
+  await-http
+}
+
+# This test ensures that
+#   1. low zoom tiles with enough facilities are answered with clusters
+#   2. the clusters count the facilities in the cells of the grid
+#   3. clustering can be disabled
+@test "Clustering" {
+  create-facility "Foobar 1" 10 11
+  create-facility "Foobar 2" 10.001 11.001
+  create-facility "Foobar 3" -30 -40
+
+  local result=$(request get facilities/by-tile/0/0/0)
+  field-equals "$result" .result "success"
+  field-equals "$result" .clustered "true"
+  field-equals "$result" .featureCount "2"
+  field-equals "$result" '[.features[].properties.count] | add' "3"
+  field-equals "$result" '[.features[].properties.breakdown.wheelchairAccess.unknown] | add' "3"
+  field-equals "$result" '.features[0].properties.tile.z' "3"
+
+  local result=$(request get "facilities/by-tile/0/0/0?cluster=false")
+  field-equals "$result" .result "success"
+  field-equals "$result" .featureCount "3"
+  field-equals "$result" .clustered "null"
+}
+
+# This test ensures that low zoom vector tiles with enough facilities contain clusters instead of the facilities
+@test "Clustered vector tile" {
+  create-facility "Foobar 1" 10 11
+  create-facility "Foobar 2" 10.001 11.001
+  create-facility "Foobar 3" -30 -40
+
+  local tmpdir=$(mktemp -d)
+  curl -sS --max-time 5 -o "$tmpdir/tile.mvt" "http://$TONARI_IP:8000/facilities/by-tile/0/0/0.mvt"
+
+  grep -q "clusters" "$tmpdir/tile.mvt"
+  grep -q "breakdown.wheelchairAccess.unknown" "$tmpdir/tile.mvt"
+  ! grep -q "Foobar" "$tmpdir/tile.mvt"
+
+  rm -r "$tmpdir"
+}
@@ -0,0 +1,125 @@
+#!/usr/bin/env bats
+
+load framework
+
+dispute-wheelchair-access() {
+  local proposal=$1
+
+  local request=$(cat <<JSON
+{
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "dispute"
+    },
+    "attribute": "wheelchairAccess",
+    "value": $proposal
+}
+JSON
+)
+  request post facilities/dispute-attribute "$request"
+}
+
+dispute-image-label() {
+  local request=$(cat <<JSON
+{
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "dispute"
+    },
+    "imageURL": "https://example.com/image.jpg",
+    "imageLabel": "toilet",
+    "proposedLabel": "sink"
+}
+JSON
+)
+  request post images/dispute-label "$request"
+}
+
+# This test ensures that
+#   1. disputes are recorded without changing the value at first
+#   2. enough disputes replace the value with the value proposed by the majority
+@test "Dispute an attribute" {
+  local request=$(cat <<JSON
+{
+    "createNewFacility": false,
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "dispute"
+    },
+    "lat": 10,
+    "lon": 11,
+    "accessibility": { "accessibleWith": { "wheelchair": "noSteps" } }
+}
+JSON
+)
+  expect post facilities/set-facility '{"result":"success"}' "$request"
+
+  field-equals "$(dispute-wheelchair-access '"oneStep"')" .value "noSteps"
+  field-equals "$(dispute-wheelchair-access '"oneStep"')" .value "noSteps"
+  field-equals "$(dispute-wheelchair-access null)" .value "oneStep"
+
+  local result=$(request get "facilities/by-id/$TONARI_SOURCE_ID/dispute")
+  field-equals "$result" .features[0].properties.accessibility.accessibleWith.wheelchair "oneStep"
+
+  field-equals "$(dispute-wheelchair-access '"unknownValue"')" .result "failure"
+}
+
+# This test ensures that enough disputes of an image label unset its verification and replace it
+@test "Dispute an image label" {
+  local request=$(cat <<JSON
+{
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "dispute"
+    },
+    "imageURL": "https://example.com/image.jpg",
+    "imageLabel": "toilet",
+    "lat": 10,
+    "lon": 11
+}
+JSON
+)
+  expect post images/set-label '{"result":"success"}' "$request"
+  expect post images/verify-label '{"result":"success"}' "$request"
+
+  field-equals "$(dispute-image-label)" .imageLabel "toilet"
+  field-equals "$(dispute-image-label)" .imageLabel "toilet"
+  field-equals "$(dispute-image-label)" .imageLabel "sink"
+
+  local result=$(request get "facilities/by-id/$TONARI_SOURCE_ID/dispute")
+  field-equals "$result" .features[0].properties.images[0].label "sink"
+  field-equals "$result" .features[0].properties.images[0].labelVerified "false"
+}
+
+# This test ensures that
+#   1. verifying an image label discards its earlier disputes
+#   2. the times of label disputes are returned as RFC 3339 strings
+@test "Verify a disputed image label" {
+  local request=$(cat <<JSON
+{
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "dispute"
+    },
+    "imageURL": "https://example.com/image.jpg",
+    "imageLabel": "toilet",
+    "lat": 10,
+    "lon": 11
+}
+JSON
+)
+  expect post images/set-label '{"result":"success"}' "$request"
+
+  field-equals "$(dispute-image-label)" .imageLabel "toilet"
+  field-equals "$(dispute-image-label)" .imageLabel "toilet"
+
+  local result=$(request get "facilities/by-id/$TONARI_SOURCE_ID/dispute")
+  field-equals "$result" '.features[0].properties.images[0].labelDisputes[0].timestamp | test("^[0-9-]+T[0-9:.]+Z$")' "true"
+
+  expect post images/verify-label '{"result":"success"}' "$request"
+  field-equals "$(dispute-image-label)" .imageLabel "toilet"
+
+  local result=$(request get "facilities/by-id/$TONARI_SOURCE_ID/dispute")
+  field-equals "$result" .features[0].properties.images[0].label "toilet"
+  field-equals "$result" '.features[0].properties.images[0].labelDisputes | length' "1"
+}
@@ -0,0 +1,45 @@
+#!/usr/bin/env bats
+
+load framework
+
+# Performs a GET request and prints the HTTP status in the last line after the body.
+request-with-status() {
+  local path_=$1
+
+  curl -sS --max-time 5 --connect-timeout 5 -w '\n%{http_code}' "http://$TONARI_IP:8000/$path_"
+}
+
+# This test ensures that
+#   1. errors are answered with a matching HTTP status
+#   2. all errors share the same format, including those of unknown routes
+#   3. the request ID in the error matches the header of the response
+@test "Error format" {
+  local response=$(request-with-status "facilities/by-id/$TONARI_SOURCE_ID/missing")
+  local result=$(echo "$response" | head -n -1)
+  [ "$(echo "$response" | tail -n 1)" = "404" ]
+  field-equals "$result" .result "entryNotFound"
+  field-equals "$result" .error.code "notFound"
+  field-exists "$result" .error.message
+  field-exists "$result" .error.requestId
+
+  local response=$(request-with-status "facilities/by-source-id/$TONARI_SOURCE_ID?limit=0")
+  local result=$(echo "$response" | head -n -1)
+  [ "$(echo "$response" | tail -n 1)" = "400" ]
+  field-equals "$result" .result "failure"
+  field-equals "$result" .error.code "invalidRequest"
+
+  local response=$(request-with-status "unknown-route")
+  local result=$(echo "$response" | head -n -1)
+  [ "$(echo "$response" | tail -n 1)" = "404" ]
+  field-equals "$result" .error.code "notFound"
+
+  local status=$(curl -sS -o /dev/null -w '%{http_code}' -d '{ "bbox": ' -H 'Content-Type: application/json' "http://$TONARI_IP:8000/facilities/within")
+  [ "$status" = "400" ] || [ "$status" = "422" ]
+
+  local headers=$(mktemp)
+  local result=$(curl -sS -D "$headers" "http://$TONARI_IP:8000/facilities/by-id/$TONARI_SOURCE_ID/missing")
+  local header_id=$(tr -d '\r' < "$headers" | grep -i '^x-request-id:' | cut -d ' ' -f 2)
+  rm "$headers"
+  [ -n "$header_id" ]
+  field-equals "$result" .error.requestId "$header_id"
+}
@@ -16,13 +16,22 @@ container-ip() {
 containers-stop() {
   [ -n "${TONARI:-}" ] && container-stop "$TONARI"
   [ -n "${MONGO:-}" ] && container-stop "$MONGO"
+  if [ -n "${MINIO:-}" ]; then
+    container-stop "$MINIO"
+  fi
 }
 
 containers-run() {
   export MONGO
   MONGO=$(docker run --rm -d mongo --wiredTigerCacheSizeGB 1.5)
+  export MONGO_IP
   MONGO_IP=$(container-ip "$MONGO")
 
+  tonari-run
+}
+
+# start the backend, connected to the already running MongoDB container
+tonari-run() {
   export TONARI_SOURCE_ID=${TONARI_SOURCE_ID:-00000000000000000000000000000000}
   export TONARI_IMAGE_URL_PREFIX=${TONARI_IMAGE_URL_PREFIX:-https://tonari.app/api/images/}
   export TONARI_IMAGE_PATH=${TONARI_IMAGE_PATH:-/images}
@@ -33,7 +42,7 @@ containers-run() {
   ROCKET_SECRET_KEY_DEFAULT=$(openssl rand -base64 32)
   export ROCKET_SECRET_KEY=${ROCKET_SECRET_KEY:-$ROCKET_SECRET_KEY_DEFAULT}
   export TONARI
-  TONARI=$(docker run --rm -d -eTONARI_{SOURCE_ID,IMAGE_URL_PREFIX,IMAGE_PATH,INITIALIZE_DB} -eROCKET_{DATABASES,PORT,SECRET_KEY} tonari/backend)
+  TONARI=$(docker run --rm -d -eTONARI_{STORAGE_BACKEND,SOURCE_ID,IMAGE_URL_PREFIX,IMAGE_PATH,IMAGE_STORAGE_BACKEND,S3_BUCKET,S3_ENDPOINT,S3_ACCESS_KEY,S3_SECRET_KEY,INITIALIZE_DB,MIGRATE_TIMESTAMPS,MIGRATE_FEE_PATH,MIGRATE_VERIFIED_ATTRIBUTES,MAX_VOTES_PER_ATTRIBUTE,CLUSTER_MIN_FEATURES,ADMIN_TOKEN,IMAGE_MAX_PIXELS} -eROCKET_{DATABASES,PORT,SECRET_KEY} tonari/backend)
   export TONARI_IP
   TONARI_IP=$(container-ip "$TONARI")
 }
@@ -124,6 +133,11 @@ now() {
   date +%s%3N
 }
 
+# milliseconds since epoch, in the format accepted by timestamp parameters
+now-timestamp() {
+  echo "$(now)ms"
+}
+
 # repeat a command until it succeeds, or until timeout
 await() {
   local timeout=$1; shift
@@ -0,0 +1,54 @@
+#!/usr/bin/env bats
+
+load framework
+
+set-wheelchair-access() {
+  local value=$1
+
+  local request=$(cat <<JSON
+{
+    "createNewFacility": false,
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "history"
+    },
+    "lat": 10,
+    "lon": 11,
+    "accessibility": { "accessibleWith": { "wheelchair": "$value" } }
+}
+JSON
+)
+  expect post facilities/set-facility '{"result":"success"}' "$request"
+}
+
+# This test ensures that
+#   1. every change to a facility is recorded with its operation, upserts as creating the facility
+#   2. the values before and after a change are recorded
+@test "Facility history" {
+  set-wheelchair-access noSteps
+  set-wheelchair-access oneStep
+
+  local request=$(cat <<JSON
+{
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "history"
+    },
+    "attributes": ["wheelchairAccess"],
+    "lat": 10,
+    "lon": 11
+}
+JSON
+)
+  request post facilities/verify-attributes "$request"
+
+  local result=$(request get "facilities/history/$TONARI_SOURCE_ID/history")
+  is-json "$result"
+  field-equals "$result" .result "success"
+  field-equals "$result" .changeCount "3"
+  field-equals "$result" .changes[0].operation "createFacility"
+  field-equals "$result" '.changes[1].changes[] | select(.path == "properties.accessibility.accessibleWith.wheelchair") | .before' "noSteps"
+  field-equals "$result" '.changes[1].changes[] | select(.path == "properties.accessibility.accessibleWith.wheelchair") | .after' "oneStep"
+  field-equals "$result" .changes[2].operation "verifyAttributes"
+  field-exists "$result" .changes[2].timestamp
+}
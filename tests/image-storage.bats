@@ -0,0 +1,87 @@
+#!/usr/bin/env bats
+
+load framework
+
+setup() {
+  export TONARI_S3_ACCESS_KEY=minio-access-key
+  export TONARI_S3_SECRET_KEY=minio-secret-key
+  export TONARI_S3_BUCKET=tonari-images
+
+  export MINIO
+  MINIO=$(docker run --rm -d -e MINIO_ROOT_USER="$TONARI_S3_ACCESS_KEY" -e MINIO_ROOT_PASSWORD="$TONARI_S3_SECRET_KEY" minio/minio server /data)
+  local minio_ip=$(container-ip "$MINIO")
+  await 10000 curl -sSf --max-time 1 "http://$minio_ip:9000/minio/health/live"
+  docker run --rm --entrypoint sh minio/mc -c "mc alias set local http://$minio_ip:9000 $TONARI_S3_ACCESS_KEY $TONARI_S3_SECRET_KEY && mc mb local/$TONARI_S3_BUCKET" >/dev/null
+
+  export TONARI_IMAGE_STORAGE_BACKEND=s3
+  export TONARI_S3_ENDPOINT="http://$minio_ip:9000"
+  containers-run
+  await-http
+}
+
+mongo-eval() {
+  docker exec "$MONGO" sh -c 'if command -v mongosh >/dev/null; then mongosh --quiet sanitary_facilities --eval "$0"; else mongo --quiet sanitary_facilities --eval "$0"; fi' "$1"
+}
+
+bucket-objects() {
+  docker exec "$MINIO" ls "/data/$TONARI_S3_BUCKET"
+}
+
+# This test ensures that
+#   1. images can be uploaded to an S3-compatible object storage
+#   2. all sizes of the image are stored in the bucket
+#   3. images can be downloaded from the bucket
+#   4. unused image IDs are recognized, even though MinIO answers HEAD requests for missing objects without a body
+@test "Image storage in S3" {
+  local tmpdir=$(mktemp -d)
+  convert -size 2000x1000 xc:blue "$tmpdir/image.jpg"
+
+  local result=$(request post-multipart "/images/upload/$TONARI_SOURCE_ID/s3?lat=10&lon=11" image=@"$tmpdir/image.jpg;type=image/jpeg")
+  field-equals "$result" .results[0].result "success"
+  local imageId=$(extract-field "$result" .results[0].id)
+
+  local objects=$(bucket-objects)
+  [[ "$objects" == *"$imageId.jpg"* ]]
+  [[ "$objects" == *"$imageId-thumb.jpg"* ]]
+  [[ "$objects" == *"$imageId-medium.jpg"* ]]
+
+  request get "/images/$imageId" > "$tmpdir/full.jpg"
+  [ "$(identify -format '%w %h' "$tmpdir/full.jpg")" = "2000 1000" ]
+  request get "/images/$imageId?size=medium" > "$tmpdir/medium.jpg"
+  [ "$(identify -format '%w %h' "$tmpdir/medium.jpg")" = "1280 640" ]
+
+  convert -size 200x100 xc:red "$tmpdir/other.jpg"
+  local result=$(request post-multipart "/images/upload/$TONARI_SOURCE_ID/s3?lat=10&lon=11" image=@"$tmpdir/other.jpg;type=image/jpeg")
+  field-equals "$result" .results[0].result "success"
+  [ "$(extract-field "$result" .results[0].id)" != "$imageId" ]
+  [ "$(bucket-objects | wc -w)" = "6" ]
+
+  rm -r "$tmpdir"
+}
+
+# This test ensures that the stored sizes of an image are deleted if the image can't be added to its facility
+@test "Failed upload to S3" {
+  # `$push` fails for images that are not an array, which happens after all sizes were stored
+  mongo-eval 'db.facilities.insertOne({
+    type: "Feature",
+    geometry: { type: "Point", coordinates: [11, 10] },
+    properties: { sourceId: "'"$TONARI_SOURCE_ID"'", originalId: "broken", images: "broken" }
+  })'
+
+  local tmpdir=$(mktemp -d)
+  convert -size 200x100 xc:red "$tmpdir/image.jpg"
+
+  local status=$(curl -sS -o "$tmpdir/result.json" -w '%{http_code}' -F image=@"$tmpdir/image.jpg;type=image/jpeg" "http://$TONARI_IP:8000/images/upload/$TONARI_SOURCE_ID/broken?lat=10&lon=11")
+  [ "$status" = "500" ]
+  field-equals "$(cat "$tmpdir/result.json")" .results[0].result "internalError"
+
+  [ -z "$(bucket-objects)" ]
+
+  rm -r "$tmpdir"
+}
+
+# This test ensures that images that are not in the bucket are not found.
+@test "Missing image in S3" {
+  local status=$(curl -sS -o /dev/null -w '%{http_code}' "http://$TONARI_IP:8000/images/00000000-0000-0000-0000-000000000000")
+  [ "$status" = "404" ]
+}
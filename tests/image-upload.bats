@@ -5,9 +5,10 @@ load framework
 # This test ensures that
 #   1. images can be uploaded
 #   2. images can be downloaded
-#   3. images are unmodified after uploading and downloading them
+#   3. images keep their resolution after uploading and downloading them
 #   4. the url property of images gets properly set
 #   5. the id identifies the image properly
+#   6. smaller sizes of the image are generated and listed
 @test "Image upload" {
   # add facility
   create-facility "Foobar" 10 11
@@ -42,7 +43,151 @@ load framework
   local url=$(extract-field "$result" .features[0].properties.images[0].url)
   diff <(echo "$url") <(echo "https://tonari.app/api/images/$imageId")
 
-  diff <(request get "/images/$imageId") "$tmpdir/image.jpg"
+  request get "/images/$imageId" > "$tmpdir/full.jpg"
+  [ "$(identify -format '%w %h' "$tmpdir/full.jpg")" = "1024 1024" ]
+
+  local thumbUrl=$(extract-field "$result" .features[0].properties.images[0].variants.thumb)
+  diff <(echo "$thumbUrl") <(echo "https://tonari.app/api/images/$imageId?size=thumb")
+  field-equals "$result" .features[0].properties.images[0].variants.full "$url"
+
+  request get "/images/$imageId?size=thumb" > "$tmpdir/thumb.jpg"
+  [ "$(identify -format '%w %h' "$tmpdir/thumb.jpg")" = "256 256" ]
+  request get "/images/$imageId?size=medium" > "$tmpdir/medium.jpg"
+  [ "$(identify -format '%w %h' "$tmpdir/medium.jpg")" = "1024 1024" ]
+
+  rm -r "$tmpdir"
+}
+
+# This test ensures that
+#   1. the metadata of uploaded images is removed
+#   2. the orientation of uploaded images is applied before
+@test "Image metadata" {
+  local tmpdir=$(mktemp -d)
+  # a landscape image that is displayed as a portrait image because of its orientation
+  convert -size 200x100 xc:white -orient RightTop -set comment "secret" "$tmpdir/image.jpg"
+  [ "$(identify -format '%[EXIF:Orientation]' "$tmpdir/image.jpg")" = "6" ]
+
+  local result=$(request post-multipart "/images/upload/$TONARI_SOURCE_ID/metadata?lat=10&lon=11" image=@"$tmpdir/image.jpg;type=image/jpeg")
+  field-equals "$result" .results[0].result "success"
+  local imageId=$(extract-field "$result" .results[0].id)
+
+  request get "/images/$imageId" > "$tmpdir/full.jpg"
+  [ "$(identify -format '%w %h' "$tmpdir/full.jpg")" = "100 200" ]
+  [ -z "$(identify -format '%[EXIF:*]%c' "$tmpdir/full.jpg")" ]
+
+  rm -r "$tmpdir"
+}
+
+# This test ensures that
+#   1. PNG images can be uploaded and are stored as JPEG
+#   2. the format is determined from the content instead of the content type
+#   3. images in unsupported formats are rejected with the accepted types
+@test "Image formats" {
+  local tmpdir=$(mktemp -d)
+  convert -size 300x200 xc:red "$tmpdir/image.png"
+
+  local result=$(request post-multipart "/images/upload/$TONARI_SOURCE_ID/formats?lat=10&lon=11" image=@"$tmpdir/image.png;type=application/octet-stream")
+  field-equals "$result" .results[0].result "success"
+  local imageId=$(extract-field "$result" .results[0].id)
+
+  request get "/images/$imageId" > "$tmpdir/full.jpg"
+  [ "$(identify -format '%m %w %h' "$tmpdir/full.jpg")" = "JPEG 300 200" ]
+
+  echo "not an image" > "$tmpdir/image.txt"
+  local status=$(curl -sS -o "$tmpdir/result.json" -w '%{http_code}' -F image=@"$tmpdir/image.txt;type=image/jpeg" "http://$TONARI_IP:8000/images/upload/$TONARI_SOURCE_ID/formats?lat=10&lon=11")
+  [ "$status" = "415" ]
+  local result=$(cat "$tmpdir/result.json")
+  field-equals "$result" .error.code "unsupportedMediaType"
+  field-equals "$result" .results[0].result "unsupportedFormat"
+  field-equals "$result" '.results[0].acceptedTypes | index("image/png") != null' "true"
+
+  rm -r "$tmpdir"
+}
+
+# This test ensures that files that look like a supported image but can't be read are rejected as invalid
+@test "Unreadable images" {
+  local tmpdir=$(mktemp -d)
+  convert -size 300x200 plasma: "$tmpdir/image.png"
+  head -c 200 "$tmpdir/image.png" > "$tmpdir/truncated.png"
+
+  local status=$(curl -sS -o "$tmpdir/result.json" -w '%{http_code}' -F image=@"$tmpdir/truncated.png;type=image/png" "http://$TONARI_IP:8000/images/upload/$TONARI_SOURCE_ID/unreadable?lat=10&lon=11")
+  [ "$status" = "400" ]
+  local result=$(cat "$tmpdir/result.json")
+  field-equals "$result" .error.code "invalidRequest"
+  field-equals "$result" .results[0].result "invalidImage"
+
+  rm -r "$tmpdir"
+}
+
+# This test ensures that images with too many pixels are rejected before they are decoded
+@test "Image pixel limit" {
+  container-stop "$TONARI"
+  export TONARI_IMAGE_MAX_PIXELS=10000
+  tonari-run
+  await-http
+
+  local tmpdir=$(mktemp -d)
+  convert -size 200x100 xc:blue "$tmpdir/large.png"
+  convert -size 100x100 xc:blue "$tmpdir/small.png"
+
+  local status=$(curl -sS -o "$tmpdir/result.json" -w '%{http_code}' -F image=@"$tmpdir/large.png;type=image/png" "http://$TONARI_IP:8000/images/upload/$TONARI_SOURCE_ID/pixels?lat=10&lon=11")
+  [ "$status" = "413" ]
+  field-equals "$(cat "$tmpdir/result.json")" .results[0].result "tooLarge"
+
+  local result=$(request post-multipart "/images/upload/$TONARI_SOURCE_ID/pixels?lat=10&lon=11" image=@"$tmpdir/small.png;type=image/png")
+  field-equals "$result" .results[0].result "success"
+
+  rm -r "$tmpdir"
+}
+
+is-red() {
+  local file=$1
+
+  [ "$(convert "$file" -resize '1x1!' -format '%[fx:r > 0.9 && g < 0.1 && b < 0.1]' info:)" = "1" ]
+}
+
+# This test ensures that lossy and lossless WebP images keep their colors
+@test "Color WebP images" {
+  local tmpdir=$(mktemp -d)
+  convert -size 300x200 xc:red -quality 80 "$tmpdir/lossy.webp"
+  convert -size 300x200 xc:red -define webp:lossless=true "$tmpdir/lossless.webp"
+
+  for file in lossy lossless; do
+    local result=$(request post-multipart "/images/upload/$TONARI_SOURCE_ID/webp?lat=10&lon=11" image=@"$tmpdir/$file.webp;type=image/webp")
+    field-equals "$result" .results[0].result "success"
+
+    request get "/images/$(extract-field "$result" .results[0].id)" > "$tmpdir/$file.jpg"
+    [ "$(identify -format '%m %w %h' "$tmpdir/$file.jpg")" = "JPEG 300 200" ]
+    is-red "$tmpdir/$file.jpg"
+  done
+
+  rm -r "$tmpdir"
+}
+
+# This test ensures that
+#   1. uploading the same file again for a facility returns the existing image
+#   2. the same file can still be uploaded for other facilities
+#   3. the hashes used to recognize the files are not returned
+@test "Image deduplication" {
+  local tmpdir=$(mktemp -d)
+  convert -size 100x100 xc:green "$tmpdir/image.jpg"
+
+  local result=$(request post-multipart "/images/upload/$TONARI_SOURCE_ID/dedup?lat=10&lon=11" image=@"$tmpdir/image.jpg;type=image/jpeg")
+  field-equals "$result" .results[0].result "success"
+  local imageId=$(extract-field "$result" .results[0].id)
+
+  local result=$(request post-multipart "/images/upload/$TONARI_SOURCE_ID/dedup?lat=10&lon=11" image=@"$tmpdir/image.jpg;type=image/jpeg")
+  field-equals "$result" .result "success"
+  field-equals "$result" .results[0].result "duplicate"
+  field-equals "$result" .results[0].id "$imageId"
+
+  local result=$(request get "facilities/by-id/$TONARI_SOURCE_ID/dedup")
+  field-equals "$result" '.features[0].properties.images | length' "1"
+  field-equals "$result" '.features[0].properties.images[0] | has("sha256")' "false"
+
+  local result=$(request post-multipart "/images/upload/$TONARI_SOURCE_ID/other?lat=10&lon=11" image=@"$tmpdir/image.jpg;type=image/jpeg")
+  field-equals "$result" .results[0].result "success"
+  [ "$(extract-field "$result" .results[0].id)" != "$imageId" ]
 
   rm -r "$tmpdir"
 }
@@ -4,7 +4,7 @@ load framework
 
 # This test ensures that map tile queries return results only in their tile
 @test "Map tile" {
-  expect get facilities/by-tile/16/15/5 '{"result":"success", "featureCount": 0, "features": []}'
+  expect get facilities/by-tile/16/15/5 '{"result":"success", "featureCount": 0, "features": [], "nextCursor": null}'
 
   # add two facilities
   create-facility "Foobar" 10 11
@@ -0,0 +1,120 @@
+#!/usr/bin/env bats
+
+load framework
+
+setup() {
+  export TONARI_STORAGE_BACKEND=memory
+  export TONARI_ADMIN_TOKEN=secret
+  containers-run
+  await-http
+}
+
+# This test ensures that facilities can be created and found without MongoDB
+@test "In-memory facility queries" {
+  create-facility "Memory Station" 10 11
+  create-facility "Memory Hall" 10 11.0001
+  create-facility "Faraway" 40 41
+
+  local result=$(request get facilities/by-radius/11/10/1000)
+  is-json "$result"
+  field-equals "$result" .result "success"
+  field-equals "$result" .featureCount "2"
+
+  local result=$(request get "facilities/nearest/11/10?count=1")
+  field-equals "$result" .featureCount "1"
+  field-equals "$result" .features[0].properties.name "Memory Station"
+
+  local result=$(request get "facilities/search?q=station")
+  field-equals "$result" .featureCount "1"
+  field-equals "$result" .features[0].properties.name "Memory Station"
+}
+
+# This test ensures that results of the in-memory store are paginated
+@test "In-memory pagination" {
+  create-facility "Memory Station" 10 11
+  create-facility "Memory Hall" 10 11
+  create-facility "Memory Square" 10 11.0001
+
+  local result=$(request get "facilities/by-source-id/$TONARI_SOURCE_ID?limit=2")
+  field-equals "$result" .featureCount "2"
+  local cursor=$(extract-field "$result" .nextCursor)
+
+  local result=$(request get "facilities/by-source-id/$TONARI_SOURCE_ID?limit=2&cursor=$cursor")
+  field-equals "$result" .featureCount "1"
+  field-equals "$result" .nextCursor "null"
+
+  local result=$(request get "facilities/by-radius/11/10/1000?limit=2")
+  field-equals "$result" .featureCount "2"
+  local cursor=$(extract-field "$result" .nextCursor)
+
+  local result=$(request get "facilities/by-radius/11/10/1000?limit=2&cursor=$cursor")
+  field-equals "$result" .featureCount "1"
+  field-equals "$result" .features[0].properties.name "Memory Square"
+  field-equals "$result" .nextCursor "null"
+}
+
+# This test ensures that updates of facilities are applied by the in-memory store
+@test "In-memory facility updates" {
+  local request=$(cat <<JSON
+{
+    "createNewFacility": false,
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "memory"
+    },
+    "lat": 10,
+    "lon": 11,
+    "name": "Memory",
+    "accessibility": { "accessibleWith": { "wheelchair": "oneStep" } }
+}
+JSON
+)
+  expect post facilities/set-facility '{"result":"success"}' "$request"
+
+  local result=$(request get "facilities/by-id/$TONARI_SOURCE_ID/memory")
+  field-equals "$result" .featureCount "1"
+  field-equals "$result" .features[0].properties.name "Memory"
+  field-equals "$result" .features[0].properties.accessibility.accessibleWith.wheelchair "oneStep"
+
+  local result=$(request get "facilities/history/$TONARI_SOURCE_ID/memory")
+  field-equals "$result" .changeCount "1"
+}
+
+admin-request() {
+  local path_=$1
+  local request=$2
+
+  if [ "$request" ]; then
+    curl -sS --max-time 5 --connect-timeout 5 -d "$request" -H 'Content-Type: application/json' -H "X-Admin-Token: $TONARI_ADMIN_TOKEN" "http://$TONARI_IP:8000/$path_"
+  else
+    curl -sS --max-time 5 --connect-timeout 5 -H "X-Admin-Token: $TONARI_ADMIN_TOKEN" "http://$TONARI_IP:8000/$path_"
+  fi
+}
+
+# This test ensures that the visit statistics are aggregated on the in-memory store
+@test "In-memory visit statistics" {
+  create-facility "Memory Station" 10 11
+
+  local original_id=$(extract-field "$(request get facilities/by-radius/11/10/1000)" .features[0].properties.originalId)
+  local request=$(cat <<JSON
+{
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "$original_id"
+    },
+    "search": { "lat": 10, "lon": 11, "radius": 100 }
+}
+JSON
+)
+  field-equals "$(request post facilities/will-visit "$request")" .result "success"
+  field-equals "$(request post facilities/will-visit "$request")" .result "success"
+
+  local result=$(admin-request facilities/visit-statistics)
+  field-equals "$result" .visitCount "2"
+  field-equals "$result" .rankedVisitCount "2"
+  field-equals "$result" '.averageRank == 1' "true"
+
+  local result=$(admin-request facilities/most-visited '{ "bbox": [10, 9, 12, 11] }')
+  field-equals "$result" .featureCount "1"
+  field-equals "$result" .features[0].properties.visitCount "2"
+}
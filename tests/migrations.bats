@@ -0,0 +1,103 @@
+#!/usr/bin/env bats
+
+load framework
+
+# Evaluates JavaScript in the database of the backend, with whichever MongoDB shell the container has.
+mongo-eval() {
+  docker exec "$MONGO" sh -c 'if command -v mongosh >/dev/null; then mongosh --quiet sanitary_facilities --eval "$0"; else mongo --quiet sanitary_facilities --eval "$0"; fi' "$1"
+}
+
+# This test ensures that
+#   1. `lastUpdated` timestamps stored as strings are converted into dates at startup
+#   2. converted facilities are found by updated-since
+#   3. timestamps that can't be parsed are left unchanged
+@test "Migrate string timestamps" {
+  mongo-eval 'db.facilities.insertOne({
+    type: "Feature",
+    geometry: { type: "Point", coordinates: [11, 10] },
+    properties: { sourceId: "'"$TONARI_SOURCE_ID"'", originalId: "migration", name: "Old" },
+    lastUpdated: "2019-01-02 03:04:05.678901 UTC"
+  })'
+  mongo-eval 'db.facilities.insertOne({
+    type: "Feature",
+    geometry: { type: "Point", coordinates: [11, 10] },
+    properties: { sourceId: "'"$TONARI_SOURCE_ID"'", originalId: "unparseable", name: "Unparseable" },
+    lastUpdated: "last tuesday"
+  })'
+
+  local result=$(request get "facilities/updated-since/2019-01-01")
+  field-equals "$result" .featureCount "0"
+
+  container-stop "$TONARI"
+  export TONARI_MIGRATE_TIMESTAMPS=1
+  tonari-run
+  await-http
+
+  local result=$(request get "facilities/updated-since/2019-01-01")
+  field-equals "$result" .featureCount "1"
+  field-equals "$result" .features[0].properties.name "Old"
+  field-equals "$result" .features[0].lastUpdated "2019-01-02T03:04:05.678Z"
+
+  # timestamps that can't be parsed are left unchanged
+  local result=$(request get "facilities/by-id/$TONARI_SOURCE_ID/unparseable")
+  field-equals "$result" .features[0].lastUpdated "last tuesday"
+}
+
+# This test ensures that
+#   1. `fee` values stored under the misspelled path are moved to the path of the attribute
+#   2. values that are already stored under the correct path are kept
+@test "Migrate misspelled fee path" {
+  mongo-eval 'db.facilities.insertMany([{
+    type: "Feature",
+    geometry: { type: "Point", coordinates: [11, 10] },
+    properties: { sourceId: "'"$TONARI_SOURCE_ID"'", originalId: "old-fee", accesibility: { fee: true } }
+  }, {
+    type: "Feature",
+    geometry: { type: "Point", coordinates: [11, 10] },
+    properties: { sourceId: "'"$TONARI_SOURCE_ID"'", originalId: "both-fees", accesibility: { fee: true }, accessibility: { fee: false } }
+  }])'
+
+  container-stop "$TONARI"
+  export TONARI_MIGRATE_FEE_PATH=1
+  tonari-run
+  await-http
+
+  local result=$(request get "facilities/by-id/$TONARI_SOURCE_ID/old-fee")
+  field-equals "$result" .features[0].properties.accessibility.fee "true"
+  field-equals "$result" .features[0].properties.accesibility "null"
+
+  local result=$(request get "facilities/by-id/$TONARI_SOURCE_ID/both-fees")
+  field-equals "$result" .features[0].properties.accessibility.fee "false"
+  field-equals "$result" .features[0].properties.accesibility "null"
+
+  local result=$(request get "facilities/by-radius/11/10/1000?fee=true")
+  field-equals "$result" .featureCount "1"
+}
+
+# This test ensures that
+#   1. attributes listed in `verifiedAttributes` are converted into votes for their current values at startup
+#   2. the list is removed
+@test "Migrate verified attributes" {
+  mongo-eval 'db.facilities.insertOne({
+    type: "Feature",
+    geometry: { type: "Point", coordinates: [11, 10] },
+    properties: {
+      sourceId: "'"$TONARI_SOURCE_ID"'",
+      originalId: "verified",
+      accessibility: { accessibleWith: { wheelchair: "oneStep" } },
+      verifiedAttributes: ["wheelchairAccess"]
+    },
+    lastUpdated: new Date()
+  })'
+
+  container-stop "$TONARI"
+  export TONARI_MIGRATE_VERIFIED_ATTRIBUTES=1
+  tonari-run
+  await-http
+
+  local result=$(request get "facilities/by-id/$TONARI_SOURCE_ID/verified")
+  field-equals "$result" '.features[0].properties.verifications.wheelchairAccess | length' "1"
+  field-equals "$result" .features[0].properties.verifications.wheelchairAccess[0].value "oneStep"
+  field-equals "$result" .features[0].properties.verifiedAttributes "null"
+  field-equals "$result" '.features[0].properties.confidence.wheelchairAccess > 0' "true"
+}
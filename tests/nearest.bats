@@ -0,0 +1,28 @@
+#!/usr/bin/env bats
+
+load framework
+
+# This test ensures that
+#   1. the nearest search returns the requested number of facilities sorted by distance
+#   2. the maximum distance excludes facilities that are too far away
+@test "Nearest search" {
+  create-facility "Foobar nearest" 10 11
+  create-facility "Foobar second" 10 11.000244140625
+  create-facility "Foobar far away" 10 11.0009765625
+
+  local result=$(request get "facilities/nearest/11/10?count=2")
+  is-json "$result"
+  field-equals "$result" .result "success"
+  field-equals "$result" .featureCount "2"
+  field-equals "$result" .features[0].properties.name "Foobar nearest"
+  field-equals "$result" .features[0].properties.distance "0"
+  field-equals "$result" .features[1].properties.name "Foobar second"
+  field-equals "$result" .features[1].properties.distance "26.767"
+
+  local result=$(request get "facilities/nearest/11/10?count=5&maxDistance=100")
+  field-equals "$result" .result "success"
+  field-equals "$result" .featureCount "2"
+
+  local result=$(request get "facilities/nearest/11/10?count=0")
+  field-equals "$result" .result "failure"
+}
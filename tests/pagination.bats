@@ -0,0 +1,89 @@
+#!/usr/bin/env bats
+
+load framework
+
+# This test ensures that
+#   1. the number of returned facilities is limited by `limit`
+#   2. following `nextCursor` returns every facility exactly once
+#   3. the last page has no `nextCursor`
+@test "Pagination" {
+  create-facility "Foobar 1" 10 11
+  create-facility "Foobar 2" 10 11.0001
+  create-facility "Foobar 3" 10 11.0002
+
+  local result=$(request get "facilities/by-source-id/$TONARI_SOURCE_ID?limit=2")
+
+  is-json "$result"
+  field-equals "$result" .result "success"
+  field-equals "$result" .featureCount "2"
+  field-exists "$result" .nextCursor
+
+  local firstIds=$(extract-field "$result" '.features[].properties._id')
+  local cursor=$(extract-field "$result" .nextCursor)
+
+  local result=$(request get "facilities/by-source-id/$TONARI_SOURCE_ID?limit=2&cursor=$cursor")
+
+  is-json "$result"
+  field-equals "$result" .result "success"
+  field-equals "$result" .featureCount "1"
+  field-equals "$result" .nextCursor "null"
+
+  local lastId=$(extract-field "$result" '.features[0].properties._id')
+  ! echo "$firstIds" | grep -qx "$lastId"
+
+  # radius searches are paginated in the order of the distance
+  local result=$(request get "facilities/by-radius/11/10/1000?limit=2")
+  field-equals "$result" .featureCount "2"
+  field-equals "$result" .features[0].properties.name "Foobar 1"
+  field-equals "$result" .features[1].properties.name "Foobar 2"
+
+  local cursor=$(extract-field "$result" .nextCursor)
+  local result=$(request get "facilities/by-radius/11/10/1000?limit=2&cursor=$cursor")
+  field-equals "$result" .featureCount "1"
+  field-equals "$result" .features[0].properties.name "Foobar 3"
+  field-equals "$result" .nextCursor "null"
+
+  # invalid cursors are rejected
+  local result=$(request get "facilities/by-source-id/$TONARI_SOURCE_ID?cursor=invalid")
+  field-equals "$result" .result "failure"
+}
+
+# This test ensures that facilities added closer than the last facility of a page of a radius search
+# neither shift nor repeat the facilities on the following pages.
+@test "Stable radius pagination" {
+  create-facility "Foobar 1" 10 11
+  create-facility "Foobar 2" 10 11.0001
+  create-facility "Foobar 3" 10 11.0002
+  create-facility "Foobar 4" 10 11.0003
+
+  local result=$(request get "facilities/by-radius/11/10/1000?limit=2")
+  field-equals "$result" .features[1].properties.name "Foobar 2"
+  local cursor=$(extract-field "$result" .nextCursor)
+
+  create-facility "Foobar 0" 10 11
+
+  local result=$(request get "facilities/by-radius/11/10/1000?limit=2&cursor=$cursor")
+  field-equals "$result" .featureCount "2"
+  field-equals "$result" .features[0].properties.name "Foobar 3"
+  field-equals "$result" .features[1].properties.name "Foobar 4"
+  field-equals "$result" .nextCursor "null"
+}
+
+# This test ensures that pages of a radius search neither repeat nor skip facilities at the same distance
+@test "Radius pagination with equal distances" {
+  for i in 1 2 3 4 5; do
+    create-facility "Foobar $i" 10 11
+  done
+
+  local ids=""
+  local cursor=""
+  for page in 1 2 3; do
+    local result=$(request get "facilities/by-radius/11/10/1000?limit=2${cursor:+&cursor=$cursor}")
+    field-equals "$result" .result "success"
+    ids="$ids $(extract-field "$result" '.features[].properties._id')"
+    cursor=$(extract-field "$result" .nextCursor)
+  done
+
+  [ "$cursor" = "null" ]
+  [ "$(echo $ids | tr ' ' '\n' | sort -u | wc -l)" -eq 5 ]
+}
@@ -0,0 +1,51 @@
+#!/usr/bin/env bats
+
+load framework
+
+will-visit() {
+  local request=$(cat <<JSON
+{
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "questions"
+    },
+    "search": { "lat": 10, "lon": 11, "radius": 100 },
+    "maxQuestions": 2
+}
+JSON
+)
+  request post facilities/will-visit "$request"
+}
+
+# This test ensures that
+#   1. the number of questions can be limited
+#   2. the most useful questions come first
+#   3. questions that were already asked become less useful
+@test "Question prioritization" {
+  local request=$(cat <<JSON
+{
+    "createNewFacility": false,
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "questions"
+    },
+    "lat": 10,
+    "lon": 11,
+    "name": "Foobar"
+}
+JSON
+)
+  expect post facilities/set-facility '{"result":"success"}' "$request"
+
+  local result=$(will-visit)
+  field-equals "$result" .result "success"
+  field-equals "$result" '.questions | length' "2"
+  field-equals "$result" .questions[0].type "askAttribute"
+  field-equals "$result" .questions[0].attribute "wheelchairAccess"
+  field-equals "$result" '.questions[0].score == 100' "true"
+  field-equals "$result" .questions[1].attribute "doorWidth"
+
+  local result=$(will-visit)
+  field-equals "$result" .questions[0].attribute "key"
+  field-equals "$result" '.questions[0].score == 80' "true"
+}
@@ -0,0 +1,125 @@
+#!/usr/bin/env bats
+
+load framework
+
+setup() {
+  export TONARI_ADMIN_TOKEN=secret
+  containers-run
+  await-http
+}
+
+set-wheelchair-access() {
+  local value=$1
+
+  local request=$(cat <<JSON
+{
+    "createNewFacility": false,
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "revert"
+    },
+    "lat": 10,
+    "lon": 11,
+    "accessibility": { "accessibleWith": { "wheelchair": "$value" } }
+}
+JSON
+)
+  expect post facilities/set-facility '{"result":"success"}' "$request"
+}
+
+wheelchair-access() {
+  extract-field "$(request get "facilities/by-id/$TONARI_SOURCE_ID/revert")" .features[0].properties.accessibility.accessibleWith.wheelchair
+}
+
+admin-request() {
+  local path_=$1
+  local request=$2
+
+  curl -sS --max-time 5 --connect-timeout 5 -d "$request" -H 'Content-Type: application/json' -H "X-Admin-Token: $TONARI_ADMIN_TOKEN" "http://$TONARI_IP:8000/$path_"
+}
+
+# This test ensures that
+#   1. a single change can be reverted and the revert is recorded
+#   2. a facility can be restored to an earlier state
+#   3. only administrators can revert changes
+@test "Revert changes" {
+  set-wheelchair-access noSteps
+  sleep 0.1
+  local timestamp=$(now-timestamp)
+  sleep 0.1
+  set-wheelchair-access oneStep
+  set-wheelchair-access multipleSteps
+
+  local history=$(request get "facilities/history/$TONARI_SOURCE_ID/revert")
+  local change_id=$(extract-field "$history" .changes[2]._id)
+
+  local status=$(curl -s -o /dev/null -w '%{http_code}' -d "{\"changeId\": \"$change_id\"}" -H 'Content-Type: application/json' "http://$TONARI_IP:8000/facilities/revert-change")
+  [ "$status" = "401" ]
+
+  local result=$(admin-request facilities/revert-change "{\"changeId\": \"$change_id\"}")
+  field-equals "$result" .result "success"
+  [ "$(wheelchair-access)" = "oneStep" ]
+
+  local history=$(request get "facilities/history/$TONARI_SOURCE_ID/revert")
+  field-equals "$history" .changes[3].operation "revertChange"
+
+  local result=$(admin-request facilities/restore-facility "{\"id\": {\"sourceId\": \"$TONARI_SOURCE_ID\", \"originalId\": \"revert\"}, \"timestamp\": \"$timestamp\"}")
+  field-equals "$result" .result "success"
+  [ "$(wheelchair-access)" = "noSteps" ]
+}
+
+# This test ensures that a change of a value can't be reverted once the value was changed again
+@test "Revert overwritten value" {
+  set-wheelchair-access noSteps
+  set-wheelchair-access oneStep
+  set-wheelchair-access multipleSteps
+
+  local history=$(request get "facilities/history/$TONARI_SOURCE_ID/revert")
+  local result=$(admin-request facilities/revert-change "{\"changeId\": \"$(extract-field "$history" .changes[1]._id)\"}")
+  field-equals "$result" .error.code "invalidRequest"
+  [ "$(wheelchair-access)" = "multipleSteps" ]
+}
+
+add-comment() {
+  local content=$1
+
+  local request=$(cat <<JSON
+{
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "revert-comments"
+    },
+    "content": "$content",
+    "lat": 10,
+    "lon": 11
+}
+JSON
+)
+  expect post facilities/add-comment '{"result":"success"}' "$request"
+}
+
+# This test ensures that
+#   1. a change that created a facility can't be reverted, even if it was made by an upsert
+#   2. a change of an array can't be reverted once the array was changed again
+#   3. the latest change of an array can be reverted
+@test "Revert restrictions" {
+  add-comment first
+  add-comment second
+  add-comment third
+
+  local history=$(request get "facilities/history/$TONARI_SOURCE_ID/revert-comments")
+  field-equals "$history" .changes[0].operation "createFacility"
+
+  local result=$(admin-request facilities/revert-change "{\"changeId\": \"$(extract-field "$history" .changes[0]._id)\"}")
+  field-equals "$result" .error.code "invalidRequest"
+
+  local result=$(admin-request facilities/revert-change "{\"changeId\": \"$(extract-field "$history" .changes[1]._id)\"}")
+  field-equals "$result" .error.code "invalidRequest"
+
+  local result=$(admin-request facilities/revert-change "{\"changeId\": \"$(extract-field "$history" .changes[2]._id)\"}")
+  field-equals "$result" .result "success"
+
+  local facility=$(request get "facilities/by-id/$TONARI_SOURCE_ID/revert-comments")
+  field-equals "$facility" '.features[0].properties.comments | length' "2"
+  field-equals "$facility" .features[0].type "Feature"
+}
@@ -0,0 +1,29 @@
+#!/usr/bin/env bats
+
+load framework
+
+# This test ensures that
+#   1. text search only returns facilities whose name matches the query
+#   2. the results are biased towards the given location
+@test "Text search" {
+  create-facility "Central Station" 10 11
+  create-facility "Central Station East" 40 41
+  create-facility "Town Hall" 10 11
+
+  local result=$(request get "facilities/search?q=station")
+  is-json "$result"
+  field-equals "$result" .result "success"
+  field-equals "$result" .featureCount "2"
+  field-exists "$result" .features[0].properties.relevance
+
+  local result=$(request get "facilities/search?q=station&lat=40&lon=41")
+  field-equals "$result" .featureCount "2"
+  field-equals "$result" .features[0].properties.name "Central Station East"
+  field-equals "$result" .features[0].properties.distance "0"
+
+  local result=$(request get "facilities/search?q=station&lat=10&lon=11")
+  field-equals "$result" .features[0].properties.name "Central Station"
+
+  local result=$(request get "facilities/search?q=station&lat=10")
+  field-equals "$result" .result "failure"
+}
@@ -0,0 +1,47 @@
+#!/usr/bin/env bats
+
+load framework
+
+set-facility() {
+  local accessibility=$1
+  local strict=${2:-false}
+
+  local request=$(cat <<JSON
+{
+    "createNewFacility": true,
+    "lat": 10,
+    "lon": 11,
+    "name": "Foobar",
+    "accessibility": $accessibility,
+    "strict": $strict
+}
+JSON
+)
+  request post facilities/set-facility "$request"
+}
+
+# This test ensures that
+#   1. valid accessibility information is accepted
+#   2. invalid values are rejected with the invalid fields
+#   3. unknown fields are dropped and reported, or rejected in strict mode
+@test "Set facility validation" {
+  local result=$(set-facility '{ "accessibleWith": { "wheelchair": "noSteps" }, "fee": false }')
+  field-equals "$result" .result "success"
+
+  local result=$(set-facility '{ "accessibleWith": { "wheelchair": "banana" } }')
+  field-equals "$result" .result "failure"
+  field-equals "$result" .invalidValues[0].attribute "wheelchairAccess"
+  field-equals "$result" .invalidValues[0].value "banana"
+
+  local result=$(set-facility '{ "fee": true, "colour": "blue" }')
+  field-equals "$result" .result "success"
+  field-equals "$result" .unknownAttributes[0] "colour"
+
+  local result=$(set-facility '{ "fee": true, "colour": "blue" }' true)
+  field-equals "$result" .result "failure"
+  field-equals "$result" .unknownAttributes[0] "colour"
+
+  local result=$(request get facilities/by-tile/0/0/0)
+  field-equals "$result" .featureCount "2"
+  field-equals "$result" '[.features[].properties.accessibility.colour] | map(select(. != null)) | length' "0"
+}
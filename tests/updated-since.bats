@@ -0,0 +1,42 @@
+#!/usr/bin/env bats
+
+load framework
+
+# This test ensures that
+#   1. updated-since accepts RFC 3339, ISO 8601 and epoch millisecond timestamps
+#   2. only facilities updated after the timestamp are returned
+#   3. invalid timestamps are rejected
+@test "Updated since" {
+  local before=$(now-timestamp)
+  create-facility "Foobar" 10 11
+
+  local result=$(request get "facilities/updated-since/$before")
+  field-equals "$result" .result "success"
+  field-equals "$result" .featureCount "1"
+  field-equals "$result" .features[0].properties.name "Foobar"
+
+  local result=$(request get "facilities/updated-since/2000-01-01T00:00:00%2B02:00")
+  field-equals "$result" .result "success"
+  field-equals "$result" .featureCount "1"
+
+  local result=$(request get "facilities/updated-since/2000-01-01%2000:00:00.000")
+  field-equals "$result" .result "success"
+  field-equals "$result" .featureCount "1"
+
+  local result=$(request get "facilities/updated-since/2999-01-01T00:00:00Z")
+  field-equals "$result" .result "success"
+  field-equals "$result" .featureCount "0"
+
+  # the returned timestamp can be used for the next request
+  local lastUpdated=$(extract-field "$(request get "facilities/updated-since/$before")" .features[0].lastUpdated)
+  local result=$(request get "facilities/updated-since/$lastUpdated")
+  field-equals "$result" .featureCount "1"
+
+  local result=$(request get "facilities/updated-since/yesterday")
+  field-equals "$result" .result "failure"
+  field-exists "$result" .error.message
+
+  # plain numbers are ambiguous, so milliseconds need the `ms` suffix
+  local result=$(request get "facilities/updated-since/$(now)")
+  field-equals "$result" .result "failure"
+}
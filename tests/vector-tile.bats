@@ -0,0 +1,31 @@
+#!/usr/bin/env bats
+
+load framework
+
+# This test ensures that
+#   1. map tiles can be retrieved as Mapbox Vector Tiles
+#   2. the tile contains the facilities in it
+@test "Vector tile" {
+  create-facility "Foobar" 10 11
+
+  local tmpdir=$(mktemp -d)
+
+  local contentType=$(curl -sS --max-time 5 -o "$tmpdir/tile.mvt" -w '%{content_type}' "http://$TONARI_IP:8000/facilities/by-tile/16/15/5.mvt")
+  diff <(echo "$contentType") <(echo "application/vnd.mapbox-vector-tile")
+
+  # the tile contains the layer name and the name of the facility
+  grep -q "facilities" "$tmpdir/tile.mvt"
+  grep -q "Foobar" "$tmpdir/tile.mvt"
+
+  # an empty tile has no features
+  curl -sS --max-time 5 -o "$tmpdir/empty.mvt" "http://$TONARI_IP:8000/facilities/by-tile/0/0/5.mvt"
+  ! grep -q "Foobar" "$tmpdir/empty.mvt"
+
+  # tiles that don't exist are rejected
+  local status=$(curl -sS --max-time 5 -o /dev/null -w '%{http_code}' "http://$TONARI_IP:8000/facilities/by-tile/0/0/32.mvt")
+  [ "$status" = "400" ]
+  local status=$(curl -sS --max-time 5 -o /dev/null -w '%{http_code}' "http://$TONARI_IP:8000/facilities/by-tile/32/0/5.mvt")
+  [ "$status" = "400" ]
+
+  rm -r "$tmpdir"
+}
@@ -0,0 +1,93 @@
+#!/usr/bin/env bats
+
+load framework
+
+set-wheelchair-access() {
+  local value=$1
+
+  local request=$(cat <<JSON
+{
+    "createNewFacility": false,
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "verification"
+    },
+    "lat": 10,
+    "lon": 11,
+    "accessibility": { "accessibleWith": { "wheelchair": "$value" } }
+}
+JSON
+)
+  expect post facilities/set-facility '{"result":"success"}' "$request"
+}
+
+verify-wheelchair-access() {
+  local request=$(cat <<JSON
+{
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "verification"
+    },
+    "attributes": ["wheelchairAccess"],
+    "lat": 10,
+    "lon": 11
+}
+JSON
+)
+  expect post facilities/verify-attributes '{"result":"success","unknownAttributes":[]}' "$request"
+}
+
+wheelchair-questions() {
+  local request=$(cat <<JSON
+{
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "verification"
+    },
+    "search": { "lat": 10, "lon": 11, "radius": 100 }
+}
+JSON
+)
+  extract-field "$(request post facilities/will-visit "$request")" '[.questions[] | select(.attribute == "wheelchairAccess")] | length'
+}
+
+confidence() {
+  extract-field "$(request get "facilities/by-id/$TONARI_SOURCE_ID/verification")" '.features[0].properties.confidence.wheelchairAccess * 100 | floor'
+}
+
+# This test ensures that
+#   1. setting and verifying a value raise the confidence in it
+#   2. questions are only asked while the confidence is low
+#   3. contradicting answers lower the confidence
+@test "Consensus verification" {
+  set-wheelchair-access noSteps
+  [ "$(confidence)" = "50" ]
+  [ "$(wheelchair-questions)" = "1" ]
+
+  verify-wheelchair-access
+  [ "$(confidence)" = "66" ]
+  [ "$(wheelchair-questions)" = "0" ]
+
+  set-wheelchair-access oneStep
+  [ "$(confidence)" = "0" ]
+  [ "$(wheelchair-questions)" = "1" ]
+
+  local result=$(request get "facilities/by-id/$TONARI_SOURCE_ID/verification")
+  field-equals "$result" '.features[0].properties.verifications.wheelchairAccess | length' "3"
+}
+
+# This test ensures that only the most recent votes for an attribute are kept
+@test "Vote limit" {
+  container-stop "$TONARI"
+  export TONARI_MAX_VOTES_PER_ATTRIBUTE=2
+  tonari-run
+  await-http
+
+  set-wheelchair-access noSteps
+  verify-wheelchair-access
+  set-wheelchair-access oneStep
+
+  local result=$(request get "facilities/by-id/$TONARI_SOURCE_ID/verification")
+  field-equals "$result" '.features[0].properties.verifications.wheelchairAccess | length' "2"
+  field-equals "$result" .features[0].properties.verifications.wheelchairAccess[1].value "oneStep"
+}
@@ -0,0 +1,89 @@
+#!/usr/bin/env bats
+
+load framework
+
+setup() {
+  export TONARI_ADMIN_TOKEN=secret
+  containers-run
+  await-http
+}
+
+admin-request() {
+  local path_=$1
+  local request=$2
+
+  if [ "$request" ]; then
+    curl -sS --max-time 5 --connect-timeout 5 -d "$request" -H 'Content-Type: application/json' -H "X-Admin-Token: $TONARI_ADMIN_TOKEN" "http://$TONARI_IP:8000/$path_"
+  else
+    curl -sS --max-time 5 --connect-timeout 5 -H "X-Admin-Token: $TONARI_ADMIN_TOKEN" "http://$TONARI_IP:8000/$path_"
+  fi
+}
+
+will-visit() {
+  local original_id=$1
+
+  local request=$(cat <<JSON
+{
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "$original_id"
+    },
+    "search": { "lat": 10, "lon": 11, "radius": 100 }
+}
+JSON
+)
+  field-equals "$(request post facilities/will-visit "$request")" .result "success"
+}
+
+# This test ensures that
+#   1. will-visit requests are recorded with the rank of the facility
+#   2. visits are counted per facility
+#   3. the most visited facilities within an area can be retrieved
+#   4. only administrators can retrieve visit statistics
+@test "Visit statistics" {
+  local request=$(cat <<JSON
+{
+    "createNewFacility": false,
+    "id": {
+        "sourceId": "$TONARI_SOURCE_ID",
+        "originalId": "visits"
+    },
+    "lat": 10,
+    "lon": 11,
+    "name": "Foobar"
+}
+JSON
+)
+  expect post facilities/set-facility '{"result":"success"}' "$request"
+
+  will-visit visits
+  will-visit visits
+  will-visit unknown
+
+  local result=$(admin-request "facilities/visits/$TONARI_SOURCE_ID/visits")
+  field-equals "$result" .result "success"
+  field-equals "$result" .visitCount "2"
+  field-equals "$result" '.averageRank == 1' "true"
+
+  local result=$(admin-request facilities/visit-statistics)
+  field-equals "$result" .visitCount "3"
+  field-equals "$result" .rankedVisitCount "2"
+
+  local result=$(admin-request facilities/most-visited '{ "bbox": [10, 9, 12, 11] }')
+  field-equals "$result" .result "success"
+  field-equals "$result" .featureCount "1"
+  field-equals "$result" .features[0].properties.originalId "visits"
+  field-equals "$result" .features[0].properties.visitCount "2"
+
+  local result=$(admin-request facilities/most-visited '{ "bbox": [20, 19, 22, 21] }')
+  field-equals "$result" .featureCount "0"
+
+  local result=$(admin-request "facilities/most-visited?limit=0" '{ "bbox": [10, 9, 12, 11] }')
+  field-equals "$result" .error.code "invalidRequest"
+
+  local result=$(admin-request "facilities/visit-statistics?since=$(now-timestamp)")
+  field-equals "$result" .visitCount "0"
+
+  local status=$(curl -sS -o /dev/null -w '%{http_code}' "http://$TONARI_IP:8000/facilities/visit-statistics")
+  [ "$status" = "401" ]
+}
@@ -0,0 +1,39 @@
+#!/usr/bin/env bats
+
+load framework
+
+# This test ensures that
+#   1. polygon and bounding box searches properly include and exclude facilities
+#   2. holes of polygons are excluded
+#   3. areas with invalid coordinates are rejected
+@test "Area search" {
+  create-facility "Foobar inside" 10 11
+  create-facility "Foobar in hole" 10.5 11.5
+  create-facility "Foobar outside" 20 21
+
+  local polygon='{ "type": "Polygon", "coordinates": [[[10, 9], [12, 9], [12, 11], [10, 11], [10, 9]]] }'
+  local result=$(request post facilities/within "$polygon")
+  is-json "$result"
+  field-equals "$result" .result "success"
+  field-equals "$result" .featureCount "2"
+
+  local with_hole='{ "type": "Polygon", "coordinates": [[[10, 9], [12, 9], [12, 11], [10, 11], [10, 9]], [[11.4, 10.4], [11.6, 10.4], [11.6, 10.6], [11.4, 10.6], [11.4, 10.4]]] }'
+  local result=$(request post facilities/within "$with_hole")
+  field-equals "$result" .featureCount "1"
+  field-equals "$result" .features[0].properties.name "Foobar inside"
+
+  local multi_polygon='{ "type": "MultiPolygon", "coordinates": [[[[10, 9], [12, 9], [12, 11], [10, 11], [10, 9]]], [[[20, 19], [22, 19], [22, 21], [20, 21], [20, 19]]]] }'
+  local result=$(request post facilities/within "$multi_polygon")
+  field-equals "$result" .featureCount "3"
+
+  local result=$(request post facilities/within '{ "bbox": [20, 19, 22, 21] }')
+  field-equals "$result" .featureCount "1"
+  field-equals "$result" .features[0].properties.name "Foobar outside"
+
+  local result=$(request post facilities/within '{ "bbox": [200, 19, 22, 21] }')
+  field-equals "$result" .result "failure"
+
+  local open_ring='{ "type": "Polygon", "coordinates": [[[10, 9], [12, 9], [12, 11], [10, 11]]] }'
+  local result=$(request post facilities/within "$open_ring")
+  field-equals "$result" .result "failure"
+}